@@ -0,0 +1,439 @@
+//! BM8563 (PCF8563 compatible) real-time clock
+//!
+//! ```ignore
+//! let mut rtc = bm8563::BM8563::new(i2c);
+//! if !rtc.is_clock_valid()? {
+//!     rtc.set_datetime(&DateTime { year: 2024, month: 1, day: 1, weekday: 1, hours: 0, minutes: 0, seconds: 0 })?;
+//! }
+//! let now = rtc.datetime()?;
+//! ```
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+const ADDRESS: u8 = 0x51;
+
+const CONTROL_STATUS_2: u8 = 0x01;
+const VL_SECONDS: u8 = 0x02;
+const MINUTE_ALARM: u8 = 0x09;
+const TIMER_CONTROL: u8 = 0x0E;
+
+/// Timer interrupt is active while the flag is set (`TI_TP`)
+const TI_TP: u8 = 1 << 4;
+/// Alarm flag
+const AF: u8 = 1 << 3;
+/// Timer flag
+const TF: u8 = 1 << 2;
+/// Alarm interrupt enable
+const AIE: u8 = 1 << 1;
+/// Timer interrupt enable
+const TIE: u8 = 1 << 0;
+
+/// Low voltage flag, set when clock integrity is no longer guaranteed
+const VL: u8 = 1 << 7;
+/// Century flag, set for the 1900s
+const CENTURY: u8 = 1 << 7;
+/// Alarm field disabled
+const AE: u8 = 1 << 7;
+/// Timer enable
+const TE: u8 = 1 << 7;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The bus transfer failed
+    I2c(E),
+    /// A field is out of range or the registers hold no valid BCD
+    InvalidValue,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    /// 1900 to 2099
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    /// 0 (Sunday) to 6
+    pub weekday: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+/// Alarm fields; the alarm fires when all fields that are `Some` match
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Alarm {
+    pub minute: Option<u8>,
+    pub hour: Option<u8>,
+    pub day: Option<u8>,
+    pub weekday: Option<u8>,
+}
+
+/// Source clock of the countdown timer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerFrequency {
+    Hz4096 = 0b00,
+    Hz64 = 0b01,
+    Hz1 = 0b10,
+    /// One tick per minute
+    Hz1_60 = 0b11,
+}
+
+fn bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+fn from_bcd<E>(value: u8) -> Result<u8, Error<E>> {
+    let (tens, units) = (value >> 4, value & 0x0F);
+    if tens > 9 || units > 9 {
+        return Err(Error::InvalidValue);
+    }
+    Ok(tens * 10 + units)
+}
+
+pub struct BM8563<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> BM8563<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            address: ADDRESS,
+        }
+    }
+
+    /// Reads date and time in one burst.
+    pub fn datetime(&mut self) -> Result<DateTime, Error<E>> {
+        let mut data = [0; 7];
+        self.i2c
+            .write_read(self.address, &[VL_SECONDS], &mut data)?;
+        let century = if data[5] & CENTURY != 0 { 1900 } else { 2000 };
+        Ok(DateTime {
+            seconds: from_bcd(data[0] & 0x7F)?,
+            minutes: from_bcd(data[1] & 0x7F)?,
+            hours: from_bcd(data[2] & 0x3F)?,
+            day: from_bcd(data[3] & 0x3F)?,
+            weekday: data[4] & 0x07,
+            month: from_bcd(data[5] & 0x1F)?,
+            year: century + from_bcd(data[6])? as u16,
+        })
+    }
+
+    /// Sets date and time and clears the low voltage flag.
+    pub fn set_datetime(&mut self, datetime: &DateTime) -> Result<(), Error<E>> {
+        let valid = (1900..=2099).contains(&datetime.year)
+            && (1..=12).contains(&datetime.month)
+            && (1..=31).contains(&datetime.day)
+            && datetime.weekday <= 6
+            && datetime.hours <= 23
+            && datetime.minutes <= 59
+            && datetime.seconds <= 59;
+        if !valid {
+            return Err(Error::InvalidValue);
+        }
+        let century = if datetime.year < 2000 { CENTURY } else { 0 };
+        self.i2c.write(
+            self.address,
+            &[
+                VL_SECONDS,
+                bcd(datetime.seconds),
+                bcd(datetime.minutes),
+                bcd(datetime.hours),
+                bcd(datetime.day),
+                datetime.weekday,
+                century | bcd(datetime.month),
+                bcd((datetime.year % 100) as u8),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// `false` if the supply dropped too low since the time was last set,
+    /// i.e. the time can not be trusted (the VL bit).
+    pub fn is_clock_valid(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.read(VL_SECONDS)? & VL == 0)
+    }
+
+    /// Programs and enables the alarm. The alarm flag is cleared.
+    pub fn set_alarm(&mut self, alarm: &Alarm) -> Result<(), Error<E>> {
+        let field = |value: Option<u8>, max: u8| match value {
+            Some(v) if v <= max => Ok(bcd(v)),
+            Some(_) => Err(Error::InvalidValue),
+            None => Ok(AE),
+        };
+        let data = [
+            MINUTE_ALARM,
+            field(alarm.minute, 59)?,
+            field(alarm.hour, 23)?,
+            field(alarm.day, 31)?,
+            field(alarm.weekday, 6)?,
+        ];
+        self.i2c.write(self.address, &data)?;
+        self.clear_alarm_flag()
+    }
+
+    pub fn disable_alarm(&mut self) -> Result<(), Error<E>> {
+        self.i2c
+            .write(self.address, &[MINUTE_ALARM, AE, AE, AE, AE])?;
+        self.update(CONTROL_STATUS_2, AIE | AF, 0)
+    }
+
+    /// Whether the alarm has fired since the flag was last cleared
+    pub fn alarm_flag(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.read(CONTROL_STATUS_2)? & AF != 0)
+    }
+
+    pub fn clear_alarm_flag(&mut self) -> Result<(), Error<E>> {
+        self.update(CONTROL_STATUS_2, AF, 0)
+    }
+
+    /// Drives INT low while the alarm flag is set.
+    pub fn enable_alarm_interrupt(&mut self, enable: bool) -> Result<(), Error<E>> {
+        self.update(CONTROL_STATUS_2, AIE, if enable { AIE } else { 0 })
+    }
+
+    /// Starts the countdown timer with `count` ticks of `frequency`.
+    pub fn set_timer(&mut self, frequency: TimerFrequency, count: u8) -> Result<(), Error<E>> {
+        self.i2c
+            .write(self.address, &[TIMER_CONTROL, TE | frequency as u8, count])?;
+        self.clear_timer_flag()
+    }
+
+    pub fn disable_timer(&mut self) -> Result<(), Error<E>> {
+        self.i2c
+            .write(self.address, &[TIMER_CONTROL, TimerFrequency::Hz1_60 as u8])?;
+        self.update(CONTROL_STATUS_2, TIE | TF, 0)
+    }
+
+    pub fn timer_flag(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.read(CONTROL_STATUS_2)? & TF != 0)
+    }
+
+    pub fn clear_timer_flag(&mut self) -> Result<(), Error<E>> {
+        self.update(CONTROL_STATUS_2, TF, 0)
+    }
+
+    /// Drives INT low on timer expiry, either while the flag is set or, with
+    /// `pulse`, as a short pulse.
+    pub fn enable_timer_interrupt(&mut self, enable: bool, pulse: bool) -> Result<(), Error<E>> {
+        let mut bits = 0;
+        if enable {
+            bits |= TIE;
+            if pulse {
+                bits |= TI_TP;
+            }
+        }
+        self.update(CONTROL_STATUS_2, TIE | TI_TP, bits)
+    }
+
+    /// Releases the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, Error<E>> {
+        let mut data = [0];
+        self.i2c.write_read(self.address, &[register], &mut data)?;
+        Ok(data[0])
+    }
+
+    /// Replaces the bits in `mask` of `register` with `bits`. The alarm and
+    /// timer flags are only cleared by writing 0, so they are written as 1
+    /// unless they are part of `mask`.
+    fn update(&mut self, register: u8, mask: u8, bits: u8) -> Result<(), Error<E>> {
+        let mut value = self.read(register)?;
+        if register == CONTROL_STATUS_2 {
+            value |= AF | TF;
+        }
+        value = (value & !mask) | (bits & mask);
+        self.i2c.write(self.address, &[register, value])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Register file of the RTC. Like the chip, the alarm and timer flags
+    /// are only cleared by writing 0.
+    pub(crate) struct Registers(pub [u8; 16]);
+
+    impl WriteRead for Registers {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            let start = bytes[0] as usize;
+            buffer.copy_from_slice(&self.0[start..start + buffer.len()]);
+            Ok(())
+        }
+    }
+
+    impl Write for Registers {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            for (i, &value) in bytes[1..].iter().enumerate() {
+                let register = bytes[0] as usize + i;
+                self.0[register] = if register == CONTROL_STATUS_2 as usize {
+                    let flags = AF | TF;
+                    (value & !flags) | (value & self.0[register] & flags)
+                } else {
+                    value
+                };
+            }
+            Ok(())
+        }
+    }
+
+    const LEAP_DAY: DateTime = DateTime {
+        year: 2024,
+        month: 2,
+        day: 29,
+        weekday: 4,
+        hours: 23,
+        minutes: 59,
+        seconds: 58,
+    };
+
+    #[test]
+    fn bcd_round_trip() {
+        for value in 0..=99 {
+            assert_eq!(from_bcd::<()>(bcd(value)).unwrap(), value);
+        }
+        assert_eq!(bcd(59), 0x59);
+        assert!(from_bcd::<()>(0x1A).is_err());
+        assert!(from_bcd::<()>(0xA1).is_err());
+    }
+
+    #[test]
+    fn writes_bcd_registers() {
+        let mut rtc = BM8563::new(Registers([0; 16]));
+        rtc.set_datetime(&LEAP_DAY).unwrap();
+        assert_eq!(
+            rtc.release().0[VL_SECONDS as usize..][..7],
+            [0x58, 0x59, 0x23, 0x29, 0x04, 0x02, 0x24]
+        );
+    }
+
+    #[test]
+    fn century_flag() {
+        let mut rtc = BM8563::new(Registers([0; 16]));
+        let datetime = DateTime {
+            year: 1999,
+            month: 12,
+            day: 31,
+            weekday: 5,
+            ..LEAP_DAY
+        };
+        rtc.set_datetime(&datetime).unwrap();
+        assert_eq!(rtc.datetime().unwrap(), datetime);
+        let registers = rtc.release();
+        assert_eq!(registers.0[7], CENTURY | 0x12);
+        assert_eq!(registers.0[8], 0x99);
+
+        let mut rtc = BM8563::new(registers);
+        rtc.set_datetime(&LEAP_DAY).unwrap();
+        assert_eq!(rtc.datetime().unwrap(), LEAP_DAY);
+    }
+
+    #[test]
+    fn masks_unused_bits() {
+        let mut registers = [0; 16];
+        registers[2..9].copy_from_slice(&[
+            VL | 0x30,
+            0x80 | 0x15,
+            0xC0 | 0x08,
+            0xC0 | 0x01,
+            0xF8 | 0x02,
+            0x60 | 0x07,
+            0x25,
+        ]);
+        let mut rtc = BM8563::new(Registers(registers));
+        assert!(!rtc.is_clock_valid().unwrap());
+        assert_eq!(
+            rtc.datetime().unwrap(),
+            DateTime {
+                year: 2025,
+                month: 7,
+                day: 1,
+                weekday: 2,
+                hours: 8,
+                minutes: 15,
+                seconds: 30,
+            }
+        );
+        // Setting the time clears the low voltage flag
+        rtc.set_datetime(&LEAP_DAY).unwrap();
+        assert!(rtc.is_clock_valid().unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut registers = [0; 16];
+        registers[2..9].copy_from_slice(&[0x5A, 0, 0, 0x01, 0, 0x01, 0]);
+        let mut rtc = BM8563::new(Registers(registers));
+        assert!(matches!(rtc.datetime(), Err(Error::InvalidValue)));
+        for datetime in [
+            DateTime {
+                year: 2100,
+                ..LEAP_DAY
+            },
+            DateTime {
+                month: 13,
+                ..LEAP_DAY
+            },
+            DateTime { day: 0, ..LEAP_DAY },
+            DateTime {
+                hours: 24,
+                ..LEAP_DAY
+            },
+        ] {
+            assert!(matches!(
+                rtc.set_datetime(&datetime),
+                Err(Error::InvalidValue)
+            ));
+        }
+    }
+
+    #[test]
+    fn alarm_registers_and_flags() {
+        let mut registers = [0; 16];
+        registers[CONTROL_STATUS_2 as usize] = AF | TF;
+        let mut rtc = BM8563::new(Registers(registers));
+        rtc.set_alarm(&Alarm {
+            minute: Some(30),
+            hour: Some(7),
+            ..Alarm::default()
+        })
+        .unwrap();
+        rtc.enable_alarm_interrupt(true).unwrap();
+        assert!(!rtc.alarm_flag().unwrap());
+        // Clearing the alarm leaves a pending timer flag alone
+        assert!(rtc.timer_flag().unwrap());
+        assert!(rtc
+            .set_alarm(&Alarm {
+                hour: Some(24),
+                ..Alarm::default()
+            })
+            .is_err());
+        let registers = rtc.release().0;
+        assert_eq!(
+            registers[MINUTE_ALARM as usize..][..4],
+            [0x30, 0x07, AE, AE]
+        );
+        assert_eq!(registers[CONTROL_STATUS_2 as usize], TF | AIE);
+    }
+}
@@ -0,0 +1,85 @@
+//! Shared I2C bus
+//!
+//! The touch controller, RTC and RFID reader all sit on the internal bus
+//! (GPIO11/GPIO12). [`I2cBus`] owns the peripheral and hands out
+//! [`I2cDevice`] handles implementing the `embedded-hal` I2C traits, so each
+//! driver can own its handle. The handles borrow the bus for the duration of
+//! a transfer only and are meant for the main thread; transfers do not mask
+//! interrupts, so encoder edges are not missed during a long read.
+//!
+//! ```ignore
+//! let bus = bus::I2cBus::new(i2c);
+//! let touch = ft3267::FT3267::new(bus.device());
+//! let rtc = bm8563::BM8563::new(bus.device());
+//! ```
+//!
+//! The Grove Port A bus (GPIO13/GPIO15) is shared the same way; [`scan`]
+//! lists the units plugged into it.
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use heapless::Vec;
+
+/// Lowest and highest addresses that are not reserved
+const SCAN_ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+/// Returns the addresses that acknowledge a one byte read.
+pub fn scan<I2C: Read>(i2c: &mut I2C) -> Vec<u8, 112> {
+    SCAN_ADDRESSES
+        .filter(|&address| i2c.read(address, &mut [0]).is_ok())
+        .collect()
+}
+
+pub struct I2cBus<I2C> {
+    bus: RefCell<I2C>,
+}
+
+impl<I2C> I2cBus<I2C> {
+    pub const fn new(i2c: I2C) -> Self {
+        Self {
+            bus: RefCell::new(i2c),
+        }
+    }
+
+    pub fn device(&self) -> I2cDevice<'_, I2C> {
+        I2cDevice { bus: &self.bus }
+    }
+}
+
+/// Handle to a device on an [`I2cBus`]
+pub struct I2cDevice<'a, I2C> {
+    bus: &'a RefCell<I2C>,
+}
+
+impl<I2C, E> Write for I2cDevice<'_, I2C>
+where
+    I2C: Write<Error = E>,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        self.bus.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<I2C, E> Read for I2cDevice<'_, I2C>
+where
+    I2C: Read<Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        self.bus.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<I2C, E> WriteRead for I2cDevice<'_, I2C>
+where
+    I2C: WriteRead<Error = E>,
+{
+    type Error = E;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), E> {
+        self.bus.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
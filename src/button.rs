@@ -0,0 +1,310 @@
+//! Debounced push button of the Dial knob (GPIO42)
+//!
+//! [`Button`] is a pure state machine: feed it raw samples together with a
+//! timestamp and it reports [`Event`]s.
+//!
+//! ```ignore
+//! let mut button = button::Button::new(button::Config::default());
+//! for event in button.update(pin.is_low().unwrap(), time::now_ms()) {
+//!     println!("{event:?}");
+//! }
+//! ```
+use heapless::Vec;
+
+/// Button timings in milliseconds
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// A level has to be stable this long before it is accepted
+    pub debounce_ms: u64,
+    /// Maximum gap between two clicks of a double click, 0 disables double
+    /// clicks and reports clicks right on release
+    pub double_click_ms: u64,
+    /// Press duration that triggers [`Event::LongPress`]
+    pub long_press_ms: u64,
+    /// Interval of [`Event::Hold`] after a long press
+    pub repeat_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 20,
+            double_click_ms: 250,
+            long_press_ms: 600,
+            repeat_ms: 100,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The button went down
+    Press,
+    /// The button was released; follows every [`Event::Press`]
+    Release,
+    /// A short press that was not followed by a second one
+    Click,
+    /// Two short presses in quick succession
+    DoubleClick,
+    /// The button has been held for [`Config::long_press_ms`]
+    LongPress,
+    /// Auto-repeat while the button stays down after a long press
+    Hold,
+}
+
+pub struct Button {
+    config: Config,
+    raw: bool,
+    raw_since: u64,
+    pressed: bool,
+    pressed_at: u64,
+    long_pressed: bool,
+    consumed: bool,
+    next_repeat: u64,
+    pending_click: Option<u64>,
+}
+
+impl Button {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            raw: false,
+            raw_since: 0,
+            pressed: false,
+            pressed_at: 0,
+            long_pressed: false,
+            consumed: false,
+            next_repeat: 0,
+            pending_click: None,
+        }
+    }
+
+    /// Debounced button state
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Feeds a raw sample (`true` while pressed) taken at `now_ms`.
+    pub fn update(&mut self, raw: bool, now_ms: u64) -> Vec<Event, 2> {
+        let mut events = Vec::new();
+
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now_ms;
+        }
+        let stable = now_ms.saturating_sub(self.raw_since) >= self.config.debounce_ms;
+
+        if stable && self.raw != self.pressed {
+            self.pressed = self.raw;
+            if self.pressed {
+                self.pressed_at = now_ms;
+                self.long_pressed = false;
+                self.consumed = false;
+                if let Some(released_at) = self.pending_click {
+                    if now_ms.saturating_sub(released_at) > self.config.double_click_ms {
+                        self.pending_click = None;
+                        events.push(Event::Click).ok();
+                    }
+                }
+                events.push(Event::Press).ok();
+            } else {
+                events.push(Event::Release).ok();
+                if !self.long_pressed && !self.consumed {
+                    if let Some(event) = self.click(now_ms) {
+                        events.push(event).ok();
+                    }
+                }
+            }
+        } else if self.pressed && !self.consumed {
+            if !self.long_pressed {
+                if now_ms.saturating_sub(self.pressed_at) >= self.config.long_press_ms {
+                    self.long_pressed = true;
+                    // A long press is never the second half of a double click
+                    if self.pending_click.take().is_some() {
+                        events.push(Event::Click).ok();
+                    }
+                    self.next_repeat = now_ms + self.config.repeat_ms;
+                    events.push(Event::LongPress).ok();
+                }
+            } else if self.config.repeat_ms > 0 && now_ms >= self.next_repeat {
+                self.next_repeat += self.config.repeat_ms;
+                events.push(Event::Hold).ok();
+            }
+        } else if let Some(released_at) = self.pending_click {
+            if now_ms.saturating_sub(released_at) > self.config.double_click_ms {
+                self.pending_click = None;
+                events.push(Event::Click).ok();
+            }
+        }
+
+        events
+    }
+
+    /// Marks the current press as used by another gesture: releasing it
+    /// reports no click and holding it no long press or repeat.
+    pub fn consume(&mut self) {
+        if self.pressed {
+            self.consumed = true;
+        }
+    }
+
+    fn click(&mut self, now_ms: u64) -> Option<Event> {
+        if self.config.double_click_ms == 0 {
+            return Some(Event::Click);
+        }
+        match self.pending_click.take() {
+            Some(_) => Some(Event::DoubleClick),
+            None => {
+                self.pending_click = Some(now_ms);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples `levels` every millisecond until `end_ms`. Each entry sets the
+    /// raw level from its timestamp on.
+    fn run(
+        button: &mut Button,
+        levels: &[(u64, bool)],
+        end_ms: u64,
+    ) -> std::vec::Vec<(u64, Event)> {
+        let mut events = std::vec::Vec::new();
+        for now in 0..end_ms {
+            let raw = levels
+                .iter()
+                .take_while(|(at, _)| *at <= now)
+                .last()
+                .is_some_and(|(_, level)| *level);
+            events.extend(
+                button
+                    .update(raw, now)
+                    .into_iter()
+                    .map(|event| (now, event)),
+            );
+        }
+        events
+    }
+
+    fn button() -> Button {
+        Button::new(Config::default())
+    }
+
+    #[test]
+    fn bounces_are_filtered() {
+        let levels = [
+            (10, true),
+            (12, false),
+            (13, true),
+            (15, false),
+            (16, true),
+            (200, false),
+        ];
+        let events = run(&mut button(), &levels, 600);
+        assert_eq!(
+            events,
+            [
+                (36, Event::Press),
+                (220, Event::Release),
+                (471, Event::Click)
+            ]
+        );
+    }
+
+    #[test]
+    fn click_waits_for_double_click_window() {
+        let events = run(&mut button(), &[(0, true), (100, false)], 400);
+        assert_eq!(
+            events,
+            [
+                (20, Event::Press),
+                (120, Event::Release),
+                (371, Event::Click)
+            ]
+        );
+    }
+
+    #[test]
+    fn double_click() {
+        let levels = [(0, true), (80, false), (200, true), (280, false)];
+        let events = run(&mut button(), &levels, 1000);
+        assert_eq!(
+            events,
+            [
+                (20, Event::Press),
+                (100, Event::Release),
+                (220, Event::Press),
+                (300, Event::Release),
+                (300, Event::DoubleClick),
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_clicks_stay_single() {
+        let levels = [(0, true), (80, false), (500, true), (580, false)];
+        let events: std::vec::Vec<_> = run(&mut button(), &levels, 1200)
+            .into_iter()
+            .filter(|(_, event)| !matches!(event, Event::Press | Event::Release))
+            .collect();
+        assert_eq!(events, [(351, Event::Click), (851, Event::Click)]);
+    }
+
+    #[test]
+    fn long_press_repeats() {
+        let events = run(&mut button(), &[(0, true), (1000, false)], 1500);
+        assert_eq!(
+            events,
+            [
+                (20, Event::Press),
+                (620, Event::LongPress),
+                (720, Event::Hold),
+                (820, Event::Hold),
+                (920, Event::Hold),
+                (1020, Event::Release),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press_ends_pending_click() {
+        let levels = [(0, true), (80, false), (200, true), (1000, false)];
+        let events = run(&mut button(), &levels, 1100);
+        assert!(events.contains(&(820, Event::Click)));
+        assert!(events.contains(&(820, Event::LongPress)));
+        assert!(!events.iter().any(|(_, event)| *event == Event::DoubleClick));
+    }
+
+    #[test]
+    fn consumed_press_reports_nothing() {
+        let mut button = button();
+        run(&mut button, &[(0, true)], 50);
+        button.consume();
+        let mut events = std::vec::Vec::new();
+        for now in 50..1000 {
+            events.extend(button.update(now < 800, now));
+        }
+        assert_eq!(events, [Event::Release]);
+    }
+
+    #[test]
+    fn click_without_double_click() {
+        let mut button = Button::new(Config {
+            double_click_ms: 0,
+            ..Config::default()
+        });
+        let events = run(&mut button, &[(0, true), (100, false)], 200);
+        assert_eq!(
+            events,
+            [
+                (20, Event::Press),
+                (120, Event::Release),
+                (120, Event::Click)
+            ]
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! LEDC output of the buzzer
+use esp32s3_hal::{
+    gpio::OutputPin,
+    ledc::{
+        channel::{self, Channel, ChannelIFace},
+        timer::{self, Timer, TimerIFace},
+        LowSpeed, LEDC,
+    },
+    prelude::*,
+};
+
+use super::{Melody, Note, Output, Player};
+
+const TIMER: timer::Number = timer::Number::Timer0;
+/// 10 bit duty resolution covers roughly 80 Hz to 78 kHz from the APB clock
+const DUTY: timer::config::Duty = timer::config::Duty::Duty10Bit;
+const MIN_FREQUENCY: u32 = 80;
+const MAX_FREQUENCY: u32 = 20_000;
+
+fn timer_config(frequency: u32) -> timer::config::Config {
+    timer::config::Config {
+        duty: DUTY,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: frequency.Hz(),
+    }
+}
+
+/// Configures the buzzer timer; it has to outlive the [`Buzzer`].
+pub fn configure_timer<'d>(ledc: &'d LEDC<'d>) -> Timer<'d, LowSpeed> {
+    let mut timer = ledc.get_timer::<LowSpeed>(TIMER);
+    timer.configure(timer_config(1000)).unwrap();
+    timer
+}
+
+pub struct Buzzer<'d, O: OutputPin> {
+    ledc: &'d LEDC<'d>,
+    channel: Channel<'d, LowSpeed, O>,
+    player: Player,
+}
+
+impl<'d, O: OutputPin> Buzzer<'d, O> {
+    pub fn new(ledc: &'d LEDC<'d>, timer: &'d Timer<'d, LowSpeed>, pin: O) -> Self {
+        let mut channel = ledc.get_channel(channel::Number::Channel0, pin);
+        channel
+            .configure(channel::config::Config {
+                timer,
+                duty_pct: 0,
+                pin_config: channel::config::PinConfig::PushPull,
+            })
+            .unwrap();
+        Self {
+            ledc,
+            channel,
+            player: Player::new(),
+        }
+    }
+
+    /// Plays `frequency` Hz for `duration_ms`, replacing anything playing.
+    pub fn tone(&mut self, frequency: u32, duration_ms: u32, now_ms: u64) {
+        self.play(Melody::Tone(Note::new(frequency, duration_ms)), now_ms);
+    }
+
+    /// Starts `melody`, replacing anything playing.
+    pub fn play(&mut self, melody: Melody, now_ms: u64) {
+        self.player.play(melody, now_ms);
+        self.update(now_ms);
+    }
+
+    pub fn stop(&mut self) {
+        let output = self.player.stop();
+        self.output(output);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.player.is_playing()
+    }
+
+    /// Advances the current tone or melody.
+    pub fn update(&mut self, now_ms: u64) {
+        while let Some(output) = self.player.update(now_ms) {
+            self.output(output);
+        }
+    }
+
+    fn output(&mut self, output: Output) {
+        match output {
+            Output::Tone(frequency) => {
+                // The channel keeps pointing at the hardware timer, so
+                // reconfiguring it through a second handle changes the pitch.
+                let frequency = frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+                let mut timer = self.ledc.get_timer::<LowSpeed>(TIMER);
+                if timer.configure(timer_config(frequency)).is_ok() {
+                    self.channel.set_duty(50).ok();
+                }
+            }
+            Output::Off => {
+                self.channel.set_duty(0).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use super::{rtttl::Rtttl, Note};
+
+/// Something to play
+#[derive(Clone, Debug)]
+pub enum Melody {
+    Tone(Note),
+    Notes(&'static [Note]),
+    Rtttl(Rtttl<'static>),
+}
+
+/// What the output has to do next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Start a tone at the given frequency in Hz
+    Tone(u32),
+    /// Silence the output
+    Off,
+}
+
+/// Non-blocking melody sequencer
+///
+/// Call [`Player::update`] regularly with the current time; it tells the
+/// caller when to change the output. Consecutive notes are separated by a
+/// short silence so repeated notes stay distinguishable.
+pub struct Player {
+    melody: Option<Melody>,
+    index: usize,
+    gap_ms: u32,
+    /// When the output has to change next and what it changes to
+    next: Option<(u64, Output)>,
+    /// Time the current note ends and the next one may start
+    note_end_ms: u64,
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player {
+    pub const DEFAULT_GAP_MS: u32 = 10;
+
+    pub fn new() -> Self {
+        Self {
+            melody: None,
+            index: 0,
+            gap_ms: Self::DEFAULT_GAP_MS,
+            next: None,
+            note_end_ms: 0,
+        }
+    }
+
+    pub fn with_gap(mut self, gap_ms: u32) -> Self {
+        self.gap_ms = gap_ms;
+        self
+    }
+
+    /// Replaces whatever is playing; the first note starts on the next
+    /// [`Player::update`].
+    pub fn play(&mut self, melody: Melody, now_ms: u64) {
+        self.melody = Some(melody);
+        self.index = 0;
+        self.note_end_ms = now_ms;
+        self.next = None;
+    }
+
+    pub fn stop(&mut self) -> Output {
+        self.melody = None;
+        self.next = None;
+        Output::Off
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.melody.is_some() || self.next.is_some()
+    }
+
+    /// Returns the output change due at `now_ms`, if any.
+    pub fn update(&mut self, now_ms: u64) -> Option<Output> {
+        if let Some((at, output)) = self.next {
+            if now_ms < at {
+                return None;
+            }
+            self.next = None;
+            // Unless the next note is already due and replaces it anyway
+            if self.melody.is_none() || now_ms < self.note_end_ms {
+                return Some(output);
+            }
+        }
+        if self.melody.is_none() || now_ms < self.note_end_ms {
+            return None;
+        }
+
+        let Some(note) = self.next_note() else {
+            self.melody = None;
+            return Some(Output::Off);
+        };
+        // Notes follow each other without drift, however late the update
+        self.note_end_ms += note.duration_ms as u64;
+        let gap = self.gap_ms.min(note.duration_ms / 2) as u64;
+        if note.frequency == 0 {
+            Some(Output::Off)
+        } else {
+            self.next = Some((self.note_end_ms - gap, Output::Off));
+            Some(Output::Tone(note.frequency))
+        }
+    }
+
+    fn next_note(&mut self) -> Option<Note> {
+        let note = match self.melody.as_mut()? {
+            Melody::Tone(note) => (self.index == 0).then_some(*note),
+            Melody::Notes(notes) => notes.get(self.index).copied(),
+            // A malformed note ends the melody
+            Melody::Rtttl(rtttl) => rtttl.next().and_then(Result::ok),
+        };
+        self.index += 1;
+        note
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SCALE: [Note; 4] = [
+        Note::new(440, 100),
+        Note::rest(100),
+        Note::new(880, 100),
+        Note::new(660, 100),
+    ];
+
+    /// Runs `player` with updates every `tick_ms` and logs the output changes.
+    fn run(player: &mut Player, tick_ms: u64, until_ms: u64) -> std::vec::Vec<(u64, Output)> {
+        (0..=until_ms / tick_ms)
+            .filter_map(|i| {
+                player
+                    .update(i * tick_ms)
+                    .map(|output| (i * tick_ms, output))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_notes_with_gaps() {
+        let mut player = Player::new();
+        player.play(Melody::Notes(&SCALE), 0);
+        assert!(player.is_playing());
+        assert_eq!(
+            run(&mut player, 1, 1000),
+            [
+                (0, Output::Tone(440)),
+                (90, Output::Off),
+                (100, Output::Off),
+                (200, Output::Tone(880)),
+                (290, Output::Off),
+                (300, Output::Tone(660)),
+                (390, Output::Off),
+                (400, Output::Off),
+            ]
+        );
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn slow_updates_keep_the_tempo() {
+        let mut player = Player::new().with_gap(0);
+        static NOTES: [Note; 10] = [Note::new(440, 50); 10];
+        player.play(Melody::Notes(&NOTES), 0);
+        let starts: std::vec::Vec<u64> = run(&mut player, 32, 2000)
+            .into_iter()
+            .filter(|(_, output)| *output == Output::Tone(440))
+            .map(|(at, _)| at)
+            .collect();
+        assert_eq!(starts.len(), 10);
+        for (i, start) in starts.iter().enumerate() {
+            let due = i as u64 * 50;
+            // Each note starts on the first update after it is due
+            assert!(*start >= due && *start < due + 32, "note {i} at {start}");
+        }
+    }
+
+    #[test]
+    fn tone_and_stop() {
+        let mut player = Player::new();
+        player.play(Melody::Tone(Note::new(2000, 40)), 500);
+        assert_eq!(player.update(500), Some(Output::Tone(2000)));
+        assert_eq!(player.update(520), None);
+        assert_eq!(player.stop(), Output::Off);
+        assert!(!player.is_playing());
+        assert_eq!(player.update(600), None);
+    }
+
+    #[test]
+    fn rtttl_melody() {
+        let mut player = Player::new();
+        let melody = Rtttl::parse("x:d=4,o=5,b=240:c,p,x,e").unwrap();
+        player.play(Melody::Rtttl(melody), 0);
+        // A malformed note ends the melody
+        assert_eq!(
+            run(&mut player, 1, 1000),
+            [
+                (0, Output::Tone(523)),
+                (240, Output::Off),
+                (250, Output::Off),
+                (500, Output::Off),
+            ]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Piezo buzzer on GPIO3
+//!
+//! The buzzer is driven by an LEDC channel at 50% duty; the timer frequency
+//! sets the pitch. Tones and melodies are non-blocking: [`Buzzer::update`]
+//! has to be called from the main loop to advance them.
+//!
+//! ```ignore
+//! let mut ledc = LEDC::new(peripherals.LEDC, &clocks, &mut system.peripheral_clock_control);
+//! ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+//! let timer = buzzer::configure_timer(&ledc);
+//! let mut buzzer = Buzzer::new(&ledc, &timer, io.pins.gpio3.into_push_pull_output());
+//! buzzer.play(Melody::Rtttl(Rtttl::parse(STARTUP)?), time::now_ms());
+//! loop {
+//!     buzzer.update(time::now_ms());
+//! }
+//! ```
+#[cfg(target_arch = "xtensa")]
+mod ledc;
+mod melody;
+pub mod rtttl;
+
+#[cfg(target_arch = "xtensa")]
+pub use ledc::{configure_timer, Buzzer};
+pub use melody::{Melody, Output, Player};
+pub use rtttl::Rtttl;
+
+/// A note of `duration_ms`, `frequency` 0 is a rest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Note {
+    pub frequency: u32,
+    pub duration_ms: u32,
+}
+
+impl Note {
+    pub const fn new(frequency: u32, duration_ms: u32) -> Self {
+        Self {
+            frequency,
+            duration_ms,
+        }
+    }
+
+    pub const fn rest(duration_ms: u32) -> Self {
+        Self::new(0, duration_ms)
+    }
+}
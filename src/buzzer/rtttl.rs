@@ -0,0 +1,227 @@
+use super::Note;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The string does not have the `name:defaults:notes` form
+    MissingSection,
+    /// A `d=`, `o=` or `b=` default could not be parsed
+    InvalidDefault,
+    /// A note could not be parsed
+    InvalidNote,
+}
+
+/// Octave 8 frequencies from C to B; lower octaves are derived by halving
+const OCTAVE_8: [u32; 12] = [
+    4186, 4435, 4699, 4978, 5274, 5588, 5920, 6272, 6645, 7040, 7459, 7902,
+];
+
+/// RTTTL (ring tone text transfer language) melody
+///
+/// `name:d=4,o=5,b=120:8c6,8e6,4g.6,p` — the notes are parsed lazily while
+/// iterating, so no buffer is needed.
+#[derive(Clone, Debug)]
+pub struct Rtttl<'a> {
+    name: &'a str,
+    duration: u32,
+    octave: u32,
+    bpm: u32,
+    notes: core::str::Split<'a, char>,
+}
+
+impl<'a> Rtttl<'a> {
+    pub fn parse(s: &'a str) -> Result<Self, Error> {
+        let mut sections = s.splitn(3, ':');
+        let (Some(name), Some(defaults), Some(notes)) =
+            (sections.next(), sections.next(), sections.next())
+        else {
+            return Err(Error::MissingSection);
+        };
+
+        let mut rtttl = Self {
+            name: name.trim(),
+            duration: 4,
+            octave: 6,
+            bpm: 63,
+            notes: notes.split(','),
+        };
+        for default in defaults.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (key, value) = default.split_once('=').ok_or(Error::InvalidDefault)?;
+            let value: u32 = value.trim().parse().map_err(|_| Error::InvalidDefault)?;
+            match key.trim() {
+                "d" if value > 0 => rtttl.duration = value,
+                "o" if value <= 8 => rtttl.octave = value,
+                "b" if value > 0 => rtttl.bpm = value,
+                _ => return Err(Error::InvalidDefault),
+            }
+        }
+        Ok(rtttl)
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    fn parse_note(&self, s: &str) -> Result<Note, Error> {
+        let s = s.trim();
+        let digits = |s: &str| s.bytes().take_while(u8::is_ascii_digit).count();
+
+        let n = digits(s);
+        let duration = if n > 0 {
+            s[..n].parse().map_err(|_| Error::InvalidNote)?
+        } else {
+            self.duration
+        };
+        let mut rest = &s[n..];
+
+        let mut chars = rest.chars();
+        let semitone = match chars.next().map(|c| c.to_ascii_lowercase()) {
+            Some('c') => Some(0),
+            Some('d') => Some(2),
+            Some('e') => Some(4),
+            Some('f') => Some(5),
+            Some('g') => Some(7),
+            Some('a') => Some(9),
+            Some('b' | 'h') => Some(11),
+            Some('p') => None,
+            _ => return Err(Error::InvalidNote),
+        };
+        rest = chars.as_str();
+
+        let mut sharp = false;
+        if let Some(r) = rest.strip_prefix('#') {
+            sharp = true;
+            rest = r;
+        }
+        // The dot is allowed both before and after the octave
+        let mut dotted = false;
+        if let Some(r) = rest.strip_prefix('.') {
+            dotted = true;
+            rest = r;
+        }
+        let n = digits(rest);
+        let octave = if n > 0 {
+            rest[..n].parse().map_err(|_| Error::InvalidNote)?
+        } else {
+            self.octave
+        };
+        rest = &rest[n..];
+        if let Some(r) = rest.strip_prefix('.') {
+            dotted = true;
+            rest = r;
+        }
+        if !rest.is_empty() || duration == 0 || octave > 8 {
+            return Err(Error::InvalidNote);
+        }
+
+        let frequency = semitone.map_or(0, |semitone| {
+            let index = semitone + sharp as usize;
+            // B# is the C of the next octave
+            let (index, octave) = if index == 12 {
+                (0, octave + 1)
+            } else {
+                (index, octave)
+            };
+            OCTAVE_8[index] >> 8u32.saturating_sub(octave)
+        });
+        // A whole note lasts four beats
+        let beats = self.bpm.checked_mul(duration).ok_or(Error::InvalidNote)?;
+        let mut duration_ms = 240_000 / beats;
+        if dotted {
+            duration_ms += duration_ms / 2;
+        }
+        Ok(Note {
+            frequency,
+            duration_ms,
+        })
+    }
+}
+
+impl Iterator for Rtttl<'_> {
+    type Item = Result<Note, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let s = self.notes.next()?;
+            if !s.trim().is_empty() {
+                return Some(self.parse_note(s));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(s: &str) -> Result<std::vec::Vec<Note>, Error> {
+        Rtttl::parse(s)?.collect()
+    }
+
+    #[test]
+    fn defaults() {
+        let rtttl = Rtttl::parse(" Intro : d=8, o=5, b=120 :c").unwrap();
+        assert_eq!(rtttl.name(), "Intro");
+        assert_eq!(notes("x:d=8,o=5,b=120:c").unwrap(), [Note::new(523, 250)]);
+        // d=4, o=6, b=63 apply when a default is missing
+        assert_eq!(notes("x::c").unwrap(), [Note::new(1046, 952)]);
+    }
+
+    #[test]
+    fn pitches_and_durations() {
+        let parsed = notes("x:d=4,o=5,b=120:a,16a4,a#,b#,h6,2p,c8,c4").unwrap();
+        assert_eq!(
+            parsed,
+            [
+                Note::new(880, 500),
+                Note::new(440, 125),
+                Note::new(932, 500),
+                // B# is the C of the next octave
+                Note::new(1046, 500),
+                Note::new(1975, 500),
+                Note::rest(1000),
+                Note::new(4186, 500),
+                Note::new(261, 500),
+            ]
+        );
+    }
+
+    #[test]
+    fn dotted_notes() {
+        // The dot may come before or after the octave
+        assert_eq!(
+            notes("x:d=4,o=5,b=120:4c.,4c.6,4c6.,8p.").unwrap(),
+            [
+                Note::new(523, 750),
+                Note::new(1046, 750),
+                Note::new(1046, 750),
+                Note::rest(375),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_empty_notes() {
+        assert_eq!(notes("x::c,, ,p,").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn malformed_melodies() {
+        assert_eq!(Rtttl::parse("x:d=4").err(), Some(Error::MissingSection));
+        for defaults in ["d=0", "o=9", "b=0", "b=fast", "q=1", "d"] {
+            let s = std::format!("x:{defaults}:c");
+            assert_eq!(Rtttl::parse(&s).err(), Some(Error::InvalidDefault), "{s}");
+        }
+        for note in ["x", "0c", "c9", "c#x", "99999999999c"] {
+            let s = std::format!("x::{note}");
+            assert_eq!(notes(&s), Err(Error::InvalidNote), "{s}");
+        }
+    }
+
+    #[test]
+    fn huge_tempo_does_not_overflow() {
+        assert_eq!(notes("x:b=4294967295:2c"), Err(Error::InvalidNote));
+        assert_eq!(notes("x:b=70000:65536c"), Err(Error::InvalidNote));
+        // Too fast to last a millisecond, but still a note
+        assert_eq!(notes("x:b=300000:c").unwrap(), [Note::new(1046, 0)]);
+    }
+}
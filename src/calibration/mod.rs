@@ -0,0 +1,174 @@
+//! Touch calibration
+//!
+//! [`run`] draws a few targets, lets the user tap each of them and fits an
+//! [`Affine`] transform from the raw panel positions to the target
+//! positions. With the `settings` feature the result is kept in the settings
+//! store.
+//!
+//! ```ignore
+//! let calibration = match settings.get::<Affine>()? {
+//!     Some(affine) => affine,
+//!     None => calibration::run(&mut touch, &mut display, &mut delay)?.unwrap_or_default(),
+//! };
+//! touch.set_transform(TouchTransform { calibration, ..Default::default() });
+//! ```
+use heapless::Vec;
+
+use crate::{
+    ft3267::{Phase, TouchReport},
+    transform::{Affine, Sample},
+};
+
+#[cfg(target_arch = "xtensa")]
+mod screen;
+
+#[cfg(target_arch = "xtensa")]
+pub use screen::run;
+
+/// Target positions in display coordinates, well inside the round display
+pub const TARGETS: [(i32, i32); 4] = [(120, 45), (195, 120), (120, 195), (45, 120)];
+
+/// Collects one raw position per target
+///
+/// While a finger is down its raw positions are averaged; lifting it
+/// completes the current target.
+pub struct Calibration {
+    samples: Vec<Sample, { TARGETS.len() }>,
+    sum: (f32, f32),
+    count: u32,
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            sum: (0.0, 0.0),
+            count: 0,
+        }
+    }
+
+    /// Target the user has to tap next, `None` once all are done
+    pub fn target(&self) -> Option<(i32, i32)> {
+        TARGETS.get(self.samples.len()).copied()
+    }
+
+    /// Feeds a report in panel coordinates. Returns `true` when a target
+    /// was completed.
+    pub fn update(&mut self, raw: &TouchReport) -> bool {
+        let Some(target) = self.target() else {
+            return false;
+        };
+        match raw.points[0] {
+            Some(p) if p.phase != Phase::Up => {
+                self.sum.0 += p.x as f32;
+                self.sum.1 += p.y as f32;
+                self.count += 1;
+                false
+            }
+            _ if self.count > 0 => {
+                let n = self.count as f32;
+                let sample = (
+                    (self.sum.0 / n, self.sum.1 / n),
+                    (target.0 as f32, target.1 as f32),
+                );
+                self.samples.push(sample).ok();
+                self.sum = (0.0, 0.0);
+                self.count = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The fitted transform once all targets are done
+    pub fn finish(&self) -> Option<Affine> {
+        if self.target().is_some() {
+            return None;
+        }
+        Affine::fit(&self.samples)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ft3267::TouchPoint;
+
+    fn report(x: u16, y: u16, phase: Phase) -> TouchReport {
+        let mut report = TouchReport::default();
+        report.points[0] = Some(TouchPoint {
+            id: 0,
+            x,
+            y,
+            phase,
+            weight: 0,
+            area: 0,
+        });
+        report
+    }
+
+    /// Touches down at each position in turn and lifts the finger; returns
+    /// whether the lift completed a target.
+    fn tap(calibration: &mut Calibration, positions: &[(u16, u16)]) -> bool {
+        for (i, &(x, y)) in positions.iter().enumerate() {
+            let phase = if i == 0 { Phase::Down } else { Phase::Move };
+            assert!(!calibration.update(&report(x, y, phase)));
+        }
+        let (x, y) = positions[positions.len() - 1];
+        calibration.update(&report(x, y, Phase::Up))
+    }
+
+    /// Raw position an uncalibrated panel reports for a display position
+    fn mounted(target: (i32, i32)) -> (u16, u16) {
+        ((240 - target.1) as u16, target.0 as u16)
+    }
+
+    fn assert_close(affine: Affine, expected: Affine) {
+        let (a, b) = (affine.to_bytes(), expected.to_bytes());
+        for (got, want) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+            let got = f32::from_le_bytes(got.try_into().unwrap());
+            let want = f32::from_le_bytes(want.try_into().unwrap());
+            assert!((got - want).abs() < 1e-3, "{affine:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn walks_through_targets() {
+        let mut calibration = Calibration::new();
+        for (i, &target) in TARGETS.iter().enumerate() {
+            assert_eq!(calibration.target(), Some(target));
+            assert_eq!(calibration.finish(), None);
+            let (x, y) = mounted(target);
+            // Jitter around the target averages out
+            let positions = [(x - 2, y), (x + 2, y + 1), (x, y - 1)];
+            assert!(tap(&mut calibration, &positions), "target {i}");
+        }
+        assert_eq!(calibration.target(), None);
+        assert_close(calibration.finish().unwrap(), Affine::MOUNTING);
+
+        // Further touches are ignored
+        assert!(!tap(&mut calibration, &[(10, 10)]));
+    }
+
+    #[test]
+    fn lift_without_touch_is_ignored() {
+        let mut calibration = Calibration::new();
+        assert!(!calibration.update(&TouchReport::default()));
+        assert_eq!(calibration.target(), Some(TARGETS[0]));
+    }
+
+    #[test]
+    fn same_spot_every_time_does_not_fit() {
+        let mut calibration = Calibration::new();
+        for _ in TARGETS {
+            assert!(tap(&mut calibration, &[(100, 100)]));
+        }
+        assert_eq!(calibration.finish(), None);
+    }
+}
@@ -0,0 +1,53 @@
+//! Calibration targets on the display
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Circle, Line, PrimitiveStyle},
+};
+use embedded_hal::blocking::{delay::DelayMs, i2c::WriteRead};
+
+use super::Calibration;
+use crate::{ft3267::Error, touch::Touch, transform::Affine};
+
+/// Walks the user through all targets. Returns `None` if the taps did not
+/// allow a fit, e.g. because the same spot was tapped every time.
+pub fn run<I2C, E, D, DELAY>(
+    touch: &mut Touch<I2C>,
+    display: &mut D,
+    delay: &mut DELAY,
+) -> Result<Option<Affine>, Error<E>>
+where
+    I2C: WriteRead<Error = E>,
+    D: DrawTarget<Color = Rgb565>,
+    DELAY: DelayMs<u32>,
+{
+    let mut calibration = Calibration::new();
+    while let Some(target) = calibration.target() {
+        draw_target(display, Point::new(target.0, target.1));
+        loop {
+            if calibration.update(&touch.touch_raw()?) {
+                break;
+            }
+            delay.delay_ms(16);
+        }
+    }
+    display.clear(Rgb565::BLACK).ok();
+    Ok(calibration.finish())
+}
+
+fn draw_target<D: DrawTarget<Color = Rgb565>>(display: &mut D, center: Point) {
+    let style = PrimitiveStyle::with_stroke(Rgb565::WHITE, 2);
+    display.clear(Rgb565::BLACK).ok();
+    Circle::with_center(center, 20)
+        .into_styled(style)
+        .draw(display)
+        .ok();
+    Line::new(center - Point::new(15, 0), center + Point::new(15, 0))
+        .into_styled(style)
+        .draw(display)
+        .ok();
+    Line::new(center - Point::new(0, 15), center + Point::new(0, 15))
+        .into_styled(style)
+        .draw(display)
+        .ok();
+}
@@ -0,0 +1,293 @@
+//! Wall-clock time
+//!
+//! [`Clock`] reads the RTC once and then extrapolates with the monotonic
+//! system timer, so calendar time is available without touching the bus.
+//! Timestamps are passed in by the caller, usually from
+//! [`time::now_ms`](crate::time::now_ms).
+//!
+//! ```ignore
+//! let mut clock = clock::Clock::new(rtc, time::now_ms())?;
+//! let now = clock.now(time::now_ms());
+//! println!("{:02}:{:02}", now.hours, now.minutes);
+//! ```
+use core::time::Duration;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use crate::bm8563::{Alarm, DateTime, Error, BM8563};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00, `None` for earlier dates
+    pub fn timestamp(&self) -> Option<u64> {
+        // Howard Hinnant's days_from_civil
+        let (y, m) = if self.month <= 2 {
+            (self.year as i64 - 1, self.month as i64 + 9)
+        } else {
+            (self.year as i64, self.month as i64 - 3)
+        };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * m + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
+        Some(
+            days * SECONDS_PER_DAY
+                + self.hours as u64 * 3600
+                + self.minutes as u64 * 60
+                + self.seconds as u64,
+        )
+    }
+
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        let days = (timestamp / SECONDS_PER_DAY) as i64;
+        let secs = timestamp % SECONDS_PER_DAY;
+
+        // Howard Hinnant's civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            // 1970-01-01 was a Thursday
+            weekday: ((days + 4) % 7) as u8,
+            hours: (secs / 3600) as u8,
+            minutes: (secs / 60 % 60) as u8,
+            seconds: (secs % 60) as u8,
+        }
+    }
+}
+
+pub struct Clock<I2C> {
+    rtc: BM8563<I2C>,
+    base: u64,
+    base_ms: u64,
+    valid: bool,
+}
+
+impl<I2C, E> Clock<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    pub fn new(rtc: BM8563<I2C>, now_ms: u64) -> Result<Self, Error<E>> {
+        let mut clock = Self {
+            rtc,
+            base: 0,
+            base_ms: now_ms,
+            valid: false,
+        };
+        clock.sync(now_ms)?;
+        Ok(clock)
+    }
+
+    /// Re-reads the RTC, e.g. to correct drift of the system timer.
+    ///
+    /// An RTC date before 1970 counts as not set and reads as the epoch.
+    pub fn sync(&mut self, now_ms: u64) -> Result<(), Error<E>> {
+        let valid = self.rtc.is_clock_valid()?;
+        let timestamp = self.rtc.datetime()?.timestamp();
+        self.valid = valid && timestamp.is_some();
+        self.base = timestamp.unwrap_or(0);
+        self.base_ms = now_ms;
+        Ok(())
+    }
+
+    /// `false` until the time has been set if the RTC lost power
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    /// Seconds since 1970-01-01 00:00:00
+    pub fn timestamp(&self, now_ms: u64) -> u64 {
+        self.base + now_ms.saturating_sub(self.base_ms) / 1000
+    }
+
+    pub fn now(&self, now_ms: u64) -> DateTime {
+        DateTime::from_timestamp(self.timestamp(now_ms))
+    }
+
+    /// Sets the RTC; dates before 1970 are rejected as
+    /// [`Error::InvalidValue`].
+    pub fn set(&mut self, datetime: &DateTime, now_ms: u64) -> Result<(), Error<E>> {
+        let timestamp = datetime.timestamp().ok_or(Error::InvalidValue)?;
+        self.rtc.set_datetime(datetime)?;
+        self.base = timestamp;
+        self.base_ms = now_ms;
+        self.valid = true;
+        Ok(())
+    }
+
+    /// Sets the RTC alarm to the first full minute at least `after` from
+    /// now and lets it drive INT. Returns the time until the alarm fires.
+    ///
+    /// The alarm matches day, hour and minute, so `after` has to be shorter
+    /// than the shortest month.
+    pub fn set_wake_alarm(&mut self, after: Duration) -> Result<Duration, Error<E>> {
+        let now = self
+            .rtc
+            .datetime()?
+            .timestamp()
+            .ok_or(Error::InvalidValue)?;
+        let at = (now + after.as_secs()).div_ceil(60) * 60;
+        let alarm = DateTime::from_timestamp(at);
+        self.rtc.set_alarm(&Alarm {
+            minute: Some(alarm.minutes),
+            hour: Some(alarm.hours),
+            day: Some(alarm.day),
+            weekday: None,
+        })?;
+        self.rtc.enable_alarm_interrupt(true)?;
+        Ok(Duration::from_secs(at - now))
+    }
+
+    pub fn rtc(&mut self) -> &mut BM8563<I2C> {
+        &mut self.rtc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bm8563::tests::Registers;
+
+    fn datetime(year: u16, month: u8, day: u8, hours: u8, minutes: u8, seconds: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            weekday: 0,
+            hours,
+            minutes,
+            seconds,
+        }
+    }
+
+    fn rtc_at(datetime: &DateTime) -> BM8563<Registers> {
+        let mut rtc = BM8563::new(Registers([0; 16]));
+        rtc.set_datetime(datetime).unwrap();
+        rtc
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(datetime(1970, 1, 1, 0, 0, 0).timestamp(), Some(0));
+        assert_eq!(
+            datetime(2000, 2, 29, 0, 0, 0).timestamp(),
+            Some(951_782_400)
+        );
+        assert_eq!(
+            datetime(2024, 2, 29, 12, 0, 0).timestamp(),
+            Some(1_709_208_000)
+        );
+        assert_eq!(
+            datetime(2099, 12, 31, 23, 59, 59).timestamp(),
+            Some(4_102_444_799)
+        );
+    }
+
+    #[test]
+    fn dates_before_epoch() {
+        assert_eq!(datetime(1969, 12, 31, 23, 59, 59).timestamp(), None);
+        assert_eq!(datetime(1900, 1, 1, 0, 0, 0).timestamp(), None);
+
+        // The century bit lets the RTC hold such a date
+        let mut clock = Clock::new(rtc_at(&datetime(1969, 7, 20, 20, 17, 0)), 0).unwrap();
+        assert!(!clock.is_valid());
+        assert_eq!(clock.timestamp(0), 0);
+
+        let before = clock.rtc().datetime().unwrap();
+        assert!(matches!(
+            clock.set(&datetime(1950, 1, 1, 0, 0, 0), 0),
+            Err(Error::InvalidValue)
+        ));
+        assert_eq!(clock.rtc().datetime().unwrap(), before);
+        assert!(!clock.is_valid());
+    }
+
+    #[test]
+    fn calendar_round_trip() {
+        // Every day from 1970 to 2099, at a varying time of day
+        for day in 0..47_482u64 {
+            let timestamp = day * SECONDS_PER_DAY + day * 7 % SECONDS_PER_DAY;
+            let datetime = DateTime::from_timestamp(timestamp);
+            assert_eq!(datetime.timestamp(), Some(timestamp), "{datetime:?}");
+        }
+        let leap = DateTime::from_timestamp(1_709_208_000);
+        assert_eq!((leap.year, leap.month, leap.day), (2024, 2, 29));
+        // A Thursday
+        assert_eq!(leap.weekday, 4);
+    }
+
+    #[test]
+    fn extrapolates_from_system_timer() {
+        let rtc = rtc_at(&datetime(2023, 12, 31, 23, 59, 50));
+        let clock = Clock::new(rtc, 1_000).unwrap();
+        assert!(clock.is_valid());
+        let now = clock.now(16_500);
+        assert_eq!(
+            (
+                now.year,
+                now.month,
+                now.day,
+                now.hours,
+                now.minutes,
+                now.seconds
+            ),
+            (2024, 1, 1, 0, 0, 5)
+        );
+        // A timestamp from before the sync does not go back in time
+        assert_eq!(clock.timestamp(0), clock.timestamp(1_000));
+    }
+
+    #[test]
+    fn sync_and_set() {
+        // Supply dropped since the time was set
+        let mut registers = Registers([0; 16]);
+        registers.0[2..9].copy_from_slice(&[0x80, 0, 0x12, 0x01, 0, 0x06, 0x24]);
+        let mut clock = Clock::new(BM8563::new(registers), 0).unwrap();
+        assert!(!clock.is_valid());
+
+        let set = datetime(2024, 6, 2, 8, 30, 0);
+        clock.set(&set, 5_000).unwrap();
+        assert!(clock.is_valid());
+        assert_eq!(clock.timestamp(65_000), set.timestamp().unwrap() + 60);
+
+        // The RTC kept counting while the system timer drifted
+        clock
+            .rtc()
+            .set_datetime(&datetime(2024, 6, 2, 8, 32, 0))
+            .unwrap();
+        clock.sync(100_000).unwrap();
+        assert_eq!(clock.now(100_000).minutes, 32);
+        assert!(clock.is_valid());
+    }
+
+    #[test]
+    fn wake_alarm() {
+        let mut clock = Clock::new(rtc_at(&datetime(2024, 6, 2, 8, 29, 30)), 0).unwrap();
+        let until = clock.set_wake_alarm(Duration::from_secs(30 * 60)).unwrap();
+        assert_eq!(until, Duration::from_secs(30 * 60 + 30));
+        let registers = clock.rtc.release().0;
+        // 09:00 on the 2nd, any weekday
+        assert_eq!(registers[9..13], [0x00, 0x09, 0x02, 0x80]);
+        assert_eq!(registers[1] & 0x0A, 0x02);
+    }
+
+    #[test]
+    fn wake_alarm_across_month_end() {
+        let mut clock = Clock::new(rtc_at(&datetime(2024, 1, 31, 23, 50, 0)), 0).unwrap();
+        let until = clock.set_wake_alarm(Duration::from_secs(30 * 60)).unwrap();
+        assert_eq!(until, Duration::from_secs(30 * 60));
+        assert_eq!(clock.rtc.release().0[9..13], [0x20, 0x00, 0x01, 0x80]);
+    }
+}
@@ -0,0 +1,229 @@
+// Float methods are inherent on the host
+#[cfg_attr(not(target_arch = "xtensa"), allow(unused_imports))]
+use num_traits::real::Real;
+
+/// Maps the rotation speed (detents per second) to a delta multiplier
+#[derive(Clone, Copy, Debug)]
+pub enum Curve {
+    /// `1 + gain * speed`, capped at `max`
+    Linear { gain: f32, max: f32 },
+    /// `e^(rate * speed)`, capped at `max`
+    Exponential { rate: f32, max: f32 },
+    /// `(speed, multiplier)` pairs sorted by speed. The multiplier of the
+    /// last entry not faster than the current speed is used, 1 below the first.
+    Table(&'static [(u32, f32)]),
+}
+
+impl Curve {
+    pub fn multiplier(&self, speed: u32) -> f32 {
+        let speed_f = speed as f32;
+        let m = match *self {
+            Curve::Linear { gain, max } => (1.0 + gain * speed_f).min(max),
+            Curve::Exponential { rate, max } => (rate * speed_f).exp().min(max),
+            Curve::Table(table) => table
+                .iter()
+                .take_while(|(threshold, _)| *threshold <= speed)
+                .last()
+                .map_or(1.0, |(_, m)| *m),
+        };
+        m.max(1.0)
+    }
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Curve::Exponential {
+            rate: 0.08,
+            max: 50.0,
+        }
+    }
+}
+
+/// Speeds up the encoder when it is turned quickly
+///
+/// Feed it the detent deltas from [`Encoder::poll`](super::Encoder::poll)
+/// together with a timestamp; slow turns pass through unchanged while fast
+/// spins are scaled by the [`Curve`].
+pub struct Acceleration {
+    curve: Curve,
+    timeout_ms: u64,
+    last_ms: Option<u64>,
+    direction: i32,
+    speed: u32,
+    remainder: f32,
+}
+
+impl Acceleration {
+    /// Gaps longer than this are treated as the start of a new spin
+    pub const DEFAULT_TIMEOUT_MS: u64 = 200;
+
+    pub fn new(curve: Curve) -> Self {
+        Self {
+            curve,
+            timeout_ms: Self::DEFAULT_TIMEOUT_MS,
+            last_ms: None,
+            direction: 0,
+            speed: 0,
+            remainder: 0.0,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Scales `delta` detents observed at `now_ms`
+    pub fn apply(&mut self, delta: i32, now_ms: u64) -> i32 {
+        if delta == 0 {
+            if let Some(last) = self.last_ms {
+                if now_ms.saturating_sub(last) > self.timeout_ms {
+                    self.reset();
+                }
+            }
+            return 0;
+        }
+
+        let direction = delta.signum();
+        match self.last_ms {
+            Some(last)
+                if direction == self.direction
+                    && now_ms.saturating_sub(last) <= self.timeout_ms =>
+            {
+                let elapsed = now_ms.saturating_sub(last).max(1);
+                let instant = (delta.unsigned_abs() as u64 * 1000 / elapsed) as u32;
+                // Average with the previous estimate to smooth out uneven detents
+                self.speed = (self.speed + instant) / 2;
+            }
+            _ => {
+                self.speed = 0;
+                self.remainder = 0.0;
+            }
+        }
+        self.direction = direction;
+        self.last_ms = Some(now_ms);
+
+        let scaled = delta as f32 * self.curve.multiplier(self.speed) + self.remainder;
+        let out = scaled.trunc();
+        self.remainder = scaled - out;
+        out as i32
+    }
+
+    /// Current speed estimate in detents per second
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn reset(&mut self) {
+        self.last_ms = None;
+        self.direction = 0;
+        self.speed = 0;
+        self.remainder = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds one detent every `interval_ms` and returns the summed output
+    fn spin(accel: &mut Acceleration, start_ms: u64, interval_ms: u64, detents: u32) -> i32 {
+        (0..detents as u64)
+            .map(|i| accel.apply(1, start_ms + i * interval_ms))
+            .sum()
+    }
+
+    #[test]
+    fn linear_curve() {
+        let curve = Curve::Linear {
+            gain: 0.5,
+            max: 4.0,
+        };
+        assert_eq!(curve.multiplier(0), 1.0);
+        assert_eq!(curve.multiplier(2), 2.0);
+        assert_eq!(curve.multiplier(100), 4.0);
+    }
+
+    #[test]
+    fn exponential_curve() {
+        let curve = Curve::Exponential {
+            rate: 0.1,
+            max: 50.0,
+        };
+        assert_eq!(curve.multiplier(0), 1.0);
+        assert!((curve.multiplier(10) - core::f32::consts::E).abs() < 1e-4);
+        assert_eq!(curve.multiplier(1000), 50.0);
+    }
+
+    #[test]
+    fn table_curve() {
+        let curve = Curve::Table(&[(10, 2.0), (20, 5.0), (30, 0.5)]);
+        assert_eq!(curve.multiplier(9), 1.0);
+        assert_eq!(curve.multiplier(10), 2.0);
+        assert_eq!(curve.multiplier(25), 5.0);
+        // Multipliers below 1 never slow the knob down
+        assert_eq!(curve.multiplier(30), 1.0);
+    }
+
+    #[test]
+    fn slow_turns_pass_through() {
+        let mut accel = Acceleration::new(Curve::default());
+        assert_eq!(spin(&mut accel, 0, 500, 10), 10);
+        assert_eq!(accel.speed(), 0);
+    }
+
+    #[test]
+    fn fast_spin_accelerates() {
+        let mut accel = Acceleration::new(Curve::Linear {
+            gain: 0.1,
+            max: 4.0,
+        });
+        // 50 detents per second
+        let out = spin(&mut accel, 0, 20, 20);
+        assert!(out > 40, "{out}");
+        assert!(accel.speed() > 40 && accel.speed() <= 50);
+        assert_eq!(accel.apply(1, 400), 4);
+    }
+
+    #[test]
+    fn speed_is_smoothed() {
+        let mut accel = Acceleration::new(Curve::default());
+        accel.apply(1, 0);
+        accel.apply(1, 10);
+        assert_eq!(accel.speed(), 50);
+        accel.apply(1, 20);
+        assert_eq!(accel.speed(), 75);
+    }
+
+    #[test]
+    fn fractions_carry_over() {
+        let mut accel = Acceleration::new(Curve::Table(&[(0, 1.5)]));
+        let out: Vec<i32> = (0..4).map(|i| accel.apply(1, i * 10)).collect();
+        assert_eq!(out, [1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn direction_change_restarts() {
+        let mut accel = Acceleration::new(Curve::Linear {
+            gain: 0.1,
+            max: 4.0,
+        });
+        spin(&mut accel, 0, 20, 10);
+        assert!(accel.speed() > 0);
+        assert_eq!(accel.apply(-1, 200), -1);
+        assert_eq!(accel.speed(), 0);
+    }
+
+    #[test]
+    fn pause_restarts() {
+        let mut accel = Acceleration::new(Curve::Linear {
+            gain: 0.1,
+            max: 4.0,
+        })
+        .with_timeout(100);
+        spin(&mut accel, 0, 20, 10);
+        assert_eq!(accel.apply(0, 300), 0);
+        assert_eq!(accel.speed(), 0);
+        assert_eq!(accel.apply(1, 310), 1);
+    }
+}
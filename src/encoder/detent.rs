@@ -0,0 +1,155 @@
+use super::Config;
+
+/// Turns raw quadrature counts into one step per mechanical detent.
+///
+/// The filter keeps an anchor on the detent the knob last rested at and
+/// steps once the raw count has travelled past the half-detent point towards
+/// a neighbour, in either direction. `hysteresis` pushes that point a few
+/// counts further out, so a knob resting between two clicks does not flicker
+/// back and forth, while a knob resting on a detent is always a full step
+/// away from the previous one.
+pub struct Detent {
+    counts_per_detent: i32,
+    /// Distance from the anchor that completes a step
+    threshold: i32,
+    reversed: bool,
+    anchor: i32,
+    position: i32,
+}
+
+impl Detent {
+    pub fn new(config: &Config) -> Self {
+        let counts_per_detent = config.counts_per_detent.max(1) as i32;
+        let threshold = counts_per_detent / 2 + config.hysteresis as i32;
+        Self {
+            counts_per_detent,
+            threshold: threshold.clamp(1, counts_per_detent),
+            reversed: config.reversed,
+            anchor: 0,
+            position: 0,
+        }
+    }
+
+    /// Feeds the current raw count and returns the number of detents moved
+    /// since the previous call.
+    pub fn update(&mut self, raw: i32) -> i32 {
+        let raw = if self.reversed { -raw } else { raw };
+        let mut steps = 0;
+        loop {
+            let diff = raw - self.anchor;
+            if diff >= self.threshold {
+                self.anchor += self.counts_per_detent;
+                steps += 1;
+            } else if diff <= -self.threshold {
+                self.anchor -= self.counts_per_detent;
+                steps -= 1;
+            } else {
+                break;
+            }
+        }
+        self.position += steps;
+        steps
+    }
+
+    /// Absolute position in detents.
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(counts_per_detent: u8, hysteresis: u8) -> Detent {
+        Detent::new(&Config {
+            counts_per_detent,
+            hysteresis,
+            ..Config::default()
+        })
+    }
+
+    /// Feeds every raw count on the way from `from` to `to` and returns the
+    /// steps reported on each one.
+    fn sweep(detent: &mut Detent, from: i32, to: i32) -> std::vec::Vec<i32> {
+        let counts: std::vec::Vec<i32> = if from <= to {
+            (from..=to).collect()
+        } else {
+            (to..=from).rev().collect()
+        };
+        counts.into_iter().map(|raw| detent.update(raw)).collect()
+    }
+
+    #[test]
+    fn one_step_per_click_forward() {
+        let mut detent = filter(4, 1);
+        assert_eq!(
+            sweep(&mut detent, 0, 12),
+            [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0]
+        );
+        assert_eq!(detent.position(), 3);
+    }
+
+    #[test]
+    fn one_step_per_click_backward() {
+        let mut detent = filter(4, 1);
+        assert_eq!(sweep(&mut detent, 0, -8), [0, 0, 0, -1, 0, 0, 0, -1, 0]);
+        assert_eq!(detent.position(), -2);
+    }
+
+    #[test]
+    fn reversal_steps_on_first_click() {
+        let mut detent = filter(4, 1);
+        assert_eq!(detent.update(4), 1);
+        assert_eq!(detent.update(0), -1);
+        assert_eq!(detent.update(-4), -1);
+        assert_eq!(detent.update(0), 1);
+        assert_eq!(detent.position(), 0);
+
+        // Counting back one edge at a time behaves the same
+        let mut detent = filter(4, 2);
+        assert_eq!(sweep(&mut detent, 0, 4).iter().sum::<i32>(), 1);
+        assert_eq!(sweep(&mut detent, 4, 0), [0, 0, 0, 0, -1]);
+    }
+
+    #[test]
+    fn bounce_between_clicks_does_not_flicker() {
+        let mut detent = filter(4, 1);
+        // Past the step point and back into the hysteresis band
+        let steps: std::vec::Vec<i32> = [1, 2, 3, 2, 3, 2, 3, 2]
+            .into_iter()
+            .map(|raw| detent.update(raw))
+            .collect();
+        assert_eq!(steps, [0, 0, 1, 0, 0, 0, 0, 0]);
+        // Contact bounce around a resting detent
+        for raw in [4, 5, 4, 3, 4, 5, 4] {
+            assert_eq!(detent.update(raw), 0);
+        }
+        assert_eq!(detent.position(), 1);
+    }
+
+    #[test]
+    fn fast_spin_reports_every_click() {
+        let mut detent = filter(4, 1);
+        assert_eq!(detent.update(40), 10);
+        assert_eq!(detent.update(-8), -12);
+        assert_eq!(detent.position(), -2);
+    }
+
+    #[test]
+    fn reversed_and_degenerate_configs() {
+        let mut reversed = Detent::new(&Config {
+            reversed: true,
+            ..Config::default()
+        });
+        assert_eq!(reversed.update(4), -1);
+
+        // Hysteresis is capped so a resting detent always completes a step
+        let mut detent = filter(4, 10);
+        assert_eq!(detent.update(4), 1);
+        assert_eq!(detent.update(0), -1);
+
+        let mut detent = filter(0, 0);
+        assert_eq!(sweep(&mut detent, 0, 3), [0, 1, 1, 1]);
+    }
+}
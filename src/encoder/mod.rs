@@ -0,0 +1,296 @@
+//! Rotary encoder of the Dial knob (GPIO40/GPIO41)
+//!
+//! The PCNT unit counts all four quadrature edges, so a single mechanical
+//! click shows up as several raw counts. [`Encoder`] runs those counts through
+//! a [`Detent`] filter and hands out exactly one step per click.
+//!
+//! Raw counts come from a [`Counter`]: either the [`Pcnt`] peripheral or, with
+//! the `software-encoder` feature, the [`Software`] decoder driven by GPIO
+//! interrupts. Both count in the same direction and units.
+//!
+//! ```ignore
+//! let config = encoder::Config::default();
+//! let counter = encoder::Pcnt::new(pcnt.get_unit(unit::Number::Unit1), &mut a, &mut b, &config);
+//! let mut encoder = encoder::Encoder::new(counter, config);
+//!
+//! let delta = encoder.poll();
+//! ```
+//!
+//! For coarse settings, wrap the deltas in an [`Acceleration`] so fast spins
+//! cover a larger range:
+//!
+//! ```ignore
+//! let mut accel = encoder::Acceleration::new(encoder::Curve::default());
+//! value += accel.apply(encoder.poll(), time::now_ms());
+//! ```
+use core::{
+    future::poll_fn,
+    task::{Context, Poll},
+};
+
+mod accel;
+mod detent;
+#[cfg(all(target_arch = "xtensa", not(feature = "software-encoder")))]
+mod pcnt;
+mod quadrature;
+#[cfg(all(target_arch = "xtensa", feature = "software-encoder"))]
+mod software;
+#[cfg(target_arch = "xtensa")]
+mod wakeup;
+
+pub use accel::{Acceleration, Curve};
+pub use detent::Detent;
+#[cfg(all(target_arch = "xtensa", not(feature = "software-encoder")))]
+pub use pcnt::Pcnt;
+pub use quadrature::Quadrature;
+#[cfg(all(target_arch = "xtensa", feature = "software-encoder"))]
+pub use software::Software;
+#[cfg(target_arch = "xtensa")]
+pub use wakeup::{listen_wakeup, unlisten_wakeup};
+
+/// Source of raw quadrature counts
+pub trait Counter {
+    /// Raw count, four per quadrature cycle
+    fn count(&self) -> i32;
+
+    /// Re-centres the rotation threshold on the current position and clears
+    /// a pending rotation event.
+    fn arm(&mut self);
+
+    /// Ready once the knob moved past the threshold since [`Counter::arm`].
+    fn poll_rotation(&mut self, cx: &mut Context<'_>) -> Poll<()>;
+}
+
+/// Glitch filter applied to the encoder inputs, in APB clock cycles (80 MHz)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Filter(u16);
+
+impl Filter {
+    /// Largest value the PCNT filter register can hold
+    pub const MAX_CYCLES: u16 = 1023;
+
+    const CYCLES_PER_MICRO: u16 = 80;
+
+    /// Returns `None` if `cycles` does not fit into the filter register.
+    pub const fn from_cycles(cycles: u16) -> Option<Self> {
+        if cycles > Self::MAX_CYCLES {
+            None
+        } else {
+            Some(Self(cycles))
+        }
+    }
+
+    /// Pulses shorter than `micros` are ignored. Saturates at [`Filter::MAX_CYCLES`].
+    pub const fn from_micros(micros: u16) -> Self {
+        let cycles = micros.saturating_mul(Self::CYCLES_PER_MICRO);
+        if cycles > Self::MAX_CYCLES {
+            Self(Self::MAX_CYCLES)
+        } else {
+            Self(cycles)
+        }
+    }
+
+    pub const fn cycles(&self) -> u16 {
+        self.0
+    }
+}
+
+/// Encoder configuration
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Raw counts reported by the counter for one mechanical click
+    pub counts_per_detent: u8,
+    /// Swap clockwise and counter-clockwise
+    pub reversed: bool,
+    /// Counts past the half-detent point required for a step, so a knob
+    /// resting between two clicks does not flicker
+    pub hysteresis: u8,
+    /// Input glitch filter, `None` disables it
+    pub filter: Option<Filter>,
+    /// Detents of movement that raise a rotation event, 0 disables it
+    pub wake_detents: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            counts_per_detent: 4,
+            reversed: false,
+            hysteresis: 1,
+            filter: Some(Filter::from_micros(10)),
+            wake_detents: 1,
+        }
+    }
+}
+
+/// Detent-aware rotary encoder
+pub struct Encoder<C> {
+    counter: C,
+    detent: Detent,
+}
+
+impl<C: Counter> Encoder<C> {
+    pub fn new(counter: C, config: Config) -> Self {
+        let mut detent = Detent::new(&config);
+        detent.update(counter.count());
+        Self { counter, detent }
+    }
+
+    /// Returns the number of detents turned since the last call,
+    /// positive for clockwise rotation.
+    pub fn poll(&mut self) -> i32 {
+        self.detent.update(self.counter.count())
+    }
+
+    pub fn counter_mut(&mut self) -> &mut C {
+        &mut self.counter
+    }
+
+    /// Absolute position in detents since the encoder was created
+    pub fn position(&self) -> i32 {
+        self.detent.position()
+    }
+
+    /// Waits until the knob is turned by [`Config::wake_detents`].
+    ///
+    /// Call [`Encoder::poll`] afterwards to collect the steps.
+    pub async fn wait_for_rotation(&mut self) {
+        self.counter.arm();
+        poll_fn(|cx| self.counter.poll_rotation(cx)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Counter whose raw count is set by the test
+    struct MockCounter {
+        count: i32,
+    }
+
+    impl Counter for MockCounter {
+        fn count(&self) -> i32 {
+            self.count
+        }
+
+        fn arm(&mut self) {}
+
+        fn poll_rotation(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+            Poll::Pending
+        }
+    }
+
+    /// Count change of the [`Pcnt`] channel configuration for one edge.
+    /// Channel 1 counts A edges and channel 0 counts B edges the other way
+    /// round, each reversed while its control pin is low.
+    fn pcnt_edge((a, b): (bool, bool), (next_a, next_b): (bool, bool)) -> i32 {
+        match (a != next_a, b != next_b) {
+            (false, false) => 0,
+            (true, false) => {
+                let count = if next_a { 1 } else { -1 };
+                if b {
+                    count
+                } else {
+                    -count
+                }
+            }
+            (false, true) => {
+                let count = if next_b { -1 } else { 1 };
+                if a {
+                    count
+                } else {
+                    -count
+                }
+            }
+            (true, true) => panic!("the PCNT unit sees every edge on its own"),
+        }
+    }
+
+    fn encoder() -> Encoder<MockCounter> {
+        Encoder::new(MockCounter { count: 0 }, Config::default())
+    }
+
+    /// Steps reported after each level with the PCNT counting model
+    fn pcnt(levels: &[(bool, bool)]) -> Vec<i32> {
+        let mut encoder = encoder();
+        let mut previous = (false, false);
+        levels
+            .iter()
+            .map(|&level| {
+                encoder.counter_mut().count += pcnt_edge(previous, level);
+                previous = level;
+                encoder.poll()
+            })
+            .collect()
+    }
+
+    /// Steps reported after each level with the software decoder
+    fn software(levels: &[(bool, bool)]) -> Vec<i32> {
+        let mut encoder = encoder();
+        let mut decoder = Quadrature::new(false, false);
+        levels
+            .iter()
+            .map(|&(a, b)| {
+                decoder.update(a, b);
+                encoder.counter_mut().count = decoder.count();
+                encoder.poll()
+            })
+            .collect()
+    }
+
+    const L00: (bool, bool) = (false, false);
+    const L01: (bool, bool) = (false, true);
+    const L10: (bool, bool) = (true, false);
+    const L11: (bool, bool) = (true, true);
+
+    /// Both backends report `steps` for `levels`, starting on a detent at 00
+    fn assert_backends(levels: &[(bool, bool)], steps: &[i32]) {
+        assert_eq!(pcnt(levels), steps, "pcnt");
+        assert_eq!(software(levels), steps, "software");
+    }
+
+    #[test]
+    fn clockwise_clicks() {
+        let levels = [L01, L11, L10, L00, L01, L11, L10, L00];
+        assert_backends(&levels, &[0, 0, 1, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn counter_clockwise_clicks() {
+        let levels = [L10, L11, L01, L00, L10, L11, L01, L00];
+        assert_backends(&levels, &[0, 0, -1, 0, 0, 0, -1, 0]);
+    }
+
+    #[test]
+    fn direction_reversal() {
+        let levels = [L01, L11, L10, L00, L10, L11, L01, L00, L10, L11, L01, L00];
+        assert_backends(&levels, &[0, 0, 1, 0, 0, 0, -1, 0, 0, 0, -1, 0]);
+    }
+
+    #[test]
+    fn bounces() {
+        // Bounce on the first edge and right past the step point
+        let levels = [L01, L00, L01, L11, L10, L11, L10, L00];
+        assert_backends(&levels, &[0, 0, 0, 0, 1, 0, 0, 0]);
+        // Bounce while resting on a detent
+        assert_backends(&[L01, L00, L10, L00, L01, L00], &[0; 6]);
+    }
+
+    #[test]
+    fn missed_edges() {
+        // The PCNT unit sees every edge, the software decoder misses the
+        // interrupt of the 11 state in both cycles
+        let full = [L01, L11, L10, L00, L01, L11, L10, L00];
+        let sampled = [L01, L10, L00, L01, L10, L00];
+        assert_eq!(pcnt(&full).iter().sum::<i32>(), 2);
+        assert_eq!(software(&sampled), [0, 1, 0, 0, 1, 0]);
+
+        let full = [L10, L11, L01, L00];
+        let sampled = [L10, L01, L00];
+        assert_eq!(pcnt(&full).iter().sum::<i32>(), -1);
+        assert_eq!(software(&sampled), [0, -1, 0]);
+    }
+}
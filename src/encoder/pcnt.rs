@@ -0,0 +1,177 @@
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+    task::{Context, Poll},
+};
+
+use critical_section::Mutex;
+use esp32s3_hal::{
+    gpio::InputPin,
+    interrupt,
+    pcnt::{
+        channel::{self, PcntSource},
+        unit::{self, Unit},
+    },
+    peripheral::Peripheral,
+    peripherals,
+    prelude::*,
+};
+
+use super::{Config, Counter};
+use crate::waker::WakerCell;
+
+const LIMIT: i16 = 100;
+
+static UNIT0: Mutex<RefCell<Option<Unit>>> = Mutex::new(RefCell::new(None));
+static VALUE: AtomicI32 = AtomicI32::new(0);
+static ROTATED: AtomicBool = AtomicBool::new(false);
+static WAKER: WakerCell = WakerCell::new();
+
+/// Quadrature counter backed by a PCNT unit
+///
+/// Both channels count on both edges, so one full quadrature cycle yields
+/// four counts. The hardware counter wraps at ±`LIMIT` and the overflow is
+/// accumulated in the PCNT interrupt, or by whoever reads the count first
+/// while the interrupt is still pending.
+///
+/// The unit's `thresh0`/`thresh1` registers are programmed at ±`threshold`
+/// counts around the position at which [`Counter::arm`] was last called, so
+/// an interrupt fires once the knob has moved that far in either direction.
+pub struct Pcnt {
+    filter: Option<u16>,
+    threshold: i16,
+}
+
+impl Pcnt {
+    pub fn new<A, B>(mut unit: Unit, a: &mut A, b: &mut B, config: &Config) -> Self
+    where
+        A: InputPin + Peripheral<P = A>,
+        B: InputPin + Peripheral<P = B>,
+    {
+        let filter = config.filter.map(|filter| filter.cycles());
+        let threshold =
+            (config.wake_detents as i16 * config.counts_per_detent.max(1) as i16).min(LIMIT - 1);
+
+        configure_unit(&mut unit, filter, threshold);
+        let mut ch0 = unit.get_channel(channel::Number::Channel0);
+        ch0.configure(
+            PcntSource::from_pin(&mut *b),
+            PcntSource::from_pin(&mut *a),
+            channel::Config {
+                lctrl_mode: channel::CtrlMode::Reverse,
+                hctrl_mode: channel::CtrlMode::Keep,
+                pos_edge: channel::EdgeMode::Decrement,
+                neg_edge: channel::EdgeMode::Increment,
+                invert_ctrl: false,
+                invert_sig: false,
+            },
+        );
+        let mut ch1 = unit.get_channel(channel::Number::Channel1);
+        ch1.configure(
+            PcntSource::from_pin(&mut *a),
+            PcntSource::from_pin(&mut *b),
+            channel::Config {
+                lctrl_mode: channel::CtrlMode::Reverse,
+                hctrl_mode: channel::CtrlMode::Keep,
+                pos_edge: channel::EdgeMode::Increment,
+                neg_edge: channel::EdgeMode::Decrement,
+                invert_ctrl: false,
+                invert_sig: false,
+            },
+        );
+        unit.events(unit::Events {
+            low_limit: true,
+            high_limit: true,
+            thresh0: threshold > 0,
+            thresh1: threshold > 0,
+            zero: false,
+        });
+        unit.listen();
+        unit.resume();
+
+        critical_section::with(|cs| UNIT0.borrow_ref_mut(cs).replace(unit));
+
+        interrupt::enable(peripherals::Interrupt::PCNT, interrupt::Priority::Priority2).unwrap();
+
+        Self { filter, threshold }
+    }
+}
+
+impl Counter for Pcnt {
+    /// Raw count including the overflow accumulated by the interrupt
+    fn count(&self) -> i32 {
+        critical_section::with(|cs| {
+            let mut u0 = UNIT0.borrow_ref_mut(cs);
+            let u0 = u0.as_mut().unwrap();
+            // A wrap that the interrupt has not handled yet would otherwise
+            // show up as a jump by `LIMIT`
+            handle_events(u0);
+            u0.get_value() as i32 + VALUE.load(Ordering::SeqCst)
+        })
+    }
+
+    fn arm(&mut self) {
+        critical_section::with(|cs| {
+            let mut u0 = UNIT0.borrow_ref_mut(cs);
+            let u0 = u0.as_mut().unwrap();
+            // Fold the hardware count into VALUE so thresholds become relative
+            // to the current position. The unit is paused so that no edge or
+            // wrap lands between reading and clearing the counter.
+            u0.pause();
+            handle_events(u0);
+            VALUE.fetch_add(u0.get_value() as i32, Ordering::SeqCst);
+            u0.clear();
+            configure_unit(u0, self.filter, self.threshold);
+            ROTATED.store(false, Ordering::SeqCst);
+            u0.resume();
+        });
+    }
+
+    fn poll_rotation(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        WAKER.register(cx.waker());
+        if ROTATED.swap(false, Ordering::SeqCst) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+fn configure_unit(unit: &mut Unit, filter: Option<u16>, threshold: i16) {
+    unit.configure(unit::Config {
+        low_limit: -LIMIT,
+        high_limit: LIMIT,
+        thresh0: threshold,
+        thresh1: -threshold,
+        filter,
+    })
+    .unwrap();
+}
+
+#[interrupt]
+fn PCNT() {
+    critical_section::with(|cs| {
+        let mut u0 = UNIT0.borrow_ref_mut(cs);
+        handle_events(u0.as_mut().unwrap());
+    });
+}
+
+/// Accumulates a pending limit event and flags a pending threshold event.
+///
+/// Must run in the same critical section as any read of the counter.
+fn handle_events(unit: &mut Unit) {
+    if !unit.interrupt_set() {
+        return;
+    }
+    let events = unit.get_events();
+    if events.high_limit {
+        VALUE.fetch_add(LIMIT as i32, Ordering::SeqCst);
+    } else if events.low_limit {
+        VALUE.fetch_add(-(LIMIT as i32), Ordering::SeqCst);
+    }
+    if events.thresh0 || events.thresh1 {
+        ROTATED.store(true, Ordering::SeqCst);
+        WAKER.wake();
+    }
+    unit.reset_interrupt();
+}
@@ -0,0 +1,149 @@
+/// Marks a transition that skipped a state, i.e. an edge was missed
+const MISSED: i8 = 2;
+
+/// Count change for each `(previous << 2) | next` state, where a state is
+/// `(a << 1) | b`. The direction matches the PCNT channel configuration:
+/// `00 → 01 → 11 → 10 → 00` counts up.
+#[rustfmt::skip]
+const TRANSITIONS: [i8; 16] = [
+    //  00      01      10      11     <- next
+        0,      1,      -1,     MISSED, // 00
+        -1,     0,      MISSED, 1,      // 01
+        1,      MISSED, 0,      -1,     // 10
+        MISSED, -1,     1,      0,      // 11
+];
+
+/// Software quadrature decoder
+///
+/// Feed it the levels of both encoder pins after every edge. Contact bounce
+/// shows up as a step forth and back and cancels out; a transition that
+/// skips a state means an edge was lost, in which case two counts are
+/// assumed in the last known direction.
+pub struct Quadrature {
+    state: u8,
+    count: i32,
+    direction: i8,
+    missed: u32,
+}
+
+impl Quadrature {
+    pub fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: Self::state(a, b),
+            count: 0,
+            direction: 0,
+            missed: 0,
+        }
+    }
+
+    fn state(a: bool, b: bool) -> u8 {
+        (a as u8) << 1 | b as u8
+    }
+
+    /// Returns the count change caused by the new pin levels.
+    pub fn update(&mut self, a: bool, b: bool) -> i32 {
+        let next = Self::state(a, b);
+        let delta = match TRANSITIONS[(self.state << 2 | next) as usize] {
+            MISSED => {
+                self.missed = self.missed.wrapping_add(1);
+                self.direction as i32 * 2
+            }
+            delta => {
+                if delta != 0 {
+                    self.direction = delta;
+                }
+                delta as i32
+            }
+        };
+        self.state = next;
+        self.count = self.count.wrapping_add(delta);
+        delta
+    }
+
+    pub fn count(&self) -> i32 {
+        self.count
+    }
+
+    /// Number of transitions that skipped a state
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pin levels of one clockwise quadrature cycle
+    const CYCLE: [(bool, bool); 4] = [(false, true), (true, true), (true, false), (false, false)];
+
+    fn feed(decoder: &mut Quadrature, levels: &[(bool, bool)]) -> i32 {
+        levels.iter().map(|&(a, b)| decoder.update(a, b)).sum()
+    }
+
+    #[test]
+    fn clockwise_cycle() {
+        let mut decoder = Quadrature::new(false, false);
+        assert_eq!(feed(&mut decoder, &CYCLE), 4);
+        assert_eq!(feed(&mut decoder, &CYCLE), 4);
+        assert_eq!(decoder.count(), 8);
+        assert_eq!(decoder.missed(), 0);
+    }
+
+    #[test]
+    fn counter_clockwise_cycle() {
+        let mut decoder = Quadrature::new(false, false);
+        let reverse = [(true, false), (true, true), (false, true), (false, false)];
+        assert_eq!(feed(&mut decoder, &reverse), -4);
+        assert_eq!(decoder.count(), -4);
+    }
+
+    #[test]
+    fn bounce_cancels() {
+        let mut decoder = Quadrature::new(false, false);
+        let bounce = [(false, true), (false, false), (false, true), (false, false)];
+        assert_eq!(feed(&mut decoder, &bounce), 0);
+        assert_eq!(decoder.update(false, false), 0);
+        assert_eq!(decoder.missed(), 0);
+    }
+
+    #[test]
+    fn valid_transitions_step_by_one() {
+        for previous in 0..4u8 {
+            for next in 0..4u8 {
+                let mut decoder = Quadrature::new(previous & 2 != 0, previous & 1 != 0);
+                let delta = decoder.update(next & 2 != 0, next & 1 != 0);
+                if previous == next {
+                    assert_eq!(delta, 0);
+                } else if previous ^ next != 3 {
+                    assert_eq!(delta.abs(), 1, "{previous:02b} -> {next:02b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_transition_without_direction() {
+        let mut decoder = Quadrature::new(false, false);
+        assert_eq!(decoder.update(true, true), 0);
+        assert_eq!(decoder.missed(), 1);
+        assert_eq!(decoder.count(), 0);
+    }
+
+    #[test]
+    fn invalid_transition_follows_direction() {
+        let mut decoder = Quadrature::new(false, false);
+        decoder.update(false, true);
+        // 01 -> 10 skips 11
+        assert_eq!(decoder.update(true, false), 2);
+        assert_eq!(decoder.update(false, false), 1);
+        assert_eq!(decoder.count(), 4);
+        assert_eq!(decoder.missed(), 1);
+
+        decoder.update(true, false);
+        // 10 -> 01 skips 11 backwards
+        assert_eq!(decoder.update(false, true), -2);
+        assert_eq!(decoder.count(), 1);
+        assert_eq!(decoder.missed(), 2);
+    }
+}
@@ -0,0 +1,145 @@
+use core::{
+    cell::RefCell,
+    task::{Context, Poll},
+};
+
+use critical_section::Mutex;
+use esp32s3_hal::{
+    gpio::{Event, Gpio40, Gpio41, Input, PullUp},
+    interrupt, peripherals,
+    prelude::*,
+};
+
+use super::{Config, Counter, Quadrature};
+use crate::waker::WakerCell;
+
+struct State {
+    a: Gpio40<Input<PullUp>>,
+    b: Gpio41<Input<PullUp>>,
+    decoder: Quadrature,
+    armed_at: i32,
+    threshold: i32,
+    rotated: bool,
+}
+
+static STATE: Mutex<RefCell<Option<State>>> = Mutex::new(RefCell::new(None));
+static WAKER: WakerCell = WakerCell::new();
+
+/// Quadrature counter decoded in software from GPIO edge interrupts
+///
+/// An alternative to [`Pcnt`](super::Pcnt) that runs the [`Quadrature`]
+/// state machine on every edge of GPIO40/GPIO41. The hardware glitch filter
+/// is not available; bounces are absorbed by the state machine instead.
+pub struct Software {
+    _private: (),
+}
+
+impl Software {
+    pub fn new(
+        mut a: Gpio40<Input<PullUp>>,
+        mut b: Gpio41<Input<PullUp>>,
+        config: &Config,
+    ) -> Self {
+        let decoder = Quadrature::new(a.is_high().unwrap(), b.is_high().unwrap());
+        a.listen(Event::AnyEdge);
+        b.listen(Event::AnyEdge);
+        let threshold = config.wake_detents as i32 * config.counts_per_detent.max(1) as i32;
+
+        critical_section::with(|cs| {
+            STATE.borrow_ref_mut(cs).replace(State {
+                a,
+                b,
+                decoder,
+                armed_at: 0,
+                threshold,
+                rotated: false,
+            })
+        });
+
+        interrupt::enable(peripherals::Interrupt::GPIO, interrupt::Priority::Priority2).unwrap();
+
+        Self { _private: () }
+    }
+
+    /// Transitions that skipped a state since start-up
+    pub fn missed(&self) -> u32 {
+        critical_section::with(|cs| STATE.borrow_ref(cs).as_ref().unwrap().decoder.missed())
+    }
+
+    /// Arms the encoder pins as light-sleep wakeup sources, see
+    /// [`listen_wakeup`](super::listen_wakeup).
+    pub fn listen_wakeup(&mut self) {
+        critical_section::with(|cs| {
+            let mut state = STATE.borrow_ref_mut(cs);
+            let state = state.as_mut().unwrap();
+            super::listen_wakeup(&mut state.a, &mut state.b);
+        })
+    }
+
+    /// Restores the edge interrupts and catches up with movement made while
+    /// the pins were armed for wakeup.
+    pub fn unlisten_wakeup(&mut self) {
+        critical_section::with(|cs| {
+            let mut state = STATE.borrow_ref_mut(cs);
+            let state = state.as_mut().unwrap();
+            state.a.listen(Event::AnyEdge);
+            state.b.listen(Event::AnyEdge);
+            let a = state.a.is_high().unwrap();
+            let b = state.b.is_high().unwrap();
+            state.decoder.update(a, b);
+        })
+    }
+
+    /// Handles edges on the encoder pins; call from the `GPIO` interrupt.
+    pub fn on_interrupt() {
+        critical_section::with(|cs| {
+            let mut state = STATE.borrow_ref_mut(cs);
+            let Some(state) = state.as_mut() else {
+                return;
+            };
+            if !state.a.is_interrupt_set() && !state.b.is_interrupt_set() {
+                return;
+            }
+            state.a.clear_interrupt();
+            state.b.clear_interrupt();
+            let a = state.a.is_high().unwrap();
+            let b = state.b.is_high().unwrap();
+            state.decoder.update(a, b);
+            if state.threshold > 0
+                && (state.decoder.count() - state.armed_at).abs() >= state.threshold
+            {
+                state.armed_at = state.decoder.count();
+                state.rotated = true;
+                WAKER.wake();
+            }
+        });
+    }
+}
+
+impl Counter for Software {
+    fn count(&self) -> i32 {
+        critical_section::with(|cs| STATE.borrow_ref(cs).as_ref().unwrap().decoder.count())
+    }
+
+    fn arm(&mut self) {
+        critical_section::with(|cs| {
+            let mut state = STATE.borrow_ref_mut(cs);
+            let state = state.as_mut().unwrap();
+            state.armed_at = state.decoder.count();
+            state.rotated = false;
+        })
+    }
+
+    fn poll_rotation(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        WAKER.register(cx.waker());
+        critical_section::with(|cs| {
+            let mut state = STATE.borrow_ref_mut(cs);
+            let state = state.as_mut().unwrap();
+            if core::mem::take(&mut state.rotated) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+}
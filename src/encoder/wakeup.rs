@@ -0,0 +1,34 @@
+use esp32s3_hal::gpio::{Event, InputPin, Pin};
+
+/// Arms the encoder pins as light-sleep wakeup sources.
+///
+/// Neither the PCNT unit nor edge interrupts work in light sleep. GPIO
+/// wakeup is level triggered, so each pin is armed for the level opposite to
+/// the one it currently rests at; any movement of the knob flips at least
+/// one of them. Call [`unlisten_wakeup`] after waking up.
+pub fn listen_wakeup<A, B>(a: &mut A, b: &mut B)
+where
+    A: InputPin + Pin,
+    B: InputPin + Pin,
+{
+    fn opposite_level<P: InputPin>(pin: &P) -> Event {
+        if pin.is_input_high() {
+            Event::LowLevel
+        } else {
+            Event::HighLevel
+        }
+    }
+    let event = opposite_level(a);
+    a.listen_with_options(event, false, false, true);
+    let event = opposite_level(b);
+    b.listen_with_options(event, false, false, true);
+}
+
+pub fn unlisten_wakeup<A, B>(a: &mut A, b: &mut B)
+where
+    A: InputPin + Pin,
+    B: InputPin + Pin,
+{
+    a.unlisten();
+    b.unlisten();
+}
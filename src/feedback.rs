@@ -0,0 +1,208 @@
+//! Audible feedback for knob, button and touch input
+//!
+//! Maps input events to short buzzer notes. Detent clicks are rate limited so
+//! a fast spin does not turn into a continuous tone, and they never cut off
+//! a longer press or tap note that is still sounding.
+//!
+//! ```ignore
+//! let mut feedback = feedback::Feedback::new(feedback::Config::default());
+//! for event in input.update(pressed, encoder.poll(), time::now_ms()) {
+//!     if let Some(note) = feedback.input(&event, time::now_ms()) {
+//!         buzzer.play(Melody::Tone(note), time::now_ms());
+//!     }
+//! }
+//! ```
+use crate::buzzer::Note;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cue {
+    /// The knob or the bezel moved by at least one detent
+    Detent,
+    /// The button went down
+    Press,
+    LongPress,
+    Tap,
+}
+
+/// Note per cue, `None` keeps a cue silent
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub detent: Option<Note>,
+    pub press: Option<Note>,
+    pub long_press: Option<Note>,
+    pub tap: Option<Note>,
+    /// Minimum time between two detent clicks
+    pub detent_interval_ms: u32,
+}
+
+impl Default for Config {
+    /// The buzzer is only updated once per main loop iteration (32 ms), so
+    /// no note is shorter than that and detent clicks leave a silent
+    /// iteration between them.
+    fn default() -> Self {
+        Self {
+            detent: Some(Note::new(4000, 32)),
+            press: Some(Note::new(2000, 32)),
+            long_press: Some(Note::new(1500, 64)),
+            tap: Some(Note::new(3000, 32)),
+            detent_interval_ms: 64,
+        }
+    }
+}
+
+pub struct Feedback {
+    config: Config,
+    enabled: bool,
+    last_detent_ms: Option<u64>,
+    /// End of the last non-detent note
+    busy_until_ms: u64,
+}
+
+impl Feedback {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            enabled: true,
+            last_detent_ms: None,
+            busy_until_ms: 0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the note to play for `cue`, if any.
+    pub fn cue(&mut self, cue: Cue, now_ms: u64) -> Option<Note> {
+        if !self.enabled {
+            return None;
+        }
+        let note = match cue {
+            Cue::Detent => {
+                if now_ms < self.busy_until_ms {
+                    return None;
+                }
+                if let Some(last) = self.last_detent_ms {
+                    if now_ms < last + self.config.detent_interval_ms as u64 {
+                        return None;
+                    }
+                }
+                let note = self.config.detent?;
+                self.last_detent_ms = Some(now_ms);
+                return Some(note);
+            }
+            Cue::Press => self.config.press?,
+            Cue::LongPress => self.config.long_press?,
+            Cue::Tap => self.config.tap?,
+        };
+        self.busy_until_ms = now_ms + note.duration_ms as u64;
+        Some(note)
+    }
+
+    /// Feedback for encoder detents when there is no [`crate::input::Input`]
+    pub fn detents(&mut self, delta: i32, now_ms: u64) -> Option<Note> {
+        if delta == 0 {
+            return None;
+        }
+        self.cue(Cue::Detent, now_ms)
+    }
+
+    #[cfg(feature = "button")]
+    pub fn input(&mut self, event: &crate::input::Event, now_ms: u64) -> Option<Note> {
+        use crate::{button, input::Event};
+
+        let cue = match event {
+            Event::Rotate { delta } | Event::PressedRotate { delta } if *delta != 0 => Cue::Detent,
+            Event::Button(button::Event::Press) => Cue::Press,
+            Event::Button(button::Event::LongPress) => Cue::LongPress,
+            _ => return None,
+        };
+        self.cue(cue, now_ms)
+    }
+
+    #[cfg(feature = "touch")]
+    pub fn gesture(&mut self, event: &crate::gesture::Event, now_ms: u64) -> Option<Note> {
+        use crate::gesture::Event;
+
+        let cue = match event {
+            Event::Tap { .. } | Event::DoubleTap { .. } => Cue::Tap,
+            Event::LongPress { .. } => Cue::LongPress,
+            Event::Rotate { delta } if *delta != 0 => Cue::Detent,
+            _ => return None,
+        };
+        self.cue(cue, now_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DETENT: Note = Note::new(4000, 32);
+    const PRESS: Note = Note::new(2000, 32);
+    const LONG_PRESS: Note = Note::new(1500, 64);
+    const TAP: Note = Note::new(3000, 32);
+
+    #[test]
+    fn fast_spin_is_throttled() {
+        let mut feedback = Feedback::new(Config::default());
+        let clicks: std::vec::Vec<u64> = (0..200)
+            .step_by(16)
+            .filter(|&now| feedback.detents(1, now).is_some())
+            .collect();
+        assert_eq!(clicks, [0, 64, 128, 192]);
+        // Slow turning clicks on every detent
+        assert_eq!(feedback.detents(-1, 300), Some(DETENT));
+        assert_eq!(feedback.detents(0, 400), None);
+    }
+
+    #[test]
+    fn detent_does_not_cut_off_notes() {
+        let mut feedback = Feedback::new(Config::default());
+        assert_eq!(feedback.cue(Cue::LongPress, 1_000), Some(LONG_PRESS));
+        assert_eq!(feedback.cue(Cue::Detent, 1_000), None);
+        assert_eq!(feedback.cue(Cue::Detent, 1_063), None);
+        assert_eq!(feedback.cue(Cue::Detent, 1_064), Some(DETENT));
+
+        // A press still sounds over a recent detent click
+        assert_eq!(feedback.cue(Cue::Press, 1_070), Some(PRESS));
+        assert_eq!(feedback.cue(Cue::Detent, 1_101), None);
+        assert_eq!(feedback.cue(Cue::Tap, 1_102), Some(TAP));
+        assert_eq!(feedback.cue(Cue::Detent, 1_134), Some(DETENT));
+    }
+
+    #[test]
+    fn disabled_and_silent_cues() {
+        let mut feedback = Feedback::new(Config {
+            detent: None,
+            ..Default::default()
+        });
+        assert_eq!(feedback.cue(Cue::Detent, 0), None);
+        assert_eq!(feedback.cue(Cue::Press, 0), Some(PRESS));
+        feedback.set_enabled(false);
+        assert_eq!(feedback.cue(Cue::Press, 100), None);
+    }
+
+    #[cfg(feature = "button")]
+    #[test]
+    fn input_events() {
+        use crate::{button, input::Event};
+
+        let mut feedback = Feedback::new(Config::default());
+        let press = Event::Button(button::Event::Press);
+        assert_eq!(feedback.input(&press, 0), Some(PRESS));
+        // Turning while pressed clicks once the press note ended
+        assert_eq!(feedback.input(&Event::PressedRotate { delta: 1 }, 16), None);
+        assert_eq!(
+            feedback.input(&Event::PressedRotate { delta: 1 }, 32),
+            Some(DETENT)
+        );
+        let release = Event::Button(button::Event::Release);
+        assert_eq!(feedback.input(&release, 200), None);
+        assert_eq!(feedback.input(&Event::Rotate { delta: 0 }, 300), None);
+    }
+}
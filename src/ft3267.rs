@@ -0,0 +1,525 @@
+//! FT3267 capacitive touch controller
+//!
+//! The controller reports up to two touch points. All point registers are
+//! read in a single burst starting at `TD_STATUS`, so a report is always
+//! consistent and a failed bus transfer is returned as an error instead of
+//! showing up as a touch.
+//!
+//! Each point carries the controller's touch ID. The controller may move a
+//! finger to another register slot between polls; [`Tracker`] keeps every
+//! finger in the same slot of [`TouchReport::points`] for as long as it
+//! stays on the panel.
+//!
+//! The configuration registers are available as typed accessors, e.g.
+//! [`FT3267::set_threshold`] to make the panel usable with gloves or
+//! [`FT3267::set_auto_monitor`] to save power on battery.
+//!
+//! ```ignore
+//! let mut touch = ft3267::FT3267::new(i2c);
+//! if let Ok(report) = touch.touch() {
+//!     for point in report.points.iter().flatten() {
+//!         println!("{}: {:?} {}, {}", point.id, point.phase, point.x, point.y);
+//!     }
+//! }
+//! ```
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+const ADDRESS: u8 = 0x38;
+
+const TD_STATUS: u8 = 0x02;
+const ID_G_THGROUP: u8 = 0x80;
+const ID_G_CTRL: u8 = 0x86;
+const ID_G_TIMEENTERMONITOR: u8 = 0x87;
+const ID_G_PERIODACTIVE: u8 = 0x88;
+const ID_G_PERIODMONITOR: u8 = 0x89;
+const ID_G_LIB_VERSION_H: u8 = 0xA1;
+const ID_G_CIPHER: u8 = 0xA3;
+const ID_G_MODE: u8 = 0xA4;
+const ID_G_PMODE: u8 = 0xA5;
+const ID_G_FIRMID: u8 = 0xA6;
+const ID_G_FOCALTECH_ID: u8 = 0xA8;
+/// `TD_STATUS` up to and including `P2_MISC`
+const REPORT_LEN: usize = 13;
+const P1: usize = 1;
+const P2: usize = 7;
+
+/// Register layout of a point, relative to its `Pn_XH`
+const XH: usize = 0;
+const XL: usize = 1;
+const YH: usize = 2;
+const YL: usize = 3;
+const WEIGHT: usize = 4;
+const MISC: usize = 5;
+const POINT_LEN: usize = 6;
+
+/// Largest number of points the controller tracks
+pub const MAX_POINTS: usize = 2;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The bus transfer failed
+    I2c(E),
+    /// `TD_STATUS` reported more points than the controller supports
+    InvalidCount(u8),
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
+}
+
+/// Power mode (`ID_G_PMODE`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerMode {
+    /// Scans at the active report rate
+    Active = 0,
+    /// Scans at the monitor report rate until touched
+    Monitor = 1,
+    /// Stops scanning; only a reset of the controller wakes it up again
+    Hibernate = 3,
+}
+
+/// Behaviour of the INT line (`ID_G_MODE`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+    /// INT stays low while the panel is touched
+    Polling = 0,
+    /// INT pulses low once per report
+    Trigger = 1,
+}
+
+/// Identification registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChipInfo {
+    /// `ID_G_CIPHER`
+    pub chip_id: u8,
+    /// `ID_G_FOCALTECH_ID`
+    pub vendor_id: u8,
+    /// `ID_G_FIRMID`
+    pub firmware: u8,
+    /// `ID_G_LIB_VERSION_H` and `_L`
+    pub library: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// The finger touched down in this report
+    Down,
+    /// The finger stays on the panel
+    Move,
+    /// The finger was lifted; the point holds its last position
+    Up,
+}
+
+impl Phase {
+    /// Decodes the event flag in the upper bits of `XH`.
+    fn from_flag(flag: u8) -> Self {
+        match flag {
+            0b00 => Phase::Down,
+            0b01 => Phase::Up,
+            // 0b10 is "contact", 0b11 "no event"; both mean the finger is down
+            _ => Phase::Move,
+        }
+    }
+}
+
+/// A touch point in panel coordinates; see [`crate::transform`] for the
+/// mapping to the display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TouchPoint {
+    /// Touch ID assigned by the controller, stable while the finger is down
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+    pub phase: Phase,
+    /// Touch pressure as reported by the controller
+    pub weight: u8,
+    /// Contact area as reported by the controller
+    pub area: u8,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TouchReport {
+    pub points: [Option<TouchPoint>; MAX_POINTS],
+}
+
+impl TouchReport {
+    /// Number of fingers on the panel
+    pub fn count(&self) -> usize {
+        self.points
+            .iter()
+            .flatten()
+            .filter(|p| p.phase != Phase::Up)
+            .count()
+    }
+
+    /// Positions of the fingers on the panel, by slot
+    pub fn positions(&self) -> [Option<(u16, u16)>; MAX_POINTS] {
+        self.points
+            .map(|p| p.filter(|p| p.phase != Phase::Up).map(|p| (p.x, p.y)))
+    }
+
+    /// Returns the point count as error if it is out of range.
+    fn parse(data: &[u8; REPORT_LEN]) -> Result<Self, u8> {
+        let count = data[0] & 0x0F;
+        if count as usize > MAX_POINTS {
+            return Err(count);
+        }
+        let mut report = Self::default();
+        for (i, offset) in [P1, P2].into_iter().enumerate().take(count as usize) {
+            let p = &data[offset..offset + POINT_LEN];
+            let x = ((p[XH] as u16 & 0x0F) << 8) | p[XL] as u16;
+            let y = ((p[YH] as u16 & 0x0F) << 8) | p[YL] as u16;
+            report.points[i] = Some(TouchPoint {
+                id: p[YH] >> 4,
+                x,
+                y,
+                phase: Phase::from_flag(p[XH] >> 6),
+                weight: p[WEIGHT],
+                area: p[MISC] >> 4,
+            });
+        }
+        Ok(report)
+    }
+}
+
+/// Keeps each finger in the same slot across reports
+///
+/// Fingers are matched by touch ID. A new finger takes the first free slot
+/// and is reported as [`Phase::Down`]; a finger that disappears without an
+/// up event is reported once as [`Phase::Up`] at its last position.
+#[derive(Default)]
+pub struct Tracker {
+    slots: [Option<TouchPoint>; MAX_POINTS],
+}
+
+impl Tracker {
+    pub fn update(&mut self, report: &TouchReport) -> TouchReport {
+        let mut next: [Option<TouchPoint>; MAX_POINTS] = [None; MAX_POINTS];
+
+        // Fingers that are already tracked keep their slot
+        for (slot, previous) in self.slots.iter().enumerate() {
+            let Some(previous) = previous.filter(|p| p.phase != Phase::Up) else {
+                continue;
+            };
+            next[slot] = Some(
+                match report.points.iter().flatten().find(|p| p.id == previous.id) {
+                    Some(point) => TouchPoint {
+                        phase: match point.phase {
+                            Phase::Up => Phase::Up,
+                            _ => Phase::Move,
+                        },
+                        ..*point
+                    },
+                    None => TouchPoint {
+                        phase: Phase::Up,
+                        ..previous
+                    },
+                },
+            );
+        }
+
+        // New fingers take the first free slot
+        for point in report.points.iter().flatten() {
+            if point.phase == Phase::Up || next.iter().flatten().any(|p| p.id == point.id) {
+                continue;
+            }
+            if let Some(slot) = next.iter_mut().find(|slot| slot.is_none()) {
+                *slot = Some(TouchPoint {
+                    phase: Phase::Down,
+                    ..*point
+                });
+            }
+        }
+
+        self.slots = next;
+        TouchReport { points: next }
+    }
+}
+
+pub struct FT3267<I2C> {
+    i2c: I2C,
+    address: u8,
+    tracker: Tracker,
+}
+
+impl<I2C, E> FT3267<I2C>
+where
+    I2C: WriteRead<Error = E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, ADDRESS)
+    }
+
+    pub fn with_address(i2c: I2C, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            tracker: Tracker::default(),
+        }
+    }
+
+    /// Reads the current touch points, each finger in a stable slot.
+    pub fn touch(&mut self) -> Result<TouchReport, Error<E>> {
+        let mut data = [0; REPORT_LEN];
+        self.i2c.write_read(self.address, &[TD_STATUS], &mut data)?;
+        let report = TouchReport::parse(&data).map_err(Error::InvalidCount)?;
+        Ok(self.tracker.update(&report))
+    }
+
+    /// Touch threshold; lower values are more sensitive, e.g. for gloves.
+    pub fn threshold(&mut self) -> Result<u8, Error<E>> {
+        self.read(ID_G_THGROUP)
+    }
+
+    /// Reports per second while the panel is touched
+    pub fn active_rate(&mut self) -> Result<u8, Error<E>> {
+        self.read(ID_G_PERIODACTIVE)
+    }
+
+    /// Reports per second in monitor mode
+    pub fn monitor_rate(&mut self) -> Result<u8, Error<E>> {
+        self.read(ID_G_PERIODMONITOR)
+    }
+
+    pub fn power_mode(&mut self) -> Result<PowerMode, Error<E>> {
+        Ok(match self.read(ID_G_PMODE)? {
+            1 => PowerMode::Monitor,
+            3 => PowerMode::Hibernate,
+            _ => PowerMode::Active,
+        })
+    }
+
+    pub fn info(&mut self) -> Result<ChipInfo, Error<E>> {
+        let mut version = [0; 2];
+        self.i2c
+            .write_read(self.address, &[ID_G_LIB_VERSION_H], &mut version)?;
+        Ok(ChipInfo {
+            chip_id: self.read(ID_G_CIPHER)?,
+            vendor_id: self.read(ID_G_FOCALTECH_ID)?,
+            firmware: self.read(ID_G_FIRMID)?,
+            library: u16::from_be_bytes(version),
+        })
+    }
+
+    /// Releases the bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, Error<E>> {
+        let mut data = [0];
+        self.i2c.write_read(self.address, &[register], &mut data)?;
+        Ok(data[0])
+    }
+}
+
+impl<I2C, E> FT3267<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    pub fn set_threshold(&mut self, threshold: u8) -> Result<(), Error<E>> {
+        self.write(ID_G_THGROUP, threshold)
+    }
+
+    pub fn set_active_rate(&mut self, rate: u8) -> Result<(), Error<E>> {
+        self.write(ID_G_PERIODACTIVE, rate)
+    }
+
+    pub fn set_monitor_rate(&mut self, rate: u8) -> Result<(), Error<E>> {
+        self.write(ID_G_PERIODMONITOR, rate)
+    }
+
+    /// Switches to monitor mode after `seconds` without a touch, or stays
+    /// in active mode with `None`.
+    pub fn set_auto_monitor(&mut self, seconds: Option<u8>) -> Result<(), Error<E>> {
+        match seconds {
+            Some(seconds) => {
+                self.write(ID_G_TIMEENTERMONITOR, seconds)?;
+                self.write(ID_G_CTRL, 1)
+            }
+            None => self.write(ID_G_CTRL, 0),
+        }
+    }
+
+    pub fn set_power_mode(&mut self, mode: PowerMode) -> Result<(), Error<E>> {
+        self.write(ID_G_PMODE, mode as u8)
+    }
+
+    /// Puts the controller into [`PowerMode::Hibernate`].
+    pub fn hibernate(&mut self) -> Result<(), Error<E>> {
+        self.set_power_mode(PowerMode::Hibernate)
+    }
+
+    pub fn set_interrupt_mode(&mut self, mode: InterruptMode) -> Result<(), Error<E>> {
+        self.write(ID_G_MODE, mode as u8)
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), Error<E>> {
+        self.i2c.write(self.address, &[register, value])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Register file behind the I2C address, with a log of burst reads
+    struct MockI2c {
+        registers: [u8; 256],
+        reads: std::vec::Vec<(u8, usize)>,
+        fail: bool,
+    }
+
+    impl MockI2c {
+        fn new() -> Self {
+            Self {
+                registers: [0; 256],
+                reads: std::vec::Vec::new(),
+                fail: false,
+            }
+        }
+
+        /// Sets `TD_STATUS` and the point registers
+        fn report(&mut self, points: &[(u8, u8, u16, u16)]) {
+            self.registers[TD_STATUS as usize..][..REPORT_LEN].fill(0xFF);
+            self.registers[TD_STATUS as usize] = points.len() as u8;
+            for (&(id, flag, x, y), offset) in points.iter().zip([P1, P2]) {
+                let p = &mut self.registers[TD_STATUS as usize + offset..][..POINT_LEN];
+                p[XH] = flag << 6 | (x >> 8) as u8;
+                p[XL] = x as u8;
+                p[YH] = id << 4 | (y >> 8) as u8;
+                p[YL] = y as u8;
+                p[WEIGHT] = 0x20;
+                p[MISC] = 0x30;
+            }
+        }
+    }
+
+    impl WriteRead for MockI2c {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            if self.fail {
+                return Err(());
+            }
+            let start = bytes[0] as usize;
+            self.reads.push((bytes[0], buffer.len()));
+            buffer.copy_from_slice(&self.registers[start..start + buffer.len()]);
+            Ok(())
+        }
+    }
+
+    impl Write for MockI2c {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            self.registers[bytes[0] as usize..][..bytes.len() - 1].copy_from_slice(&bytes[1..]);
+            Ok(())
+        }
+    }
+
+    const DOWN: u8 = 0b00;
+    const UP: u8 = 0b01;
+    const CONTACT: u8 = 0b10;
+
+    #[test]
+    fn reads_report_in_one_burst() {
+        let mut i2c = MockI2c::new();
+        i2c.report(&[(3, DOWN, 0x123, 0x0AB)]);
+        let mut touch = FT3267::new(i2c);
+        let report = touch.touch().unwrap();
+        assert_eq!(
+            report.points[0],
+            Some(TouchPoint {
+                id: 3,
+                x: 0x123,
+                y: 0x0AB,
+                phase: Phase::Down,
+                weight: 0x20,
+                area: 3,
+            })
+        );
+        assert_eq!(report.points[1], None);
+        assert_eq!(touch.release().reads, [(TD_STATUS, REPORT_LEN)]);
+    }
+
+    #[test]
+    fn parses_two_points() {
+        let mut i2c = MockI2c::new();
+        i2c.report(&[(0, DOWN, 10, 20), (1, DOWN, 230, 240)]);
+        let mut touch = FT3267::new(i2c);
+        let report = touch.touch().unwrap();
+        assert_eq!(report.count(), 2);
+        assert_eq!(report.positions(), [Some((10, 20)), Some((230, 240))]);
+    }
+
+    #[test]
+    fn rejects_invalid_count() {
+        let mut i2c = MockI2c::new();
+        i2c.registers[TD_STATUS as usize] = 5;
+        let mut touch = FT3267::new(i2c);
+        assert!(matches!(touch.touch(), Err(Error::InvalidCount(5))));
+    }
+
+    #[test]
+    fn bus_error_is_not_a_touch() {
+        let mut i2c = MockI2c::new();
+        i2c.report(&[(0, DOWN, 10, 20)]);
+        i2c.fail = true;
+        let mut touch = FT3267::new(i2c);
+        assert!(matches!(touch.touch(), Err(Error::I2c(()))));
+    }
+
+    #[test]
+    fn fingers_keep_their_slot() {
+        let mut touch = FT3267::new(MockI2c::new());
+        touch.i2c.report(&[(0, DOWN, 10, 10), (1, DOWN, 200, 200)]);
+        touch.touch().unwrap();
+
+        // The controller moves finger 1 into the first register slot
+        touch
+            .i2c
+            .report(&[(1, CONTACT, 201, 201), (0, CONTACT, 11, 11)]);
+        let report = touch.touch().unwrap();
+        assert_eq!(report.positions(), [Some((11, 11)), Some((201, 201))]);
+        assert_eq!(report.points[0].unwrap().phase, Phase::Move);
+
+        // Finger 0 vanishes without an up event
+        touch.i2c.report(&[(1, CONTACT, 202, 202)]);
+        let report = touch.touch().unwrap();
+        let lifted = report.points[0].unwrap();
+        assert_eq!((lifted.id, lifted.phase, lifted.x), (0, Phase::Up, 11));
+        assert_eq!(report.count(), 1);
+
+        // A new finger takes the free slot
+        touch
+            .i2c
+            .report(&[(1, CONTACT, 203, 203), (2, DOWN, 50, 50)]);
+        let report = touch.touch().unwrap();
+        assert_eq!(report.points[0].unwrap().id, 2);
+        assert_eq!(report.points[0].unwrap().phase, Phase::Down);
+
+        touch.i2c.report(&[(1, UP, 203, 203), (2, CONTACT, 50, 50)]);
+        let report = touch.touch().unwrap();
+        assert_eq!(report.points[1].unwrap().phase, Phase::Up);
+        assert_eq!(report.count(), 1);
+    }
+
+    #[test]
+    fn configuration_registers() {
+        let mut touch = FT3267::new(MockI2c::new());
+        touch.set_threshold(0x16).unwrap();
+        touch.set_auto_monitor(Some(5)).unwrap();
+        touch.set_power_mode(PowerMode::Monitor).unwrap();
+        assert_eq!(touch.threshold().unwrap(), 0x16);
+        assert_eq!(touch.power_mode().unwrap(), PowerMode::Monitor);
+        let i2c = touch.release();
+        assert_eq!(i2c.registers[ID_G_TIMEENTERMONITOR as usize], 5);
+        assert_eq!(i2c.registers[ID_G_CTRL as usize], 1);
+    }
+}
@@ -0,0 +1,112 @@
+// Float methods are inherent on the host
+#[cfg_attr(not(target_arch = "xtensa"), allow(unused_imports))]
+use num_traits::real::Real;
+
+/// Rim area and resolution of the bezel gesture
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Centre of the round display
+    pub center: (i32, i32),
+    /// Touches starting at least this far from the centre are on the rim
+    pub inner_radius: u16,
+    /// Steps reported for a full circle; matches the detents of the knob by
+    /// default so both can drive the same value
+    pub steps_per_turn: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            center: (120, 120),
+            inner_radius: 85,
+            steps_per_turn: 32,
+        }
+    }
+}
+
+/// Turns a finger dragged along the rim into rotation steps
+///
+/// The angle around the display centre is tracked while the finger moves;
+/// every `360 / steps_per_turn` degrees produce one step, clockwise positive,
+/// just like a detent of the encoder.
+pub struct Bezel {
+    config: Config,
+    last_angle: Option<f32>,
+    accumulated: f32,
+}
+
+impl Bezel {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            last_angle: None,
+            accumulated: 0.0,
+        }
+    }
+
+    /// Whether `(x, y)` lies on the rim
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let dx = (x - self.config.center.0) as f32;
+        let dy = (y - self.config.center.1) as f32;
+        let r = self.config.inner_radius as f32;
+        dx * dx + dy * dy >= r * r
+    }
+
+    /// Whether the move from `from` to `to` runs mostly along the rim rather
+    /// than towards or away from the centre
+    pub fn is_tangential(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+        let radius = self.radius(from.0, from.1);
+        let radial = (self.radius(to.0, to.1) - radius).abs();
+        let mut delta = self.angle(to.0, to.1) - self.angle(from.0, from.1);
+        if delta > 180.0 {
+            delta -= 360.0;
+        } else if delta <= -180.0 {
+            delta += 360.0;
+        }
+        delta.to_radians().abs() * radius > radial
+    }
+
+    /// Starts tracking at `(x, y)`.
+    pub fn start(&mut self, x: i32, y: i32) {
+        self.last_angle = Some(self.angle(x, y));
+        self.accumulated = 0.0;
+    }
+
+    /// Follows the finger to `(x, y)` and returns the steps turned.
+    pub fn update(&mut self, x: i32, y: i32) -> i32 {
+        let angle = self.angle(x, y);
+        let Some(last) = self.last_angle.replace(angle) else {
+            return 0;
+        };
+        let mut delta = angle - last;
+        if delta > 180.0 {
+            delta -= 360.0;
+        } else if delta <= -180.0 {
+            delta += 360.0;
+        }
+        self.accumulated += delta;
+
+        let step = 360.0 / self.config.steps_per_turn.max(1) as f32;
+        let steps = (self.accumulated / step).trunc();
+        self.accumulated -= steps * step;
+        steps as i32
+    }
+
+    pub fn end(&mut self) {
+        self.last_angle = None;
+        self.accumulated = 0.0;
+    }
+
+    fn radius(&self, x: i32, y: i32) -> f32 {
+        let dx = (x - self.config.center.0) as f32;
+        let dy = (y - self.config.center.1) as f32;
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// Clockwise angle in degrees, 0 at the right edge
+    fn angle(&self, x: i32, y: i32) -> f32 {
+        ((y - self.config.center.1) as f32)
+            .atan2((x - self.config.center.0) as f32)
+            .to_degrees()
+    }
+}
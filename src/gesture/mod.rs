@@ -0,0 +1,616 @@
+//! Touch gesture recognition
+//!
+//! [`Gestures`] consumes the [`TouchReport`]s of the FT3267 driver together
+//! with a timestamp and turns them into [`Event`]s. It keeps no reference to
+//! the driver, so recorded touch traces can be replayed through it.
+//!
+//! A drag that starts on the rim of the round display and runs along it is
+//! handled by [`Bezel`] and reported as [`Event::Rotate`], in the same detent
+//! units as the knob, instead of as a swipe. Drags from the rim towards the
+//! centre remain swipes.
+//!
+//! ```ignore
+//! let mut gestures = gesture::Gestures::new(gesture::Config::default());
+//! if let Ok(report) = touch.touch() {
+//!     for event in gestures.update(&report, time::now_ms()) {
+//!         println!("{event:?}");
+//!     }
+//! }
+//! ```
+use heapless::Vec;
+// Float methods are inherent on the host
+#[cfg_attr(not(target_arch = "xtensa"), allow(unused_imports))]
+use num_traits::real::Real;
+
+use crate::ft3267::{Phase, TouchReport};
+
+mod bezel;
+
+pub use bezel::{Bezel, Config as BezelConfig};
+
+/// Gesture thresholds; distances are in pixels, times in milliseconds
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Movement up to which a touch still counts as a tap or long press
+    pub tap_slop: u16,
+    /// Longest touch that counts as a tap
+    pub tap_max_ms: u64,
+    /// Longest gap between the taps of a double tap, 0 disables double taps
+    pub double_tap_ms: u64,
+    /// Largest distance between the taps of a double tap
+    pub double_tap_slop: u16,
+    /// Touch duration that triggers [`Event::LongPress`]
+    pub long_press_ms: u64,
+    /// Shortest distance covered by a swipe
+    pub swipe_min_distance: u16,
+    /// Slowest swipe, in pixels per second
+    pub swipe_min_velocity: u16,
+    /// Relative change of the finger distance that starts a pinch
+    pub pinch_threshold: f32,
+    /// Angle in degrees that starts a two-finger rotation
+    pub rotate_threshold: f32,
+    /// Rim gesture, `None` reports drags along the rim as swipes
+    pub bezel: Option<BezelConfig>,
+}
+
+impl Default for Config {
+    /// Tuned for the 240 x 240 px round display of the Dial
+    fn default() -> Self {
+        Self {
+            tap_slop: 12,
+            tap_max_ms: 250,
+            double_tap_ms: 300,
+            double_tap_slop: 30,
+            long_press_ms: 600,
+            swipe_min_distance: 50,
+            swipe_min_velocity: 150,
+            pinch_threshold: 0.1,
+            rotate_threshold: 10.0,
+            bezel: Some(BezelConfig::default()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Tap {
+        x: i32,
+        y: i32,
+    },
+    DoubleTap {
+        x: i32,
+        y: i32,
+    },
+    LongPress {
+        x: i32,
+        y: i32,
+    },
+    /// `velocity` in pixels per second
+    Swipe {
+        direction: Direction,
+        velocity: u16,
+    },
+    /// Finger distance relative to the start of the pinch
+    Pinch {
+        scale: f32,
+    },
+    /// Degrees turned since the start of the rotation, clockwise positive
+    TwoFingerRotate {
+        angle: f32,
+    },
+    /// A finger dragged along the rim by `delta` steps, clockwise positive,
+    /// in the same units as [`input::Event::Rotate`](crate::input::Event::Rotate)
+    Rotate {
+        delta: i32,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    fn distance(&self, other: &Point) -> f32 {
+        let dx = (other.x - self.x) as f32;
+        let dy = (other.y - self.y) as f32;
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    fn angle(&self, other: &Point) -> f32 {
+        ((other.y - self.y) as f32)
+            .atan2((other.x - self.x) as f32)
+            .to_degrees()
+    }
+}
+
+enum State {
+    Idle,
+    OneFinger {
+        start: Point,
+        start_ms: u64,
+        last: Point,
+        moved: bool,
+        long_pressed: bool,
+    },
+    TwoFingers {
+        distance: f32,
+        angle: f32,
+        scale: Option<f32>,
+        rotation: Option<f32>,
+    },
+    /// A finger is dragged along the rim
+    Bezel,
+    /// A multi-finger gesture is over but fingers remain on the panel
+    Finished,
+}
+
+pub struct Gestures {
+    config: Config,
+    state: State,
+    pending_tap: Option<(u64, Point)>,
+    bezel: Option<Bezel>,
+}
+
+impl Gestures {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            state: State::Idle,
+            pending_tap: None,
+            bezel: config.bezel.map(Bezel::new),
+        }
+    }
+
+    /// Feeds a touch report taken at `now_ms`.
+    pub fn update(&mut self, report: &TouchReport, now_ms: u64) -> Vec<Event, 2> {
+        let mut events = Vec::new();
+
+        let mut fingers = report
+            .points
+            .iter()
+            .flatten()
+            .filter(|p| p.phase != Phase::Up)
+            .map(|p| Point {
+                x: p.x as i32,
+                y: p.y as i32,
+            });
+        let first = fingers.next();
+        let second = fingers.next();
+
+        if let Some((tapped_at, at)) = self.pending_tap {
+            if now_ms.saturating_sub(tapped_at) > self.config.double_tap_ms {
+                self.pending_tap = None;
+                events.push(Event::Tap { x: at.x, y: at.y }).ok();
+            }
+        }
+
+        match (first, second) {
+            (Some(a), Some(b)) => self.two_fingers(a, b, &mut events),
+            (Some(p), None) => self.one_finger(p, now_ms, &mut events),
+            _ => self.released(now_ms, &mut events),
+        }
+
+        events
+    }
+
+    fn one_finger(&mut self, p: Point, now_ms: u64, events: &mut Vec<Event, 2>) {
+        match &mut self.state {
+            State::Idle => {
+                self.state = State::OneFinger {
+                    start: p,
+                    start_ms: now_ms,
+                    last: p,
+                    moved: false,
+                    long_pressed: false,
+                }
+            }
+            State::OneFinger {
+                start,
+                start_ms,
+                last,
+                moved,
+                long_pressed,
+            } => {
+                *last = p;
+                if !*moved && start.distance(&p) > self.config.tap_slop as f32 {
+                    *moved = true;
+                    let along_rim = |b: &&mut Bezel| {
+                        b.contains(start.x, start.y)
+                            && b.is_tangential((start.x, start.y), (p.x, p.y))
+                    };
+                    if let Some(bezel) = self.bezel.as_mut().filter(along_rim) {
+                        bezel.start(start.x, start.y);
+                        let delta = bezel.update(p.x, p.y);
+                        if delta != 0 {
+                            events.push(Event::Rotate { delta }).ok();
+                        }
+                        self.state = State::Bezel;
+                        return;
+                    }
+                }
+                if !*moved
+                    && !*long_pressed
+                    && now_ms.saturating_sub(*start_ms) >= self.config.long_press_ms
+                {
+                    *long_pressed = true;
+                    self.pending_tap = None;
+                    events
+                        .push(Event::LongPress {
+                            x: start.x,
+                            y: start.y,
+                        })
+                        .ok();
+                }
+            }
+            State::Bezel => {
+                if let Some(bezel) = self.bezel.as_mut() {
+                    let delta = bezel.update(p.x, p.y);
+                    if delta != 0 {
+                        events.push(Event::Rotate { delta }).ok();
+                    }
+                }
+            }
+            State::TwoFingers { .. } => self.state = State::Finished,
+            State::Finished => {}
+        }
+    }
+
+    fn two_fingers(&mut self, a: Point, b: Point, events: &mut Vec<Event, 2>) {
+        let distance = a.distance(&b);
+        let angle = a.angle(&b);
+        match &mut self.state {
+            State::TwoFingers {
+                distance: start_distance,
+                angle: start_angle,
+                scale,
+                rotation,
+            } => {
+                if *start_distance > 0.0 {
+                    let s = distance / *start_distance;
+                    let started = scale.is_some() || (s - 1.0).abs() >= self.config.pinch_threshold;
+                    if started && *scale != Some(s) {
+                        *scale = Some(s);
+                        events.push(Event::Pinch { scale: s }).ok();
+                    }
+                }
+                let mut r = angle - *start_angle;
+                if r > 180.0 {
+                    r -= 360.0;
+                } else if r <= -180.0 {
+                    r += 360.0;
+                }
+                let started = rotation.is_some() || r.abs() >= self.config.rotate_threshold;
+                if started && *rotation != Some(r) {
+                    *rotation = Some(r);
+                    events.push(Event::TwoFingerRotate { angle: r }).ok();
+                }
+            }
+            _ => {
+                self.pending_tap = None;
+                if let Some(bezel) = self.bezel.as_mut() {
+                    bezel.end();
+                }
+                self.state = State::TwoFingers {
+                    distance,
+                    angle,
+                    scale: None,
+                    rotation: None,
+                };
+            }
+        }
+    }
+
+    fn released(&mut self, now_ms: u64, events: &mut Vec<Event, 2>) {
+        if let State::OneFinger {
+            start,
+            start_ms,
+            last,
+            moved,
+            long_pressed,
+        } = self.state
+        {
+            let duration = now_ms.saturating_sub(start_ms).max(1);
+            if long_pressed {
+                // Already reported
+            } else if moved {
+                let distance = start.distance(&last);
+                let velocity = (distance * 1000.0 / duration as f32) as u16;
+                if distance >= self.config.swipe_min_distance as f32
+                    && velocity >= self.config.swipe_min_velocity
+                {
+                    let dx = last.x - start.x;
+                    let dy = last.y - start.y;
+                    let direction = if dx.abs() >= dy.abs() {
+                        if dx > 0 {
+                            Direction::Right
+                        } else {
+                            Direction::Left
+                        }
+                    } else if dy > 0 {
+                        Direction::Down
+                    } else {
+                        Direction::Up
+                    };
+                    events
+                        .push(Event::Swipe {
+                            direction,
+                            velocity,
+                        })
+                        .ok();
+                }
+            } else if duration <= self.config.tap_max_ms {
+                self.tap(start, now_ms, events);
+            }
+        }
+        if let Some(bezel) = self.bezel.as_mut() {
+            bezel.end();
+        }
+        self.state = State::Idle;
+    }
+
+    fn tap(&mut self, at: Point, now_ms: u64, events: &mut Vec<Event, 2>) {
+        if self.config.double_tap_ms == 0 {
+            events.push(Event::Tap { x: at.x, y: at.y }).ok();
+            return;
+        }
+        match self.pending_tap.take() {
+            Some((_, first)) if first.distance(&at) <= self.config.double_tap_slop as f32 => {
+                events.push(Event::DoubleTap { x: at.x, y: at.y }).ok();
+            }
+            Some((_, first)) => {
+                events
+                    .push(Event::Tap {
+                        x: first.x,
+                        y: first.y,
+                    })
+                    .ok();
+                self.pending_tap = Some((now_ms, at));
+            }
+            None => self.pending_tap = Some((now_ms, at)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ft3267::TouchPoint;
+
+    /// One report of a recorded trace: timestamp and finger positions
+    type Frame = (u64, std::vec::Vec<(u16, u16)>);
+
+    fn report(fingers: &[(u16, u16)]) -> TouchReport {
+        let mut report = TouchReport::default();
+        for (i, &(x, y)) in fingers.iter().enumerate() {
+            report.points[i] = Some(TouchPoint {
+                id: i as u8,
+                x,
+                y,
+                phase: Phase::Move,
+                weight: 0,
+                area: 0,
+            });
+        }
+        report
+    }
+
+    fn replay(gestures: &mut Gestures, trace: &[Frame]) -> std::vec::Vec<(u64, Event)> {
+        let mut events = std::vec::Vec::new();
+        for (now, fingers) in trace {
+            let reported = gestures.update(&report(fingers), *now);
+            events.extend(reported.into_iter().map(|event| (*now, event)));
+        }
+        events
+    }
+
+    /// A finger moving in a straight line, sampled every 10 ms
+    fn drag(
+        from: (u16, u16),
+        to: (u16, u16),
+        start_ms: u64,
+        duration_ms: u64,
+    ) -> std::vec::Vec<Frame> {
+        let steps = duration_ms / 10;
+        (0..=steps)
+            .map(|i| {
+                let t = i as f32 / steps as f32;
+                let x = from.0 as f32 + (to.0 as f32 - from.0 as f32) * t;
+                let y = from.1 as f32 + (to.1 as f32 - from.1 as f32) * t;
+                (
+                    start_ms + i * 10,
+                    vec![(x.round() as u16, y.round() as u16)],
+                )
+            })
+            .collect()
+    }
+
+    fn hold(at: (u16, u16), start_ms: u64, duration_ms: u64) -> std::vec::Vec<Frame> {
+        drag(at, at, start_ms, duration_ms)
+    }
+
+    fn lift(at_ms: u64) -> Frame {
+        (at_ms, vec![])
+    }
+
+    fn gestures() -> Gestures {
+        Gestures::new(Config::default())
+    }
+
+    #[test]
+    fn tap_after_double_tap_window() {
+        let mut trace = hold((120, 120), 0, 100);
+        trace.push(lift(110));
+        trace.push(lift(300));
+        trace.push(lift(420));
+        assert_eq!(
+            replay(&mut gestures(), &trace),
+            [(420, Event::Tap { x: 120, y: 120 })]
+        );
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut trace = hold((120, 120), 0, 80);
+        trace.push(lift(90));
+        trace.extend(hold((125, 118), 200, 80));
+        trace.push(lift(290));
+        trace.push(lift(800));
+        assert_eq!(
+            replay(&mut gestures(), &trace),
+            [(290, Event::DoubleTap { x: 125, y: 118 })]
+        );
+    }
+
+    #[test]
+    fn distant_taps_stay_single() {
+        let mut trace = hold((60, 120), 0, 80);
+        trace.push(lift(90));
+        trace.extend(hold((180, 120), 200, 80));
+        trace.push(lift(290));
+        trace.push(lift(800));
+        assert_eq!(
+            replay(&mut gestures(), &trace),
+            [
+                (290, Event::Tap { x: 60, y: 120 }),
+                (800, Event::Tap { x: 180, y: 120 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let mut trace = hold((100, 110), 0, 900);
+        trace.push(lift(910));
+        trace.push(lift(1500));
+        assert_eq!(
+            replay(&mut gestures(), &trace),
+            [(600, Event::LongPress { x: 100, y: 110 })]
+        );
+    }
+
+    #[test]
+    fn swipes() {
+        let cases = [
+            ((60, 120), (180, 120), Direction::Right),
+            ((180, 120), (60, 120), Direction::Left),
+            ((120, 60), (120, 180), Direction::Down),
+            ((120, 180), (120, 60), Direction::Up),
+        ];
+        for (from, to, direction) in cases {
+            let mut trace = drag(from, to, 0, 200);
+            trace.push(lift(210));
+            assert_eq!(
+                replay(&mut gestures(), &trace),
+                [(
+                    210,
+                    Event::Swipe {
+                        direction,
+                        velocity: 571
+                    }
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn slow_drag_is_no_swipe() {
+        let mut trace = drag((60, 120), (180, 120), 0, 2000);
+        trace.push(lift(2010));
+        assert_eq!(replay(&mut gestures(), &trace), []);
+    }
+
+    #[test]
+    fn pinch() {
+        let trace: std::vec::Vec<Frame> = (0..=10)
+            .map(|i| {
+                let spread = 20 + 4 * i as u16;
+                (i * 20, vec![(120 - spread, 120), (120 + spread, 120)])
+            })
+            .collect();
+        let events = replay(&mut gestures(), &trace);
+        let Some((_, Event::Pinch { scale })) = events.last() else {
+            panic!("{events:?}");
+        };
+        assert!((scale - 3.0).abs() < 1e-3);
+        assert!(!events
+            .iter()
+            .any(|(_, event)| matches!(event, Event::TwoFingerRotate { .. })));
+    }
+
+    #[test]
+    fn two_finger_rotation() {
+        let trace: std::vec::Vec<Frame> = (0..=9)
+            .map(|i| {
+                let angle = (i as f32 * 10.0).to_radians();
+                let (dx, dy) = ((40.0 * angle.cos()) as i32, (40.0 * angle.sin()) as i32);
+                let a = ((120 - dx) as u16, (120 - dy) as u16);
+                let b = ((120 + dx) as u16, (120 + dy) as u16);
+                (i * 20, vec![a, b])
+            })
+            .collect();
+        let events = replay(&mut gestures(), &trace);
+        let Some((_, Event::TwoFingerRotate { angle })) = events.last() else {
+            panic!("{events:?}");
+        };
+        assert!((angle - 90.0).abs() < 2.0, "{angle}");
+    }
+
+    #[test]
+    fn drag_along_rim_rotates() {
+        // Quarter circle at radius 100, clockwise from the right edge
+        let mut trace: std::vec::Vec<Frame> = (0..=18)
+            .map(|i| {
+                let angle = (i as f32 * 5.0).to_radians();
+                let x = 120.0 + 100.0 * angle.cos();
+                let y = 120.0 + 100.0 * angle.sin();
+                (i * 10, vec![(x.round() as u16, y.round() as u16)])
+            })
+            .collect();
+        trace.push(lift(200));
+        let events = replay(&mut gestures(), &trace);
+        let steps: i32 = events
+            .iter()
+            .map(|(_, event)| match event {
+                Event::Rotate { delta } => *delta,
+                other => panic!("{other:?}"),
+            })
+            .sum();
+        assert_eq!(steps, 8);
+    }
+
+    #[test]
+    fn swipe_from_rim_is_no_rotation() {
+        let mut trace = drag((225, 120), (105, 120), 0, 200);
+        trace.push(lift(210));
+        assert_eq!(
+            replay(&mut gestures(), &trace),
+            [(
+                210,
+                Event::Swipe {
+                    direction: Direction::Left,
+                    velocity: 571
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn second_finger_cancels_tap() {
+        let mut trace = hold((120, 120), 0, 50);
+        trace.push((60, vec![(120, 120), (160, 120)]));
+        trace.push((80, vec![(120, 120)]));
+        trace.push(lift(90));
+        trace.push(lift(600));
+        assert_eq!(replay(&mut gestures(), &trace), []);
+    }
+}
@@ -0,0 +1,241 @@
+//! Heap over internal SRAM and PSRAM
+//!
+//! Installs the global allocator, so `alloc` collections can be used next to
+//! the static and `heapless` ones. Internal SRAM gets a fixed block; with the
+//! `psram` feature the external PSRAM is added and takes the large
+//! allocations, keeping internal RAM for small and DMA capable buffers.
+//!
+//! Running out of memory logs [`Stats`], keeps an [`OutOfMemory`] report in
+//! RTC memory and resets the chip; the next boot shows the report with
+//! [`take_report`]. On battery the Dial stays on through the reset because
+//! [`PowerHold`](crate::power::PowerHold) latches its pad.
+//!
+//! ```ignore
+//! heap::init();
+//! #[cfg(feature = "psram")]
+//! heap::init_psram(peripherals.PSRAM);
+//! if let Some(report) = heap::take_report() { /* show it */ }
+//! println!("heap: {}", heap::stats());
+//! ```
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    fmt,
+    mem::MaybeUninit,
+    ptr::addr_of_mut,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "psram")]
+use esp32s3_hal::psram;
+use esp32s3_hal::{macros::ram, reset::software_reset};
+use esp_alloc::EspHeap;
+use esp_println::println;
+
+/// Internal SRAM given to the heap
+const HEAP_SIZE: usize = 64 * 1024;
+/// Allocations from this size on prefer PSRAM
+#[cfg(feature = "psram")]
+const PSRAM_THRESHOLD: usize = 1024;
+
+static mut HEAP: MaybeUninit<[u8; HEAP_SIZE]> = MaybeUninit::uninit();
+
+#[global_allocator]
+static ALLOCATOR: Heap = Heap {
+    internal: EspHeap::empty(),
+    #[cfg(feature = "psram")]
+    psram: EspHeap::empty(),
+    peak: AtomicUsize::new(0),
+    failures: AtomicUsize::new(0),
+};
+
+struct Heap {
+    internal: EspHeap,
+    #[cfg(feature = "psram")]
+    psram: EspHeap,
+    peak: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+impl Heap {
+    #[cfg(feature = "psram")]
+    fn is_psram(ptr: *mut u8) -> bool {
+        let start = psram::psram_vaddr_start();
+        (start..start + psram::PSRAM_BYTES).contains(&(ptr as usize))
+    }
+
+    #[cfg(feature = "psram")]
+    fn used(&self) -> usize {
+        self.internal.used() + self.psram.used()
+    }
+
+    #[cfg(not(feature = "psram"))]
+    fn used(&self) -> usize {
+        self.internal.used()
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(feature = "psram")]
+        let ptr = {
+            let (first, second) = if layout.size() >= PSRAM_THRESHOLD {
+                (&self.psram, &self.internal)
+            } else {
+                (&self.internal, &self.psram)
+            };
+            match first.alloc(layout) {
+                ptr if ptr.is_null() => second.alloc(layout),
+                ptr => ptr,
+            }
+        };
+        #[cfg(not(feature = "psram"))]
+        let ptr = self.internal.alloc(layout);
+
+        if ptr.is_null() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.peak.fetch_max(self.used(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "psram")]
+        if Self::is_psram(ptr) {
+            return self.psram.dealloc(ptr, layout);
+        }
+        self.internal.dealloc(ptr, layout)
+    }
+}
+
+/// Gives the internal SRAM block to the heap. Call once, before allocating.
+pub fn init() {
+    // SAFETY: called once, nothing else uses `HEAP`
+    unsafe {
+        ALLOCATOR
+            .internal
+            .init(addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE)
+    }
+}
+
+/// Maps the PSRAM and adds it to the heap.
+#[cfg(feature = "psram")]
+pub fn init_psram(peripheral: esp32s3_hal::peripherals::PSRAM) {
+    psram::init_psram(peripheral);
+    // SAFETY: the mapped PSRAM is used for nothing else
+    unsafe {
+        ALLOCATOR
+            .psram
+            .init(psram::psram_vaddr_start() as *mut u8, psram::PSRAM_BYTES)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Region {
+    pub used: usize,
+    pub free: usize,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} KiB",
+            self.used / 1024,
+            (self.used + self.free) / 1024
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub internal: Region,
+    /// `None` without PSRAM
+    pub psram: Option<Region>,
+    /// Most bytes in use at once since boot
+    pub peak: usize,
+    /// Allocations that could not be served
+    pub failures: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "internal {}", self.internal)?;
+        if let Some(psram) = self.psram {
+            write!(f, ", psram {psram}")?;
+        }
+        write!(f, ", peak {} KiB", self.peak / 1024)
+    }
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        internal: Region {
+            used: ALLOCATOR.internal.used(),
+            free: ALLOCATOR.internal.free(),
+        },
+        #[cfg(feature = "psram")]
+        psram: Some(Region {
+            used: ALLOCATOR.psram.used(),
+            free: ALLOCATOR.psram.free(),
+        }),
+        #[cfg(not(feature = "psram"))]
+        psram: None,
+        peak: ALLOCATOR.peak.load(Ordering::Relaxed),
+        failures: ALLOCATOR.failures.load(Ordering::Relaxed),
+    }
+}
+
+/// A failed allocation that reset the chip
+#[derive(Clone, Copy, Debug)]
+pub struct OutOfMemory {
+    pub size: usize,
+    pub align: usize,
+    pub stats: Stats,
+}
+
+const REPORT_MAGIC: u32 = 0x4F4F_4D21;
+
+struct SavedReport {
+    magic: u32,
+    report: MaybeUninit<OutOfMemory>,
+}
+
+/// Survives the software reset
+#[ram(rtc_fast, uninitialized)]
+static mut SAVED_REPORT: SavedReport = SavedReport {
+    magic: 0,
+    report: MaybeUninit::uninit(),
+};
+
+/// Report of an allocation failure before the last reset. It is consumed.
+pub fn take_report() -> Option<OutOfMemory> {
+    critical_section::with(|_| unsafe {
+        if SAVED_REPORT.magic != REPORT_MAGIC {
+            return None;
+        }
+        SAVED_REPORT.magic = 0;
+        Some(SAVED_REPORT.report.assume_init())
+    })
+}
+
+/// Called by the allocation error handler. Must not allocate.
+pub fn out_of_memory(layout: Layout) -> ! {
+    let report = OutOfMemory {
+        size: layout.size(),
+        align: layout.align(),
+        stats: stats(),
+    };
+    println!(
+        "heap: out of memory allocating {} bytes, {}",
+        report.size, report.stats
+    );
+    critical_section::with(|_| unsafe {
+        SAVED_REPORT.report = MaybeUninit::new(report);
+        SAVED_REPORT.magic = REPORT_MAGIC;
+    });
+    software_reset();
+    // The reset takes a moment to hit
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
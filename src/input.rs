@@ -0,0 +1,171 @@
+//! Combined knob input
+//!
+//! Merges the button and encoder into one event stream. Turning the knob
+//! while the button is held is reported as [`Event::PressedRotate`] instead
+//! of [`Event::Rotate`], and the press it happened in no longer counts as a
+//! click or long press.
+//!
+//! ```ignore
+//! let mut input = input::Input::new(button::Config::default());
+//! for event in input.update(pin.is_low().unwrap(), encoder.poll(), time::now_ms()) {
+//!     match event {
+//!         input::Event::Rotate { delta } => value += delta,
+//!         input::Event::PressedRotate { delta } => page += delta,
+//!         input::Event::Button(button::Event::Click) => select(),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+use heapless::Vec;
+
+use crate::button::{self, Button};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The knob was turned by `delta` detents, positive clockwise
+    Rotate {
+        delta: i32,
+    },
+    /// The knob was turned while the button was held down
+    PressedRotate {
+        delta: i32,
+    },
+    Button(button::Event),
+}
+
+pub struct Input {
+    button: Button,
+}
+
+impl Input {
+    pub fn new(config: button::Config) -> Self {
+        Self {
+            button: Button::new(config),
+        }
+    }
+
+    /// Debounced button state
+    pub fn is_pressed(&self) -> bool {
+        self.button.is_pressed()
+    }
+
+    /// Feeds a raw button sample and the detents turned since the previous
+    /// call, both taken at `now_ms`.
+    pub fn update(&mut self, pressed: bool, delta: i32, now_ms: u64) -> Vec<Event, 3> {
+        let mut events: Vec<Event, 3> = self
+            .button
+            .update(pressed, now_ms)
+            .into_iter()
+            .map(Event::Button)
+            .collect();
+
+        if delta != 0 {
+            if self.button.is_pressed() {
+                self.button.consume();
+                events.push(Event::PressedRotate { delta }).ok();
+            } else {
+                events.push(Event::Rotate { delta }).ok();
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples the button every millisecond from `from` to `to`, turning the
+    /// knob by `turns[i].1` at `turns[i].0`.
+    fn run(
+        input: &mut Input,
+        pressed: impl Fn(u64) -> bool,
+        turns: &[(u64, i32)],
+        from: u64,
+        to: u64,
+    ) -> std::vec::Vec<(u64, Event)> {
+        let mut events = std::vec::Vec::new();
+        for now in from..to {
+            let delta = turns
+                .iter()
+                .filter(|(at, _)| *at == now)
+                .map(|(_, delta)| delta)
+                .sum();
+            events.extend(
+                input
+                    .update(pressed(now), delta, now)
+                    .into_iter()
+                    .map(|event| (now, event)),
+            );
+        }
+        events
+    }
+
+    fn input() -> Input {
+        Input::new(button::Config::default())
+    }
+
+    #[test]
+    fn rotate_while_released() {
+        let events = run(&mut input(), |_| false, &[(5, 1), (9, -2)], 0, 20);
+        assert_eq!(
+            events,
+            [
+                (5, Event::Rotate { delta: 1 }),
+                (9, Event::Rotate { delta: -2 })
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_while_pressed_suppresses_click() {
+        let events = run(
+            &mut input(),
+            |now| (0..100).contains(&now),
+            &[(50, 1), (60, 1)],
+            0,
+            1000,
+        );
+        assert_eq!(
+            events,
+            [
+                (20, Event::Button(button::Event::Press)),
+                (50, Event::PressedRotate { delta: 1 }),
+                (60, Event::PressedRotate { delta: 1 }),
+                (120, Event::Button(button::Event::Release)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_while_pressed_suppresses_long_press() {
+        let events = run(&mut input(), |now| now < 1000, &[(100, -1)], 0, 1100);
+        assert!(events.contains(&(100, Event::PressedRotate { delta: -1 })));
+        assert!(!events.iter().any(|(_, event)| matches!(
+            event,
+            Event::Button(button::Event::LongPress | button::Event::Hold)
+        )));
+    }
+
+    #[test]
+    fn rotation_after_release_is_plain() {
+        let mut input = input();
+        let events = run(&mut input, |now| now < 100, &[(50, 1)], 0, 150);
+        assert!(events.contains(&(50, Event::PressedRotate { delta: 1 })));
+        // The next press is a normal click again
+        let events = run(&mut input, |now| now < 200, &[(160, 1)], 150, 600);
+        assert_eq!(
+            events
+                .iter()
+                .map(|(_, event)| *event)
+                .collect::<std::vec::Vec<_>>(),
+            [
+                Event::Rotate { delta: 1 },
+                Event::Button(button::Event::Press),
+                Event::Button(button::Event::Release),
+                Event::Button(button::Event::Click),
+            ]
+        );
+    }
+}
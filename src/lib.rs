@@ -0,0 +1,60 @@
+//! Drivers and application logic of the M5Stack Dial firmware
+//!
+//! Modules that program ESP32-S3 peripherals only build for the xtensa
+//! target. Everything else is hardware independent and also builds on the
+//! host, where the unit tests run:
+//!
+//! ```text
+//! cargo +stable test --lib --target x86_64-unknown-linux-gnu
+//! ```
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(all(target_arch = "xtensa", feature = "alloc"))]
+pub mod heap;
+pub mod power;
+#[cfg(target_arch = "xtensa")]
+pub mod time;
+pub mod ui;
+#[cfg(feature = "i2c")]
+pub mod bus;
+#[cfg(any(feature = "dial", feature = "touch"))]
+pub mod waker;
+
+#[cfg(feature = "rtc")]
+pub mod bm8563;
+#[cfg(feature = "buzzer")]
+pub mod buzzer;
+#[cfg(feature = "feedback")]
+pub mod feedback;
+#[cfg(feature = "touch")]
+pub mod calibration;
+#[cfg(feature = "rtc")]
+pub mod clock;
+#[cfg(feature = "touch")]
+pub mod ft3267;
+#[cfg(feature = "touch")]
+pub mod gesture;
+#[cfg(all(target_arch = "xtensa", feature = "touch"))]
+pub mod touch;
+#[cfg(feature = "touch")]
+pub mod transform;
+#[cfg(feature = "rfid")]
+pub mod ws1850s;
+#[cfg(feature = "ndef")]
+pub mod ndef;
+#[cfg(feature = "port-b")]
+pub mod port_b;
+#[cfg(feature = "settings")]
+pub mod settings;
+#[cfg(feature = "port-a")]
+pub mod qmp6988;
+#[cfg(feature = "port-a")]
+pub mod sht3x;
+#[cfg(feature = "port-a")]
+pub mod unit;
+#[cfg(feature = "button")]
+pub mod button;
+#[cfg(feature = "button")]
+pub mod input;
+#[cfg(feature = "dial")]
+pub mod encoder;
@@ -1,24 +1,18 @@
 #![no_std]
 #![no_main]
+#![cfg_attr(feature = "alloc", feature(alloc_error_handler))]
 
-use core::{
-    cell::RefCell,
-    cmp::min,
-    f64::consts::PI,
-    sync::atomic::{AtomicI32, Ordering},
-};
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::f64::consts::PI;
 
 use esp32s3_hal::{
     clock::{ClockControl, CpuClock},
-    interrupt,
-    pcnt::{
-        channel::{self, PcntSource},
-        unit::{self, Unit},
-    },
-    peripherals::{self, Peripherals},
+    peripherals::Peripherals,
     prelude::*,
     spi::{Spi, SpiMode},
-    Delay, IO,
+    Delay, Rtc, IO,
 };
 use esp_backtrace as _;
 use esp_println::println;
@@ -29,9 +23,6 @@ use embedded_graphics::{
     Drawable,
 };
 
-#[cfg(feature = "dial")]
-use critical_section::Mutex;
-
 use display_interface_spi::SPIInterface;
 use embedded_graphics_core::{draw_target::DrawTarget, pixelcolor::Rgb565, pixelcolor::RgbColor};
 
@@ -47,24 +38,91 @@ use samjkent_gc9a01 as gc9a01;
 
 use gc9a01::*;
 
+#[cfg(feature = "i2c")]
+use m5stack_dial::bus;
+#[cfg(feature = "alloc")]
+use m5stack_dial::heap;
+use m5stack_dial::{power, time, ui};
+
+#[cfg(feature = "rtc")]
+use m5stack_dial::{bm8563, clock};
+#[cfg(feature = "buzzer")]
+use m5stack_dial::buzzer;
+#[cfg(feature = "feedback")]
+use m5stack_dial::feedback;
+#[cfg(any(feature = "buzzer", feature = "port-b"))]
+use esp32s3_hal::ledc::{LSGlobalClkSource, LEDC};
+#[cfg(feature = "touch")]
+use m5stack_dial::{calibration, ft3267, gesture, touch, transform};
+#[cfg(feature = "rfid")]
+use m5stack_dial::ws1850s;
+#[cfg(feature = "ndef")]
+use m5stack_dial::ndef;
+#[cfg(feature = "port-b")]
+use m5stack_dial::port_b;
+#[cfg(feature = "settings")]
+use m5stack_dial::settings;
+#[cfg(feature = "port-a")]
+use m5stack_dial::unit::{self, SensorUnit};
+#[cfg(any(feature = "port-a", feature = "alloc"))]
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    text::{Alignment, Text},
+};
+#[cfg(any(feature = "port-a", feature = "alloc"))]
+use core::fmt::Write as _;
+#[cfg(feature = "button")]
+use m5stack_dial::{button, input};
+#[cfg(feature = "button")]
+use esp32s3_hal::gpio::{Event, Pin};
 #[cfg(feature = "dial")]
-use esp32s3_hal::pcnt::PCNT;
+use m5stack_dial::encoder;
+#[cfg(all(feature = "dial", not(feature = "software-encoder")))]
+use esp32s3_hal::pcnt::{self, PCNT};
 
 use num_traits::real::Real;
 
-#[cfg(feature = "dial")]
-static UNIT0: Mutex<RefCell<Option<Unit>>> = Mutex::new(RefCell::new(None));
-#[cfg(feature = "dial")]
-static VALUE: AtomicI32 = AtomicI32::new(0);
+const DETENTS_PER_TURN: i32 = 32;
+
+/// How often the RFID reader looks for a card; each empty poll waits for
+/// the 25 ms reader timeout
+#[cfg(feature = "rfid")]
+const RFID_POLL_MS: u64 = 500;
+
+/// Interval between sensor unit readings
+#[cfg(feature = "port-a")]
+const UNIT_READ_MS: u64 = 2000;
+
+#[cfg(feature = "buzzer")]
+const STARTUP_MELODY: &str = "startup:d=16,o=6,b=180:c,e,g,8c7";
+
+/// Settings store region: the `settings` partition of `partitions.csv`, six
+/// 4 KiB sectors
+#[cfg(feature = "settings")]
+const SETTINGS_OFFSET: u32 = 0x40_0000;
+#[cfg(feature = "settings")]
+const SETTINGS_SECTORS: u32 = 6;
+/// The UI state is saved once the knob rested this long
+#[cfg(feature = "settings")]
+const SAVE_QUIET_MS: u64 = 2000;
+/// ... or at the latest after this long while it keeps turning
+#[cfg(feature = "settings")]
+const SAVE_MAX_DELAY_MS: u64 = 30_000;
 
 #[entry]
 fn main() -> ! {
     let peripherals = Peripherals::take();
+    #[cfg(feature = "alloc")]
+    heap::init();
+    #[cfg(feature = "psram")]
+    heap::init_psram(peripherals.PSRAM);
     let mut system = peripherals.SYSTEM.split();
     let clocks = ClockControl::configure(system.clock_control, CpuClock::Clock240MHz).freeze();
 
     let mut delay = Delay::new(&clocks);
     let io = IO::new(peripherals.GPIO, peripherals.IO_MUX);
+    // Before anything that can take a while, like showing an error report
+    let mut hold = power::PowerHold::new(io.pins.gpio46.into_push_pull_output());
 
     let sclk = io.pins.gpio6;
     let mosi = io.pins.gpio5;
@@ -113,75 +171,223 @@ fn main() -> ! {
     #[cfg(feature = "samjkent-gc9a01")]
     display.setup();
 
-    #[cfg(feature = "dial")]
-    {
-        let mut mtdo = io.pins.gpio40.into_pull_up_input();
-        let mut mtdi = io.pins.gpio41.into_pull_up_input();
-        let pcnt = PCNT::new(peripherals.PCNT, &mut system.peripheral_clock_control);
-        let mut u0: unit::Unit = pcnt.get_unit(unit::Number::Unit1);
-        u0.configure(unit::Config {
-            low_limit: -100,
-            high_limit: 100,
-            filter: Some(min(10u16 * 80, 1023u16)),
-            ..Default::default()
-        })
-        .unwrap();
-        let mut ch0 = u0.get_channel(channel::Number::Channel0);
-        ch0.configure(
-            PcntSource::from_pin(&mut mtdi),
-            PcntSource::from_pin(&mut mtdo),
-            channel::Config {
-                lctrl_mode: channel::CtrlMode::Reverse,
-                hctrl_mode: channel::CtrlMode::Keep,
-                pos_edge: channel::EdgeMode::Decrement,
-                neg_edge: channel::EdgeMode::Increment,
-                invert_ctrl: false,
-                invert_sig: false,
-            },
-        );
-        let mut ch1 = u0.get_channel(channel::Number::Channel1);
-        ch1.configure(
-            PcntSource::from_pin(&mut mtdo),
-            PcntSource::from_pin(&mut mtdi),
-            channel::Config {
-                lctrl_mode: channel::CtrlMode::Reverse,
-                hctrl_mode: channel::CtrlMode::Keep,
-                pos_edge: channel::EdgeMode::Increment,
-                neg_edge: channel::EdgeMode::Decrement,
-                invert_ctrl: false,
-                invert_sig: false,
-            },
-        );
-        u0.events(unit::Events {
-            low_limit: true,
-            high_limit: true,
-            thresh0: false,
-            thresh1: false,
-            zero: false,
-        });
-        u0.listen();
-        u0.resume();
+    #[cfg(feature = "alloc")]
+    if let Some(report) = heap::take_report() {
+        println!("heap: reset after running out of memory: {report:?}");
+        draw_out_of_memory(&mut display, &report);
+        delay.delay_ms(5000u32);
+    }
 
-        critical_section::with(|cs| UNIT0.borrow_ref_mut(cs).replace(u0));
+    let mut rtc = Rtc::new(peripherals.RTC_CNTL);
+    let mut idle = power::Idle::new(power::Config::default(), time::now_ms());
 
-        interrupt::enable(peripherals::Interrupt::PCNT, interrupt::Priority::Priority2).unwrap();
-    }
+    #[cfg(all(feature = "dial", not(feature = "software-encoder")))]
+    let mut mtdo = io.pins.gpio40.into_pull_up_input();
+    #[cfg(all(feature = "dial", not(feature = "software-encoder")))]
+    let mut mtdi = io.pins.gpio41.into_pull_up_input();
+    #[cfg(all(feature = "dial", not(feature = "software-encoder")))]
+    let mut encoder = {
+        let pcnt = PCNT::new(peripherals.PCNT, &mut system.peripheral_clock_control);
+        let config = encoder::Config::default();
+        let counter = encoder::Pcnt::new(
+            pcnt.get_unit(pcnt::unit::Number::Unit1),
+            &mut mtdo,
+            &mut mtdi,
+            &config,
+        );
+        encoder::Encoder::new(counter, config)
+    };
+
+    #[cfg(feature = "software-encoder")]
+    let mut encoder = {
+        let mtdo = io.pins.gpio40.into_pull_up_input();
+        let mtdi = io.pins.gpio41.into_pull_up_input();
+        let config = encoder::Config::default();
+        let counter = encoder::Software::new(mtdo, mtdi, &config);
+        encoder::Encoder::new(counter, config)
+    };
+    // Fast spins move the indicator up to four detents per click
+    #[cfg(feature = "dial")]
+    let mut accel = encoder::Acceleration::new(encoder::Curve::Linear {
+        gain: 0.1,
+        max: 4.0,
+    });
 
     #[cfg(feature = "button")]
-    let mtms = io.pins.gpio42.into_pull_up_input();
+    let mut mtms = io.pins.gpio42.into_pull_up_input();
+    #[cfg(feature = "button")]
+    let mut input = input::Input::new(button::Config::default());
 
     #[cfg(feature = "i2c")]
-    let i2c = esp32s3_hal::i2c::I2C::new(
+    let i2c = bus::I2cBus::new(esp32s3_hal::i2c::I2C::new(
         peripherals.I2C0,
-        io.pins.gpio11, // tp sda
-        io.pins.gpio12, // tp scl
+        io.pins.gpio11, // internal sda
+        io.pins.gpio12, // internal scl
         esp32s3_hal::prelude::_fugit_RateExtU32::kHz(400),
         &mut system.peripheral_clock_control,
         &clocks,
+    ));
+
+    #[cfg(feature = "port-a")]
+    let port_a = bus::I2cBus::new(esp32s3_hal::i2c::I2C::new(
+        peripherals.I2C1,
+        io.pins.gpio13, // port a sda
+        io.pins.gpio15, // port a scl
+        esp32s3_hal::prelude::_fugit_RateExtU32::kHz(100),
+        &mut system.peripheral_clock_control,
+        &clocks,
+    ));
+    #[cfg(feature = "port-a")]
+    let mut env = {
+        let found = bus::scan(&mut port_a.device());
+        println!("port a: {:02x?}", found.as_slice());
+        if unit::is_present(unit::ENV_ADDRESSES, &found) {
+            match unit::Env::new(port_a.device(), port_a.device()) {
+                Ok(env) => Some(env),
+                Err(e) => {
+                    println!("env: {e:?}");
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    };
+    #[cfg(feature = "port-a")]
+    let mut readings = heapless::Vec::<unit::Reading, { unit::MAX_READINGS }>::new();
+    #[cfg(feature = "port-a")]
+    let mut last_unit_read = 0;
+
+    #[cfg(feature = "settings")]
+    let mut settings = {
+        let flash = esp_storage::FlashStorage::new();
+        match settings::Store::new(flash, SETTINGS_OFFSET, SETTINGS_SECTORS) {
+            Ok(store) => Some(store),
+            Err(e) => {
+                println!("settings: {e:?}");
+                None
+            }
+        }
+    };
+
+    #[cfg(feature = "touch")]
+    let mut touch = touch::Touch::new(
+        ft3267::FT3267::new(i2c.device()),
+        io.pins.gpio14.into_pull_up_input(), // tp int
+    );
+    #[cfg(feature = "touch")]
+    if let Ok(info) = touch.controller().info() {
+        println!("touch: {info:?}");
+    }
+    #[cfg(feature = "touch")]
+    {
+        // Holding the button during boot starts the touch calibration
+        #[cfg(feature = "button")]
+        let recalibrate = mtms.is_low().unwrap();
+        #[cfg(not(feature = "button"))]
+        let recalibrate = false;
+
+        let mut affine = None;
+        if recalibrate {
+            match calibration::run(&mut touch, &mut display, &mut delay) {
+                Ok(Some(fitted)) => {
+                    #[cfg(feature = "settings")]
+                    if let Some(Err(e)) = settings.as_mut().map(|s| s.set(&fitted)) {
+                        println!("calibration: {e:?}");
+                    }
+                    affine = Some(fitted);
+                }
+                Ok(None) => println!("calibration: failed"),
+                Err(e) => println!("calibration: {e:?}"),
+            }
+        }
+        #[cfg(feature = "settings")]
+        if affine.is_none() {
+            affine = settings.as_mut().and_then(|s| s.get().ok().flatten());
+        }
+        touch.set_transform(transform::TouchTransform {
+            calibration: affine.unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+    #[cfg(feature = "rtc")]
+    let mut clock = match clock::Clock::new(bm8563::BM8563::new(i2c.device()), time::now_ms()) {
+        Ok(mut clock) => {
+            // Release INT after an alarm wake so the power switch works again
+            if let Err(e) = clock.rtc().disable_alarm() {
+                println!("rtc: {e:?}");
+            }
+            if clock.is_valid() {
+                println!("rtc: {:?}", clock.now(time::now_ms()));
+            } else {
+                println!("rtc: time not set");
+            }
+            Some(clock)
+        }
+        Err(e) => {
+            println!("rtc: {e:?}");
+            None
+        }
+    };
+
+    #[cfg(feature = "rfid")]
+    let mut rfid = ws1850s::WS1850S::new(i2c.device());
+    #[cfg(feature = "rfid")]
+    match rfid.init().and_then(|_| rfid.version()) {
+        Ok(version) => println!("rfid: version {version:#04x}"),
+        Err(e) => println!("rfid: {e:?}"),
+    }
+    #[cfg(feature = "rfid")]
+    let mut last_rfid_poll = 0;
+
+    #[cfg(any(feature = "buzzer", feature = "port-b"))]
+    let mut ledc = LEDC::new(
+        peripherals.LEDC,
+        &clocks,
+        &mut system.peripheral_clock_control,
+    );
+    #[cfg(any(feature = "buzzer", feature = "port-b"))]
+    ledc.set_global_slow_clock(LSGlobalClkSource::APBClk);
+    #[cfg(feature = "buzzer")]
+    let buzzer_timer = buzzer::configure_timer(&ledc);
+    #[cfg(feature = "buzzer")]
+    let mut buzzer =
+        buzzer::Buzzer::new(&ledc, &buzzer_timer, io.pins.gpio3.into_push_pull_output());
+    #[cfg(feature = "buzzer")]
+    match buzzer::Rtttl::parse(STARTUP_MELODY) {
+        Ok(melody) => buzzer.play(buzzer::Melody::Rtttl(melody), time::now_ms()),
+        Err(e) => println!("buzzer: {e:?}"),
+    }
+
+    #[cfg(feature = "port-b")]
+    let port_b_config = {
+        #[cfg(feature = "settings")]
+        let stored: Option<port_b::Config> =
+            settings.as_mut().and_then(|s| s.get().ok().flatten());
+        #[cfg(not(feature = "settings"))]
+        let stored: Option<port_b::Config> = None;
+        stored.unwrap_or_default()
+    };
+    #[cfg(feature = "port-b")]
+    let port_b_timer = port_b::configure_timer(&ledc, port_b_config.pwm_frequency);
+    #[cfg(feature = "port-b")]
+    let mut port_b = port_b::PortB::new(
+        port_b::DialPins::new(
+            io.pins.gpio1,
+            io.pins.gpio2,
+            peripherals.SENS.split().adc1,
+            &ledc,
+            &port_b_timer,
+        ),
+        port_b_config,
+        time::now_ms(),
     );
 
+    #[cfg(feature = "feedback")]
+    let mut feedback = feedback::Feedback::new(feedback::Config::default());
+
     #[cfg(feature = "touch")]
-    let mut touch = ft3267::FT3267::new(i2c);
+    let mut gestures = gesture::Gestures::new(gesture::Config::default());
 
     let dial_button_style = PrimitiveStyleBuilder::new()
         .stroke_width(4)
@@ -194,48 +400,196 @@ fn main() -> ! {
         .fill_color(embedded_graphics::prelude::RgbColor::RED)
         .build();
 
+    // The store also covers a brownout, RTC memory only deep sleep
+    #[cfg(feature = "settings")]
+    let stored: Option<ui::State> = settings.as_mut().and_then(|s| s.get().ok().flatten());
+    #[cfg(not(feature = "settings"))]
+    let stored: Option<ui::State> = None;
+    let mut state = [0; ui::State::LEN];
+    let restored = stored.or_else(|| {
+        (power::restore_state(&mut state) == Some(state.len()))
+            .then(|| ui::State::from_bytes(&state))
+    });
+    if let Some(restored) = restored {
+        println!("restored: {restored:?}");
+    }
+    let mut last_state = restored.unwrap_or_default();
+    let mut position = last_state.position;
+    let mut screen = last_state.screen;
+    #[cfg(feature = "settings")]
+    let mut save = settings::Debounce::new(SAVE_QUIET_MS, SAVE_MAX_DELAY_MS);
     let mut last_value = 0;
     let mut last_pressed = false;
     let mut last_touch: [Option<(u16, u16)>; 2] = [None, None];
 
+    #[cfg(feature = "alloc")]
+    println!("heap: {}", heap::stats());
+
     let mut first = true;
     loop {
         let mut changed = first;
         first = false;
 
+        #[cfg(feature = "buzzer")]
+        buzzer.update(time::now_ms());
+
         #[cfg(feature = "touch")]
         {
-            let t = touch.touch();
+            let t = match touch.touch() {
+                Ok(report) => {
+                    for event in gestures.update(&report, time::now_ms()) {
+                        println!("gesture: {event:?}");
+                        #[cfg(feature = "feedback")]
+                        if let Some(note) = feedback.gesture(&event, time::now_ms()) {
+                            buzzer.play(buzzer::Melody::Tone(note), time::now_ms());
+                        }
+                        match event {
+                            // A drag along the rim turns the dial like the knob does
+                            gesture::Event::Rotate { delta } => position += delta,
+                            gesture::Event::Swipe { direction, .. } => match direction {
+                                gesture::Direction::Left => screen = screen.next(),
+                                gesture::Direction::Right => screen = screen.previous(),
+                                _ => {}
+                            },
+                            _ => {}
+                        }
+                    }
+                    report.positions()
+                }
+                Err(e) => {
+                    println!("touch: {e:?}");
+                    last_touch
+                }
+            };
             if t != last_touch {
                 last_touch = t;
                 changed = true;
             }
         }
-        #[cfg(feature = "dial")]
-        critical_section::with(|cs| {
-            let mut u0 = UNIT0.borrow_ref_mut(cs);
-            let u0 = u0.as_mut().unwrap();
-            let mut value: i32 = u0.get_value() as i32 + VALUE.load(Ordering::SeqCst);
-            // while value < 0 {
-            //     value += 64;
-            // }
-            value %= 128;
-            value = value * 360 / 128;
-            if value != last_value {
-                println!("value: {value}");
-                last_value = value;
+        #[cfg(feature = "rfid")]
+        if time::now_ms() - last_rfid_poll >= RFID_POLL_MS {
+            last_rfid_poll = time::now_ms();
+            match rfid.read_card() {
+                Ok(Some(card)) => {
+                    println!("rfid: {:?} {:02x?}", card.card_type(), card.uid.as_bytes());
+                    #[cfg(feature = "ndef")]
+                    if card.card_type() == ws1850s::CardType::Ultralight {
+                        let mut buf = [0; 256];
+                        match ndef::ntag::read(&mut rfid, &mut buf) {
+                            Ok(message) => {
+                                for record in ndef::records(message) {
+                                    match record.and_then(|record| record.content()) {
+                                        Ok(content) => println!("ndef: {content:?}"),
+                                        Err(e) => println!("ndef: {e:?}"),
+                                    }
+                                }
+                            }
+                            Err(e) => println!("ndef: {e:?}"),
+                        }
+                    }
+                    // Halted cards stay quiet until they leave the field
+                    rfid.halt().ok();
+                    changed = true;
+                }
+                Ok(None) => {}
+                Err(e) => println!("rfid: {e:?}"),
+            }
+        }
+        #[cfg(feature = "port-a")]
+        if let Some(env) = env.as_mut() {
+            if time::now_ms() - last_unit_read >= UNIT_READ_MS {
+                last_unit_read = time::now_ms();
+                match env.read(&mut delay) {
+                    Ok(r) => {
+                        for reading in &r {
+                            let quantity = reading.quantity;
+                            println!("{}: {} {}", quantity.name(), reading.value, quantity.unit());
+                        }
+                        readings = r;
+                        changed = true;
+                    }
+                    Err(e) => println!("{}: {e:?}", env.name()),
+                }
+            }
+        }
+        #[cfg(feature = "port-b")]
+        for event in port_b.poll(time::now_ms()) {
+            println!("port b: {event:?}");
+            // External switches count as activity
+            if let port_b::Event::Input { .. } = event {
                 changed = true;
             }
-        });
+        }
+        #[cfg(feature = "dial")]
+        let delta = encoder.poll();
+        #[cfg(not(feature = "dial"))]
+        let delta = 0;
+        // Turning while the button is held is a PressedRotate and leaves the
+        // dial value alone
+        #[cfg(not(feature = "button"))]
+        let rotate = delta;
+        #[cfg(all(feature = "feedback", not(feature = "button")))]
+        if let Some(note) = feedback.detents(delta, time::now_ms()) {
+            buzzer.play(buzzer::Melody::Tone(note), time::now_ms());
+        }
+        #[cfg(feature = "button")]
+        let mut rotate = 0;
         #[cfg(feature = "button")]
         {
-            let pressed = mtms.is_low().unwrap();
+            for event in input.update(mtms.is_low().unwrap(), delta, time::now_ms()) {
+                println!("input: {event:?}");
+                if let input::Event::Rotate { delta } = event {
+                    rotate += delta;
+                }
+                #[cfg(feature = "port-b")]
+                if port_b.input(&event) {
+                    println!("port b: {:?}", port_b.config().modes);
+                    #[cfg(feature = "settings")]
+                    save.changed(time::now_ms());
+                }
+                #[cfg(feature = "feedback")]
+                if let Some(note) = feedback.input(&event, time::now_ms()) {
+                    buzzer.play(buzzer::Melody::Tone(note), time::now_ms());
+                }
+            }
+            let pressed = input.is_pressed();
             if pressed != last_pressed {
                 last_pressed = pressed;
                 changed = true;
             }
             // println!("button: {}", mtms.is_low().unwrap());
         }
+        #[cfg(feature = "dial")]
+        let step = accel.apply(rotate, time::now_ms());
+        #[cfg(not(feature = "dial"))]
+        let step = rotate;
+        position += step;
+        let value = position.rem_euclid(DETENTS_PER_TURN) * 360 / DETENTS_PER_TURN;
+        if value != last_value {
+            println!("value: {value}");
+            last_value = value;
+            changed = true;
+        }
+
+        let current = ui::State { position, screen };
+        if current != last_state {
+            if current.screen != last_state.screen {
+                println!("screen: {screen:?}");
+                changed = true;
+            }
+            last_state = current;
+            #[cfg(feature = "settings")]
+            save.changed(time::now_ms());
+        }
+        #[cfg(feature = "settings")]
+        if save.poll(time::now_ms()) {
+            save_settings(
+                &mut settings,
+                &last_state,
+                #[cfg(feature = "port-b")]
+                port_b.config(),
+            );
+        }
 
         if changed {
             Rectangle::new(Point::new(0, 0), Size::new(240, 240))
@@ -254,10 +608,25 @@ fn main() -> ! {
             let angle = <i32 as Into<f64>>::into(last_value) * PI / 180.0 * 2.0;
             let x = 120 as f64 + angle.cos() * 100 as f64 - diameter as f64 / 2.0;
             let y = 120 as f64 + angle.sin() * 100 as f64 - diameter as f64 / 2.0;
-            Circle::new(Point::new(x as i32, y as i32), diameter as u32)
-                .into_styled(dial_button_style)
-                .draw(&mut display)
-                .unwrap();
+            if screen == ui::Screen::Dial {
+                Circle::new(Point::new(x as i32, y as i32), diameter as u32)
+                    .into_styled(dial_button_style)
+                    .draw(&mut display)
+                    .unwrap();
+            }
+
+            #[cfg(feature = "port-a")]
+            if screen == ui::Screen::Sensors {
+                let text_style = MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE);
+                for (i, reading) in readings.iter().enumerate() {
+                    let mut line: heapless::String<24> = heapless::String::new();
+                    let _ = write!(line, "{:.1} {}", reading.value, reading.quantity.unit());
+                    let position = Point::new(120, 100 + 22 * i as i32);
+                    Text::with_alignment(&line, position, text_style, Alignment::Center)
+                        .draw(&mut display)
+                        .unwrap();
+                }
+            }
 
             if let Some((x1, y1)) = last_touch[0] {
                 Circle::with_center(Point::new(x1 as i32, y1 as i32), 60 as u32)
@@ -273,84 +642,134 @@ fn main() -> ! {
             }
         }
 
+        if changed {
+            idle.activity(time::now_ms());
+        }
+        let mut stage = idle.stage(time::now_ms());
+        if stage == power::Stage::LightSleep {
+            println!("light sleep");
+            #[cfg(feature = "buzzer")]
+            buzzer.stop();
+            #[cfg(feature = "settings")]
+            if save.flush() {
+                save_settings(
+                    &mut settings,
+                    &last_state,
+                    #[cfg(feature = "port-b")]
+                    port_b.config(),
+                );
+            }
+            #[cfg(all(feature = "dial", not(feature = "software-encoder")))]
+            encoder::listen_wakeup(&mut mtdo, &mut mtdi);
+            #[cfg(feature = "software-encoder")]
+            encoder.counter_mut().listen_wakeup();
+            #[cfg(feature = "button")]
+            mtms.listen_with_options(Event::LowLevel, false, false, true);
+            #[cfg(feature = "touch")]
+            touch.listen_wakeup();
+            #[cfg(feature = "rfid")]
+            rfid.set_antenna(false).ok();
+
+            let timeout = idle.until_power_off(time::now_ms());
+            let timed_out = power::light_sleep(&mut rtc, &mut delay, timeout);
+
+            #[cfg(all(feature = "dial", not(feature = "software-encoder")))]
+            encoder::unlisten_wakeup(&mut mtdo, &mut mtdi);
+            #[cfg(feature = "software-encoder")]
+            encoder.counter_mut().unlisten_wakeup();
+            #[cfg(feature = "button")]
+            mtms.unlisten();
+            #[cfg(feature = "touch")]
+            touch.unlisten_wakeup();
+            #[cfg(feature = "rfid")]
+            rfid.set_antenna(true).ok();
+
+            if timed_out {
+                stage = power::Stage::PowerOff;
+            } else {
+                idle.activity(time::now_ms());
+            }
+        }
+        if stage == power::Stage::PowerOff {
+            println!("power off");
+            #[cfg(feature = "buzzer")]
+            buzzer.stop();
+            power::save_state(&last_state.to_bytes());
+            #[cfg(feature = "settings")]
+            if save.flush() {
+                save_settings(
+                    &mut settings,
+                    &last_state,
+                    #[cfg(feature = "port-b")]
+                    port_b.config(),
+                );
+            }
+            #[allow(unused_mut)]
+            let mut wake_after = idle.wake_after();
+            #[cfg(feature = "rtc")]
+            if let Some(clock) = clock.as_mut() {
+                match clock.set_wake_alarm(wake_after) {
+                    Ok(until) => wake_after = until,
+                    Err(e) => println!("rtc: {e:?}"),
+                }
+            }
+            power::power_off(&mut hold, &mut rtc, &mut delay, wake_after);
+        }
+
         // delay.delay_ms(500u32);
         delay.delay_ms(32u32); // 30fps
     }
 }
 
-#[cfg(feature = "dial")]
-#[interrupt]
-fn PCNT() {
-    critical_section::with(|cs| {
-        let mut u0 = UNIT0.borrow_ref_mut(cs);
-        let u0 = u0.as_mut().unwrap();
-        if u0.interrupt_set() {
-            let events = u0.get_events();
-            if events.high_limit {
-                VALUE.fetch_add(100, Ordering::SeqCst);
-            } else if events.low_limit {
-                VALUE.fetch_add(-100, Ordering::SeqCst);
-            }
-            println!("VALUE: {}", VALUE.load(Ordering::SeqCst));
-            u0.reset_interrupt();
-        }
-    });
+#[cfg(feature = "alloc")]
+#[alloc_error_handler]
+fn alloc_error(layout: core::alloc::Layout) -> ! {
+    heap::out_of_memory(layout)
 }
 
-#[cfg(feature = "touch")]
-mod ft3267 {
-    use embedded_hal::blocking::i2c::WriteRead;
-
-    pub struct FT3267<I2C> {
-        i2c: I2C,
-        address: u8,
-    }
-
-    impl<I2C, E> FT3267<I2C>
-    where
-        I2C: WriteRead<Error = E>,
+#[cfg(feature = "alloc")]
+fn draw_out_of_memory<D: DrawTarget<Color = Rgb565>>(display: &mut D, report: &heap::OutOfMemory) {
+    let text_style = MonoTextStyle::new(&FONT_10X20, Rgb565::WHITE);
+    display.clear(Rgb565::RED).ok();
+    let mut size: heapless::String<24> = heapless::String::new();
+    let _ = write!(size, "{} bytes", report.size);
+    let mut used: heapless::String<24> = heapless::String::new();
+    let _ = write!(used, "{}", report.stats.internal);
+    for (i, line) in ["out of memory", size.as_str(), used.as_str()]
+        .iter()
+        .enumerate()
     {
-        pub fn new(i2c: I2C) -> Self {
-            Self { i2c, address: 0x38 }
-        }
-
-        pub fn touch(&mut self) -> [Option<(u16, u16)>; 2] {
-            const FT_TP_STATUS: usize = 0x02;
-            const FT_TP1_XH: usize = 0x03;
-            const FT_TP1_XL: usize = 0x04;
-            const FT_TP1_YH: usize = 0x05;
-            const FT_TP1_YL: usize = 0x06;
-            const FT_TP2_XH: usize = 0x09;
-            const FT_TP2_XL: usize = 0x0a;
-            const FT_TP2_YH: usize = 0x0b;
-            const FT_TP2_YL: usize = 0x0c;
-
-            let mut data: [u8; 13] = [0; 13];
-            for i in 0..13 {
-                if let Ok(d) = self.read(i) {
-                    data[i as usize] = d;
-                }
-            }
-            let count = data[FT_TP_STATUS];
-            let mut points: [Option<(u16, u16)>; 2] = [None, None];
-            if count > 0 {
-                let x1 = ((data[FT_TP1_XH] as u16 & 0x0F) << 8) | (data[FT_TP1_XL] as u16);
-                let y1 = ((data[FT_TP1_YH] as u16 & 0x0F) << 8) | (data[FT_TP1_YL] as u16);
-                points[0] = Some((y1, 240 - x1));
-            }
-            if count > 1 {
-                let x2 = ((data[FT_TP2_XH] as u16 & 0x0F) << 8) | (data[FT_TP2_XL] as u16);
-                let y2 = ((data[FT_TP2_YH] as u16 & 0x0F) << 8) | (data[FT_TP2_YL] as u16);
-                points[1] = Some((y2, 240 - x2));
-            }
-            points
-        }
+        let position = Point::new(120, 100 + 22 * i as i32);
+        Text::with_alignment(line, position, text_style, Alignment::Center)
+            .draw(display)
+            .ok();
+    }
+}
 
-        fn read(&mut self, register: u8) -> Result<u8, E> {
-            let mut data = [0];
-            self.i2c
-                .write_read(self.address, &[register], &mut data)
-                .map(|_| data[0])
-        }
+/// Writes the values that change at runtime; unchanged ones are skipped by
+/// the store.
+#[cfg(feature = "settings")]
+fn save_settings(
+    settings: &mut Option<settings::Store<esp_storage::FlashStorage>>,
+    state: &ui::State,
+    #[cfg(feature = "port-b")] port_b: &port_b::Config,
+) {
+    let Some(store) = settings.as_mut() else {
+        return;
+    };
+    let result = store.set(state);
+    #[cfg(feature = "port-b")]
+    let result = result.and_then(|_| store.set(port_b));
+    if let Err(e) = result {
+        println!("settings: {e:?}");
     }
 }
+
+#[cfg(any(feature = "software-encoder", feature = "touch"))]
+#[interrupt]
+fn GPIO() {
+    #[cfg(feature = "software-encoder")]
+    encoder::Software::on_interrupt();
+    #[cfg(feature = "touch")]
+    touch::on_interrupt();
+}
@@ -0,0 +1,491 @@
+//! NDEF messages
+//!
+//! Decodes and encodes NFC Data Exchange Format records without allocating:
+//! decoded records borrow from the tag dump, encoded messages are written
+//! into a caller supplied buffer. URI, text and MIME records have typed
+//! accessors, everything else is passed through as a raw [`Record`].
+//!
+//! ```ignore
+//! for record in ndef::records(message) {
+//!     match record?.content()? {
+//!         Content::Uri(uri) => println!("{uri}"),
+//!         Content::Text { text, .. } => println!("{text}"),
+//!         _ => {}
+//!     }
+//! }
+//!
+//! let mut writer = ndef::Writer::new(&mut buf);
+//! writer.push(&Content::Uri(Uri::new("https://m5stack.com")))?;
+//! let message = writer.finish()?;
+//! ```
+#[cfg(feature = "rfid")]
+pub mod ntag;
+pub mod tlv;
+
+use core::fmt;
+
+const MB: u8 = 1 << 7;
+const ME: u8 = 1 << 6;
+/// Chunk flag
+const CF: u8 = 1 << 5;
+/// Short record, one byte payload length
+const SR: u8 = 1 << 4;
+/// ID length present
+const IL: u8 = 1 << 3;
+const TNF_MASK: u8 = 0x07;
+
+/// Text record status bit for UTF-16 text
+const UTF16: u8 = 1 << 7;
+const LANGUAGE_MASK: u8 = 0x3F;
+
+/// URI identifier codes 0x00 to 0x23
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The data ends inside a TLV or record
+    Truncated,
+    /// A TLV block is malformed or there is no NDEF message TLV
+    InvalidTlv,
+    /// A record header or payload is malformed
+    InvalidRecord,
+    /// Chunked records and UTF-16 text are not supported
+    Unsupported,
+    /// The output buffer is full
+    BufferTooSmall,
+}
+
+/// Type name format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tnf {
+    Empty = 0,
+    WellKnown = 1,
+    Media = 2,
+    AbsoluteUri = 3,
+    External = 4,
+    Unknown = 5,
+    Unchanged = 6,
+    Reserved = 7,
+}
+
+impl Tnf {
+    fn from_bits(bits: u8) -> Self {
+        match bits & TNF_MASK {
+            0 => Tnf::Empty,
+            1 => Tnf::WellKnown,
+            2 => Tnf::Media,
+            3 => Tnf::AbsoluteUri,
+            4 => Tnf::External,
+            5 => Tnf::Unknown,
+            6 => Tnf::Unchanged,
+            _ => Tnf::Reserved,
+        }
+    }
+}
+
+/// A raw record borrowing from the message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub tnf: Tnf,
+    pub record_type: &'a [u8],
+    pub id: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+/// URI split into its abbreviated prefix and the rest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Uri<'a> {
+    pub prefix: &'static str,
+    pub rest: &'a str,
+}
+
+impl<'a> Uri<'a> {
+    /// Splits off the longest prefix that has an identifier code.
+    pub fn new(uri: &'a str) -> Self {
+        let prefix = URI_PREFIXES
+            .iter()
+            .filter(|prefix| uri.starts_with(*prefix))
+            .max_by_key(|prefix| prefix.len())
+            .unwrap_or(&"");
+        Self {
+            prefix,
+            rest: &uri[prefix.len()..],
+        }
+    }
+
+    fn code(&self) -> u8 {
+        URI_PREFIXES
+            .iter()
+            .position(|prefix| *prefix == self.prefix)
+            .unwrap_or(0) as u8
+    }
+}
+
+impl fmt::Display for Uri<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.prefix, self.rest)
+    }
+}
+
+/// Typed view of a record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Content<'a> {
+    Uri(Uri<'a>),
+    /// UTF-8 text with an IANA language code such as `en`
+    Text {
+        language: &'a str,
+        text: &'a str,
+    },
+    Mime {
+        mime_type: &'a str,
+        data: &'a [u8],
+    },
+    Other(Record<'a>),
+}
+
+impl<'a> Record<'a> {
+    pub fn content(&self) -> Result<Content<'a>, Error> {
+        let utf8 = |bytes| core::str::from_utf8(bytes).map_err(|_| Error::InvalidRecord);
+        match (self.tnf, self.record_type) {
+            (Tnf::WellKnown, b"U") => {
+                let (&code, rest) = self.payload.split_first().ok_or(Error::InvalidRecord)?;
+                Ok(Content::Uri(Uri {
+                    // Reserved codes are treated as no prefix
+                    prefix: URI_PREFIXES.get(code as usize).unwrap_or(&""),
+                    rest: utf8(rest)?,
+                }))
+            }
+            (Tnf::WellKnown, b"T") => {
+                let (&status, rest) = self.payload.split_first().ok_or(Error::InvalidRecord)?;
+                if status & UTF16 != 0 {
+                    return Err(Error::Unsupported);
+                }
+                let len = (status & LANGUAGE_MASK) as usize;
+                if len > rest.len() {
+                    return Err(Error::InvalidRecord);
+                }
+                let (language, text) = rest.split_at(len);
+                Ok(Content::Text {
+                    language: utf8(language)?,
+                    text: utf8(text)?,
+                })
+            }
+            (Tnf::Media, mime_type) => Ok(Content::Mime {
+                mime_type: utf8(mime_type)?,
+                data: self.payload,
+            }),
+            _ => Ok(Content::Other(*self)),
+        }
+    }
+}
+
+/// Iterates over the records of an NDEF message.
+pub fn records(message: &[u8]) -> Records<'_> {
+    Records {
+        data: message,
+        done: message.is_empty(),
+    }
+}
+
+pub struct Records<'a> {
+    data: &'a [u8],
+    done: bool,
+}
+
+impl<'a> Records<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.data.len() {
+            return Err(Error::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn parse(&mut self) -> Result<Record<'a>, Error> {
+        let head = self.take(2)?;
+        let (header, type_len) = (head[0], head[1] as usize);
+        if header & CF != 0 {
+            return Err(Error::Unsupported);
+        }
+        let payload_len = if header & SR != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            let len = self.take(4)?;
+            u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+        };
+        let id_len = if header & IL != 0 {
+            self.take(1)?[0] as usize
+        } else {
+            0
+        };
+        let record = Record {
+            tnf: Tnf::from_bits(header),
+            record_type: self.take(type_len)?,
+            id: self.take(id_len)?,
+            payload: self.take(payload_len)?,
+        };
+        if header & ME != 0 {
+            self.done = true;
+        }
+        Ok(record)
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.parse();
+        if record.is_err() {
+            self.done = true;
+        }
+        Some(record)
+    }
+}
+
+/// Encodes a message into a buffer
+pub struct Writer<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+    /// Offset of the last record header, which gets the ME flag
+    last: Option<usize>,
+}
+
+impl<'b> Writer<'b> {
+    pub fn new(buf: &'b mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            last: None,
+        }
+    }
+
+    pub fn push(&mut self, content: &Content) -> Result<(), Error> {
+        match *content {
+            Content::Uri(uri) => self.push_parts(
+                Tnf::WellKnown,
+                b"U",
+                &[],
+                &[&[uri.code()], uri.rest.as_bytes()],
+            ),
+            Content::Text { language, text } => {
+                if language.len() > LANGUAGE_MASK as usize {
+                    return Err(Error::InvalidRecord);
+                }
+                self.push_parts(
+                    Tnf::WellKnown,
+                    b"T",
+                    &[],
+                    &[
+                        &[language.len() as u8],
+                        language.as_bytes(),
+                        text.as_bytes(),
+                    ],
+                )
+            }
+            Content::Mime { mime_type, data } => {
+                self.push_parts(Tnf::Media, mime_type.as_bytes(), &[], &[data])
+            }
+            Content::Other(record) => self.push_record(&record),
+        }
+    }
+
+    pub fn push_record(&mut self, record: &Record) -> Result<(), Error> {
+        self.push_parts(record.tnf, record.record_type, record.id, &[record.payload])
+    }
+
+    /// Marks the last record as message end and returns the message.
+    pub fn finish(self) -> Result<&'b [u8], Error> {
+        let last = self.last.ok_or(Error::InvalidRecord)?;
+        self.buf[last] |= ME;
+        Ok(&self.buf[..self.len])
+    }
+
+    fn push_parts(
+        &mut self,
+        tnf: Tnf,
+        record_type: &[u8],
+        id: &[u8],
+        payload: &[&[u8]],
+    ) -> Result<(), Error> {
+        if record_type.len() > u8::MAX as usize || id.len() > u8::MAX as usize {
+            return Err(Error::InvalidRecord);
+        }
+        let payload_len: usize = payload.iter().map(|part| part.len()).sum();
+        let short = payload_len <= u8::MAX as usize;
+
+        let start = self.len;
+        let length_len = if short { 1 } else { 4 };
+        let id_len_len = !id.is_empty() as usize;
+        let len = 2 + length_len + id_len_len + record_type.len() + id.len() + payload_len;
+        if start + len > self.buf.len() {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut header = tnf as u8;
+        if self.last.is_none() {
+            header |= MB;
+        }
+        if short {
+            header |= SR;
+        }
+        if !id.is_empty() {
+            header |= IL;
+        }
+        self.put(&[header, record_type.len() as u8]);
+        if short {
+            self.put(&[payload_len as u8]);
+        } else {
+            self.put(&(payload_len as u32).to_be_bytes());
+        }
+        if !id.is_empty() {
+            self.put(&[id.len() as u8]);
+        }
+        self.put(record_type);
+        self.put(id);
+        for part in payload {
+            self.put(part);
+        }
+        self.last = Some(start);
+        Ok(())
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        let end = self.len + bytes.len();
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// User memory of an NTAG213 holding a URI and a text record
+    pub(crate) const DUMP: [u8; 48] = [
+        0x03, 0x1E, 0x91, 0x01, 0x08, 0x55, 0x02, 0x6E, 0x78, 0x70, 0x2E, 0x63, 0x6F, 0x6D, 0x51,
+        0x01, 0x0E, 0x54, 0x02, 0x65, 0x6E, 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x44, 0x69,
+        0x61, 0x6C, 0xFE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00,
+    ];
+
+    fn contents(message: &[u8]) -> std::vec::Vec<Content<'_>> {
+        records(message)
+            .map(|record| record.unwrap().content().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn decode_dump() {
+        let message = tlv::find_ndef(&DUMP).unwrap();
+        let contents = contents(message);
+        assert_eq!(contents.len(), 2);
+        let Content::Uri(uri) = contents[0] else {
+            panic!("{:?}", contents[0]);
+        };
+        assert_eq!(std::format!("{uri}"), "https://www.nxp.com");
+        assert_eq!(
+            contents[1],
+            Content::Text {
+                language: "en",
+                text: "Hello, Dial"
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_message() {
+        let message = tlv::find_ndef(&DUMP).unwrap();
+        let mut records = records(&message[..message.len() - 1]);
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(records.next(), Some(Err(Error::Truncated)));
+        assert_eq!(records.next(), None);
+    }
+
+    #[test]
+    fn encode_matches_dump() {
+        let message = tlv::find_ndef(&DUMP).unwrap();
+        let mut buf = [0; 64];
+        let mut writer = Writer::new(&mut buf);
+        for content in contents(message) {
+            writer.push(&content).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), message);
+    }
+
+    #[test]
+    fn long_record() {
+        let data = [7; 300];
+        let mime = Content::Mime {
+            mime_type: "application/x-dial",
+            data: &data,
+        };
+        let mut buf = [0; 400];
+        let mut writer = Writer::new(&mut buf);
+        writer.push(&mime).unwrap();
+        let message = writer.finish().unwrap();
+        assert_eq!(message[0] & SR, 0);
+        assert_eq!(contents(message), [mime]);
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buf = [0; 10];
+        let mut writer = Writer::new(&mut buf);
+        let uri = Content::Uri(Uri::new("tel:123456789"));
+        assert_eq!(writer.push(&uri), Err(Error::BufferTooSmall));
+        assert_eq!(writer.finish(), Err(Error::InvalidRecord));
+    }
+
+    #[test]
+    fn uri_prefix() {
+        let uri = Uri::new("https://www.m5stack.com");
+        assert_eq!((uri.prefix, uri.rest), ("https://www.", "m5stack.com"));
+        assert_eq!(uri.code(), 2);
+        let uri = Uri::new("urn:epc:id:sgtin");
+        assert_eq!((uri.prefix, uri.code()), ("urn:epc:id:", 0x1E));
+        let uri = Uri::new("gopher://x");
+        assert_eq!((uri.prefix, uri.code()), ("", 0));
+    }
+}
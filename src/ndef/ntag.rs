@@ -0,0 +1,204 @@
+//! NDEF access to NTAG21x and other type 2 tags
+//!
+//! ```ignore
+//! let mut buf = [0; 256];
+//! let message = ntag::read(&mut rfid, &mut buf)?;
+//! ntag::write(&mut rfid, new_message)?;
+//! ```
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use super::tlv;
+use crate::ws1850s::{self, WS1850S};
+
+/// Capability container page
+const CC_PAGE: u8 = 3;
+/// First page of user memory
+const USER_PAGE: u8 = 4;
+/// Capability container magic for NDEF formatted tags
+const CC_MAGIC: u8 = 0xE1;
+/// Capability container write access bits, 0 grants access
+const CC_WRITE_ACCESS: u8 = 0x0F;
+const PAGE_SIZE: usize = 4;
+/// A read returns four pages
+const READ_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    Rfid(ws1850s::Error<E>),
+    Ndef(super::Error),
+    /// The capability container does not announce NDEF
+    NotFormatted,
+    /// The tag is write protected
+    ReadOnly,
+    /// The message does not fit the tag or the buffer
+    TooLarge,
+}
+
+impl<E> From<ws1850s::Error<E>> for Error<E> {
+    fn from(e: ws1850s::Error<E>) -> Self {
+        Error::Rfid(e)
+    }
+}
+
+impl<E> From<super::Error> for Error<E> {
+    fn from(e: super::Error) -> Self {
+        Error::Ndef(e)
+    }
+}
+
+/// Capability container of a selected tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capability {
+    pub version: u8,
+    /// User memory size in bytes
+    pub size: usize,
+    pub writable: bool,
+}
+
+pub fn capability<I2C, E>(rfid: &mut WS1850S<I2C>) -> Result<Capability, Error<E>>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    let cc = rfid.read_block(CC_PAGE)?;
+    if cc[0] != CC_MAGIC {
+        return Err(Error::NotFormatted);
+    }
+    Ok(Capability {
+        version: cc[1],
+        size: cc[2] as usize * 8,
+        writable: cc[3] & CC_WRITE_ACCESS == 0,
+    })
+}
+
+/// Reads the NDEF message of the selected tag into `buf`.
+pub fn read<'b, I2C, E>(rfid: &mut WS1850S<I2C>, buf: &'b mut [u8]) -> Result<&'b [u8], Error<E>>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    let size = capability(rfid)?.size.min(buf.len());
+    let mut len = 0;
+    loop {
+        // Stop as soon as the message TLV is complete
+        match tlv::ndef_range(&buf[..len]) {
+            Ok(range) => return Ok(&buf[range]),
+            Err(super::Error::Truncated) if len < size => {}
+            Err(super::Error::Truncated) => return Err(Error::TooLarge),
+            Err(e) => return Err(e.into()),
+        }
+        let page = USER_PAGE + (len / PAGE_SIZE) as u8;
+        let data = rfid.read_block(page)?;
+        let n = READ_SIZE.min(size - len);
+        buf[len..len + n].copy_from_slice(&data[..n]);
+        len += n;
+    }
+}
+
+/// Writes `message` as the only TLV of the selected tag.
+pub fn write<I2C, E>(rfid: &mut WS1850S<I2C>, message: &[u8]) -> Result<(), Error<E>>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    let capability = capability(rfid)?;
+    if !capability.writable {
+        return Err(Error::ReadOnly);
+    }
+    let (header, header_len) = tlv::header(message.len())?;
+    let len = header_len + message.len() + 1;
+    if len > capability.size {
+        return Err(Error::TooLarge);
+    }
+
+    let mut bytes = header[..header_len]
+        .iter()
+        .chain(message)
+        .chain(&[tlv::TERMINATOR])
+        .copied();
+    for page in 0..len.div_ceil(PAGE_SIZE) {
+        let mut data = [0; PAGE_SIZE];
+        for (byte, value) in data.iter_mut().zip(&mut bytes) {
+            *byte = value;
+        }
+        rfid.write_page(USER_PAGE + page as u8, &data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{vec, vec::Vec};
+
+    use super::*;
+    use crate::ndef::{tests::DUMP, Content, Writer};
+    use crate::ws1850s::tests::{Card, Reader};
+
+    /// An NTAG213 with 144 bytes of user memory holding [`DUMP`]
+    fn tag(access: u8) -> WS1850S<Reader> {
+        let mut card = Card::new(&[0x04, 1, 2, 3, 4, 5, 6], 0x00);
+        card.memory[12..16].copy_from_slice(&[CC_MAGIC, 0x10, 0x12, access]);
+        card.memory[16..64].copy_from_slice(&DUMP);
+        let mut rfid = WS1850S::new(Reader::new(vec![card]));
+        rfid.init().unwrap();
+        rfid.read_card().unwrap().unwrap();
+        rfid
+    }
+
+    #[test]
+    fn read_dump() {
+        let mut rfid = tag(0x00);
+        assert_eq!(
+            capability(&mut rfid).unwrap(),
+            Capability {
+                version: 0x10,
+                size: 144,
+                writable: true
+            }
+        );
+        let mut buf = [0; 256];
+        let message = read(&mut rfid, &mut buf).unwrap();
+        assert_eq!(message, tlv::find_ndef(&DUMP).unwrap());
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let mut rfid = tag(0x00);
+        let text: std::string::String = "x".repeat(120);
+        let mut buf = [0; 200];
+        let mut writer = Writer::new(&mut buf);
+        writer
+            .push(&Content::Text {
+                language: "de",
+                text: &text,
+            })
+            .unwrap();
+        let message: Vec<u8> = writer.finish().unwrap().to_vec();
+        write(&mut rfid, &message).unwrap();
+
+        let mut buf = [0; 256];
+        assert_eq!(read(&mut rfid, &mut buf).unwrap(), message);
+    }
+
+    #[test]
+    fn too_large() {
+        let mut rfid = tag(0x00);
+        // Header and terminator do not fit next to 142 bytes
+        assert!(matches!(write(&mut rfid, &[0; 142]), Err(Error::TooLarge)));
+        assert!(write(&mut rfid, &[0; 141]).is_ok());
+
+        // A buffer that ends inside the message
+        let mut buf = [0; 16];
+        assert!(matches!(read(&mut rfid, &mut buf), Err(Error::TooLarge)));
+    }
+
+    #[test]
+    fn read_only() {
+        let mut rfid = tag(0x0F);
+        assert!(matches!(write(&mut rfid, &[]), Err(Error::ReadOnly)));
+    }
+
+    #[test]
+    fn not_formatted() {
+        let mut rfid = tag(0x00);
+        rfid.write_page(CC_PAGE, &[0; 4]).unwrap();
+        assert!(matches!(capability(&mut rfid), Err(Error::NotFormatted)));
+    }
+}
@@ -0,0 +1,123 @@
+//! Type 2 tag TLV blocks
+//!
+//! NTAG user memory holds a sequence of type-length-value blocks; the NDEF
+//! message sits in the `0x03` block and `0xFE` ends the sequence.
+use core::ops::Range;
+
+use super::Error;
+
+const NULL: u8 = 0x00;
+const NDEF_MESSAGE: u8 = 0x03;
+pub const TERMINATOR: u8 = 0xFE;
+/// Length byte announcing a two byte length
+const LONG_LENGTH: u8 = 0xFF;
+
+/// Finds the NDEF message in a dump of the tag user memory.
+///
+/// [`Error::Truncated`] means the dump ends before the message does and
+/// more memory has to be read.
+pub fn find_ndef(data: &[u8]) -> Result<&[u8], Error> {
+    ndef_range(data).map(|range| &data[range])
+}
+
+/// Position of the NDEF message in `data`
+pub fn ndef_range(data: &[u8]) -> Result<Range<usize>, Error> {
+    let mut i = 0;
+    loop {
+        let tag = *data.get(i).ok_or(Error::Truncated)?;
+        i += 1;
+        match tag {
+            NULL => continue,
+            TERMINATOR => return Err(Error::InvalidTlv),
+            _ => {}
+        }
+        let (len, header) = match *data.get(i).ok_or(Error::Truncated)? {
+            LONG_LENGTH => {
+                let len = data.get(i + 1..i + 3).ok_or(Error::Truncated)?;
+                (u16::from_be_bytes([len[0], len[1]]) as usize, 3)
+            }
+            len => (len as usize, 1),
+        };
+        i += header;
+        if tag == NDEF_MESSAGE {
+            if i + len > data.len() {
+                return Err(Error::Truncated);
+            }
+            return Ok(i..i + len);
+        }
+        // Lock control, memory control and proprietary blocks are skipped
+        i += len;
+    }
+}
+
+/// TLV header for an NDEF message of `len` bytes, returns the header buffer
+/// and its length.
+pub fn header(len: usize) -> Result<([u8; 4], usize), Error> {
+    match len {
+        0..=0xFE => Ok(([NDEF_MESSAGE, len as u8, 0, 0], 2)),
+        0xFF..=0xFFFE => {
+            let [hi, lo] = (len as u16).to_be_bytes();
+            Ok(([NDEF_MESSAGE, LONG_LENGTH, hi, lo], 4))
+        }
+        _ => Err(Error::BufferTooSmall),
+    }
+}
+
+/// Wraps `message` into an NDEF message TLV followed by a terminator.
+pub fn wrap<'b>(message: &[u8], buf: &'b mut [u8]) -> Result<&'b [u8], Error> {
+    let (header, header_len) = header(message.len())?;
+    let len = header_len + message.len() + 1;
+    let out = buf.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+    out[..header_len].copy_from_slice(&header[..header_len]);
+    out[header_len..len - 1].copy_from_slice(message);
+    out[len - 1] = TERMINATOR;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_other_blocks() {
+        // Null padding and a lock control TLV in front of an empty message
+        let data = [0x00, 0x01, 0x03, 0xA0, 0x10, 0x44, 0x03, 0x00, 0xFE];
+        assert_eq!(find_ndef(&data), Ok(&[][..]));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = [0x01, 0x03, 0xA0, 0x10, 0x44, 0x03, 0x04, 0xD1, 0x01];
+        for len in 0..data.len() {
+            assert_eq!(find_ndef(&data[..len]), Err(Error::Truncated), "{len}");
+        }
+        assert_eq!(find_ndef(&[0x03, 0xFF, 0x01]), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn terminator_before_message() {
+        assert_eq!(
+            find_ndef(&[0x01, 0x00, 0xFE, 0x03, 0x00]),
+            Err(Error::InvalidTlv)
+        );
+    }
+
+    #[test]
+    fn long_length() {
+        let message = [0x5A; 300];
+        let mut buf = [0; 310];
+        let wrapped = wrap(&message, &mut buf).unwrap();
+        assert_eq!(wrapped[..4], [0x03, 0xFF, 0x01, 0x2C]);
+        assert_eq!(wrapped.len(), 4 + 300 + 1);
+        assert_eq!(wrapped[304], TERMINATOR);
+        assert_eq!(find_ndef(wrapped), Ok(&message[..]));
+    }
+
+    #[test]
+    fn header_lengths() {
+        assert_eq!(header(0xFE), Ok(([0x03, 0xFE, 0, 0], 2)));
+        assert_eq!(header(0xFF), Ok(([0x03, 0xFF, 0x00, 0xFF], 4)));
+        assert_eq!(header(0xFFFF), Err(Error::BufferTooSmall));
+        assert_eq!(wrap(&[1, 2], &mut [0; 4]), Err(Error::BufferTooSmall));
+    }
+}
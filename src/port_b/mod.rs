@@ -0,0 +1,402 @@
+//! Grove Port B (GPIO1/GPIO2)
+//!
+//! Each pin is configured at runtime as digital input or output, ADC input
+//! or PWM output. [`PortB::poll`] turns input changes and analog samples into
+//! [`Event`]s, and the app drives outputs with [`Command`]s, so external
+//! relays, LEDs and sensors go through the same event flow as the knob.
+//!
+//! The current [`Config`], including output levels and duty cycles, encodes
+//! to bytes for persisting.
+//!
+//! ```ignore
+//! let timer = port_b::configure_timer(&ledc, 1000);
+//! let pins = port_b::DialPins::new(io.pins.gpio1, io.pins.gpio2, analog.adc1, &ledc, &timer);
+//! let mut port = port_b::PortB::new(pins, port_b::Config::default(), time::now_ms());
+//! port.handle(Command::Configure { pin: 1, mode: Mode::Output { high: false } });
+//! for event in port.poll(time::now_ms()) {
+//!     if let Event::Input { pin: 0, high } = event {
+//!         port.handle(Command::Write { pin: 1, high });
+//!     }
+//! }
+//! ```
+use core::ops::RangeInclusive;
+
+use heapless::Vec;
+
+#[cfg(target_arch = "xtensa")]
+mod pins;
+
+#[cfg(target_arch = "xtensa")]
+pub use pins::{configure_timer, DialPins};
+
+/// Number of Port B pins
+pub const PINS: usize = 2;
+
+/// PWM frequencies the LEDC timer reaches with 10 bit duty from the 80 MHz
+/// APB clock
+pub const PWM_FREQUENCY_HZ: RangeInclusive<u32> = 100..=78_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Disabled,
+    Input {
+        pull: Pull,
+    },
+    Output {
+        high: bool,
+    },
+    Analog,
+    /// Duty cycle in percent
+    Pwm {
+        duty: u8,
+    },
+}
+
+impl Mode {
+    fn to_bytes(self) -> [u8; 2] {
+        match self {
+            Mode::Disabled => [0, 0],
+            Mode::Input { pull } => [1, pull as u8],
+            Mode::Output { high } => [2, high as u8],
+            Mode::Analog => [3, 0],
+            Mode::Pwm { duty } => [4, duty],
+        }
+    }
+
+    fn from_bytes(bytes: [u8; 2]) -> Option<Self> {
+        Some(match bytes {
+            [0, _] => Mode::Disabled,
+            [1, 0] => Mode::Input { pull: Pull::None },
+            [1, 1] => Mode::Input { pull: Pull::Up },
+            [1, 2] => Mode::Input { pull: Pull::Down },
+            [2, high] => Mode::Output { high: high != 0 },
+            [3, _] => Mode::Analog,
+            [4, duty] if duty <= 100 => Mode::Pwm { duty },
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub modes: [Mode; PINS],
+    /// Shared by both pins, within [`PWM_FREQUENCY_HZ`]
+    pub pwm_frequency: u32,
+    pub analog_interval_ms: u32,
+    /// Smallest change reported as a new [`Event::Analog`]
+    pub analog_threshold_mv: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            modes: [Mode::Disabled; PINS],
+            pwm_frequency: 1000,
+            analog_interval_ms: 100,
+            analog_threshold_mv: 20,
+        }
+    }
+}
+
+impl Config {
+    pub const LEN: usize = 2 * PINS + 10;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        for (chunk, mode) in bytes.chunks_exact_mut(2).zip(self.modes) {
+            chunk.copy_from_slice(&mode.to_bytes());
+        }
+        bytes[4..8].copy_from_slice(&self.pwm_frequency.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.analog_interval_ms.to_le_bytes());
+        bytes[12..].copy_from_slice(&self.analog_threshold_mv.to_le_bytes());
+        bytes
+    }
+
+    /// `None` if the bytes hold an unknown mode or a PWM frequency outside
+    /// [`PWM_FREQUENCY_HZ`]
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let mut modes = [Mode::Disabled; PINS];
+        for (mode, chunk) in modes.iter_mut().zip(bytes.chunks_exact(2)) {
+            *mode = Mode::from_bytes([chunk[0], chunk[1]])?;
+        }
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let pwm_frequency = u32_at(4);
+        if !PWM_FREQUENCY_HZ.contains(&pwm_frequency) {
+            return None;
+        }
+        Some(Self {
+            modes,
+            pwm_frequency,
+            analog_interval_ms: u32_at(8),
+            analog_threshold_mv: u16::from_le_bytes([bytes[12], bytes[13]]),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A digital input changed
+    Input { pin: usize, high: bool },
+    /// An analog input moved by at least [`Config::analog_threshold_mv`]
+    Analog { pin: usize, millivolts: u16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Configure {
+        pin: usize,
+        mode: Mode,
+    },
+    /// Sets an output pin
+    Write {
+        pin: usize,
+        high: bool,
+    },
+    /// Sets the duty cycle of a PWM pin in percent
+    Duty {
+        pin: usize,
+        duty: u8,
+    },
+}
+
+/// Pin access of the board
+pub trait Pins {
+    type Error;
+
+    fn configure(&mut self, pin: usize, mode: Mode, pwm_frequency: u32);
+    fn is_high(&mut self, pin: usize) -> bool;
+    fn set_high(&mut self, pin: usize, high: bool);
+    fn millivolts(&mut self, pin: usize) -> Result<u16, Self::Error>;
+    fn set_duty(&mut self, pin: usize, duty: u8);
+}
+
+pub struct PortB<P> {
+    pins: P,
+    config: Config,
+    /// Last reported value per pin, digital levels as 0 and 1
+    reported: [Option<u16>; PINS],
+    last_sample_ms: u64,
+}
+
+impl<P: Pins> PortB<P> {
+    /// Applies `config` to the pins.
+    pub fn new(mut pins: P, config: Config, now_ms: u64) -> Self {
+        for (pin, mode) in config.modes.into_iter().enumerate() {
+            pins.configure(pin, mode, config.pwm_frequency);
+        }
+        Self {
+            pins,
+            config,
+            reported: [None; PINS],
+            last_sample_ms: now_ms,
+        }
+    }
+
+    /// Current configuration with output levels and duty cycles
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Applies a command; returns whether the configuration changed.
+    ///
+    /// Writes to pins in a different mode are ignored.
+    pub fn handle(&mut self, command: Command) -> bool {
+        let (pin, mode) = match command {
+            Command::Configure { pin, mode } if pin < PINS => {
+                self.pins.configure(pin, mode, self.config.pwm_frequency);
+                self.reported[pin] = None;
+                (pin, mode)
+            }
+            Command::Write { pin, high } if pin < PINS => {
+                if !matches!(self.config.modes[pin], Mode::Output { .. }) {
+                    return false;
+                }
+                self.pins.set_high(pin, high);
+                (pin, Mode::Output { high })
+            }
+            Command::Duty { pin, duty } if pin < PINS => {
+                if !matches!(self.config.modes[pin], Mode::Pwm { .. }) {
+                    return false;
+                }
+                let duty = duty.min(100);
+                self.pins.set_duty(pin, duty);
+                (pin, Mode::Pwm { duty })
+            }
+            _ => return false,
+        };
+        let changed = self.config.modes[pin] != mode;
+        self.config.modes[pin] = mode;
+        changed
+    }
+
+    /// Drives the outputs from knob input: a double click toggles digital
+    /// outputs, turning the knob while pressed changes PWM duty cycles in 5%
+    /// steps. Returns whether the configuration changed.
+    #[cfg(feature = "button")]
+    pub fn input(&mut self, event: &crate::input::Event) -> bool {
+        use crate::{button, input::Event};
+
+        let mut changed = false;
+        for (pin, mode) in self.config.modes.into_iter().enumerate() {
+            let command = match (event, mode) {
+                (Event::Button(button::Event::DoubleClick), Mode::Output { high }) => {
+                    Command::Write { pin, high: !high }
+                }
+                (Event::PressedRotate { delta }, Mode::Pwm { duty }) => Command::Duty {
+                    pin,
+                    duty: (duty as i32 + delta * 5).clamp(0, 100) as u8,
+                },
+                _ => continue,
+            };
+            changed |= self.handle(command);
+        }
+        changed
+    }
+
+    /// Samples the inputs; digital inputs on every call, analog inputs every
+    /// [`Config::analog_interval_ms`]. A failed conversion skips the sample.
+    pub fn poll(&mut self, now_ms: u64) -> Vec<Event, PINS> {
+        let sample_analog = now_ms - self.last_sample_ms >= self.config.analog_interval_ms as u64;
+        if sample_analog {
+            self.last_sample_ms = now_ms;
+        }
+
+        let mut events = Vec::new();
+        for (pin, mode) in self.config.modes.into_iter().enumerate() {
+            let reported = &mut self.reported[pin];
+            match mode {
+                Mode::Input { .. } => {
+                    let high = self.pins.is_high(pin);
+                    if *reported != Some(high as u16) {
+                        *reported = Some(high as u16);
+                        events.push(Event::Input { pin, high }).ok();
+                    }
+                }
+                Mode::Analog if sample_analog => {
+                    let Ok(millivolts) = self.pins.millivolts(pin) else {
+                        continue;
+                    };
+                    let moved = match *reported {
+                        Some(last) => last.abs_diff(millivolts) >= self.config.analog_threshold_mv,
+                        None => true,
+                    };
+                    if moved {
+                        *reported = Some(millivolts);
+                        events.push(Event::Analog { pin, millivolts }).ok();
+                    }
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pin levels and ADC readings, `None` makes the conversion fail
+    #[derive(Default)]
+    struct MockPins {
+        high: [bool; PINS],
+        millivolts: [Option<u16>; PINS],
+        duty: [u8; PINS],
+    }
+
+    impl Pins for &mut MockPins {
+        type Error = ();
+
+        fn configure(&mut self, _pin: usize, _mode: Mode, _pwm_frequency: u32) {}
+
+        fn is_high(&mut self, pin: usize) -> bool {
+            self.high[pin]
+        }
+
+        fn set_high(&mut self, pin: usize, high: bool) {
+            self.high[pin] = high;
+        }
+
+        fn millivolts(&mut self, pin: usize) -> Result<u16, ()> {
+            self.millivolts[pin].ok_or(())
+        }
+
+        fn set_duty(&mut self, pin: usize, duty: u8) {
+            self.duty[pin] = duty;
+        }
+    }
+
+    #[test]
+    fn config_bytes() {
+        let config = Config {
+            modes: [Mode::Input { pull: Pull::Up }, Mode::Pwm { duty: 40 }],
+            pwm_frequency: 25_000,
+            ..Config::default()
+        };
+        assert_eq!(Config::from_bytes(&config.to_bytes()), Some(config));
+
+        let mut bytes = config.to_bytes();
+        bytes[2] = 9;
+        assert_eq!(Config::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn pwm_frequency_out_of_range() {
+        for pwm_frequency in [0, 99, 78_001, u32::MAX] {
+            let config = Config {
+                pwm_frequency,
+                ..Config::default()
+            };
+            assert_eq!(Config::from_bytes(&config.to_bytes()), None);
+        }
+    }
+
+    #[test]
+    fn failed_conversion_skips_sample() {
+        let mut pins = MockPins::default();
+        let config = Config {
+            modes: [Mode::Analog, Mode::Analog],
+            ..Config::default()
+        };
+        let mut port = PortB::new(&mut pins, config, 0);
+        port.pins.millivolts = [None, Some(1200)];
+        assert_eq!(
+            port.poll(100).as_slice(),
+            [Event::Analog {
+                pin: 1,
+                millivolts: 1200
+            }]
+        );
+        port.pins.millivolts[0] = Some(800);
+        assert_eq!(
+            port.poll(200).as_slice(),
+            [Event::Analog {
+                pin: 0,
+                millivolts: 800
+            }]
+        );
+    }
+
+    #[test]
+    fn commands_follow_modes() {
+        let mut pins = MockPins::default();
+        let config = Config {
+            modes: [Mode::Output { high: false }, Mode::Pwm { duty: 10 }],
+            ..Config::default()
+        };
+        let mut port = PortB::new(&mut pins, config, 0);
+        assert!(port.handle(Command::Write { pin: 0, high: true }));
+        assert!(!port.handle(Command::Write { pin: 1, high: true }));
+        assert!(port.handle(Command::Duty { pin: 1, duty: 150 }));
+        assert_eq!(port.config().modes[1], Mode::Pwm { duty: 100 });
+        assert_eq!((port.pins.high, port.pins.duty), ([true, false], [0, 100]));
+    }
+}
@@ -0,0 +1,226 @@
+use embedded_hal::adc::OneShot;
+use esp32s3_hal::{
+    adc::{AdcConfig, AdcPin, Attenuation, ADC, ADC1},
+    gpio::{Analog, Gpio1, Gpio2, InputPin, OutputPin, Unknown},
+    ledc::{
+        channel::{self, Channel, ChannelIFace},
+        timer::{self, Timer, TimerIFace},
+        LowSpeed, LEDC,
+    },
+    peripheral::Peripheral,
+    prelude::*,
+};
+
+use esp_println::println;
+
+use super::{Mode, Pins, Pull};
+
+/// The buzzer has timer 0 and channel 0
+const TIMER: timer::Number = timer::Number::Timer1;
+const CHANNELS: [channel::Number; 2] = [channel::Number::Channel1, channel::Number::Channel2];
+/// Approximate, uncalibrated full scale at 11 dB attenuation
+const FULL_SCALE_MV: u32 = 3100;
+const ADC_MAX: u32 = 4095;
+
+/// Configures the PWM timer; it has to outlive the [`DialPins`].
+///
+/// An unsupported frequency is logged and leaves the timer unconfigured;
+/// PWM outputs then stay off.
+pub fn configure_timer<'d>(ledc: &'d LEDC<'d>, frequency: u32) -> Timer<'d, LowSpeed> {
+    let mut timer = ledc.get_timer::<LowSpeed>(TIMER);
+    if let Err(e) = timer.configure(timer_config(frequency)) {
+        println!("port b: {frequency} Hz: {e:?}");
+    }
+    timer
+}
+
+fn timer_config(frequency: u32) -> timer::config::Config {
+    timer::config::Config {
+        duty: timer::config::Duty::Duty10Bit,
+        clock_source: timer::LSClockSource::APBClk,
+        frequency: frequency.Hz(),
+    }
+}
+
+fn set_digital<P: InputPin + OutputPin>(pin: &mut P, mode: Mode) {
+    match mode {
+        Mode::Output { high } => {
+            pin.set_output_high(high);
+            pin.set_to_push_pull_output();
+        }
+        Mode::Input { pull } => {
+            pin.set_to_input();
+            pin.internal_pull_up(pull == Pull::Up);
+            pin.internal_pull_down(pull == Pull::Down);
+        }
+        // Disabled pins float
+        _ => {
+            pin.set_to_input();
+            pin.internal_pull_up(false);
+            pin.internal_pull_down(false);
+        }
+    }
+}
+
+/// Port B pins of the Dial
+///
+/// The pins stay owned here for the whole runtime. Modes that need a typed
+/// pin (ADC, LEDC) get an unchecked clone, and switching modes simply
+/// reconfigures the pad.
+pub struct DialPins<'d> {
+    gpio1: Gpio1<Unknown>,
+    gpio2: Gpio2<Unknown>,
+    adc: ADC<'d, ADC1>,
+    analog1: AdcPin<Gpio1<Analog>, ADC1>,
+    analog2: AdcPin<Gpio2<Analog>, ADC1>,
+    ledc: &'d LEDC<'d>,
+    timer: &'d Timer<'d, LowSpeed>,
+    pwm1: Option<Channel<'d, LowSpeed, Gpio1<Unknown>>>,
+    pwm2: Option<Channel<'d, LowSpeed, Gpio2<Unknown>>>,
+}
+
+impl<'d> DialPins<'d> {
+    pub fn new(
+        mut gpio1: Gpio1<Unknown>,
+        mut gpio2: Gpio2<Unknown>,
+        adc1: ADC1,
+        ledc: &'d LEDC<'d>,
+        timer: &'d Timer<'d, LowSpeed>,
+    ) -> Self {
+        let mut config = AdcConfig::new();
+        // SAFETY: the ADC handles only sample the pads, the pin mode is
+        // always set through `configure`
+        let analog1 = config.enable_pin(
+            unsafe { gpio1.clone_unchecked() }.into_analog(),
+            Attenuation::Attenuation11dB,
+        );
+        let analog2 = config.enable_pin(
+            unsafe { gpio2.clone_unchecked() }.into_analog(),
+            Attenuation::Attenuation11dB,
+        );
+        let adc = ADC::<ADC1>::adc(adc1, config).unwrap();
+        Self {
+            gpio1,
+            gpio2,
+            adc,
+            analog1,
+            analog2,
+            ledc,
+            timer,
+            pwm1: None,
+            pwm2: None,
+        }
+    }
+
+    fn start_pwm<P: OutputPin>(
+        &self,
+        pin: P,
+        number: channel::Number,
+        duty: u8,
+    ) -> Option<Channel<'d, LowSpeed, P>> {
+        let mut channel = self.ledc.get_channel(number, pin);
+        let config = channel::config::Config {
+            timer: self.timer,
+            duty_pct: duty,
+            pin_config: channel::config::PinConfig::PushPull,
+        };
+        match channel.configure(config) {
+            Ok(()) => Some(channel),
+            Err(e) => {
+                println!("port b: {e:?}");
+                None
+            }
+        }
+    }
+}
+
+impl Pins for DialPins<'_> {
+    type Error = ();
+
+    fn configure(&mut self, pin: usize, mode: Mode, pwm_frequency: u32) {
+        // Leaving PWM mode; the pad is reconfigured below
+        match pin {
+            0 => {
+                if let Some(mut channel) = self.pwm1.take() {
+                    channel.set_duty(0).ok();
+                }
+            }
+            1 => {
+                if let Some(mut channel) = self.pwm2.take() {
+                    channel.set_duty(0).ok();
+                }
+            }
+            _ => return,
+        }
+
+        match mode {
+            // SAFETY: see `new`
+            Mode::Analog => match pin {
+                0 => {
+                    unsafe { self.gpio1.clone_unchecked() }.into_analog();
+                }
+                _ => {
+                    unsafe { self.gpio2.clone_unchecked() }.into_analog();
+                }
+            },
+            Mode::Pwm { duty } => {
+                // Both pins share the timer, so the last frequency wins
+                let mut timer = self.ledc.get_timer::<LowSpeed>(TIMER);
+                if let Err(e) = timer.configure(timer_config(pwm_frequency)) {
+                    println!("port b: {pwm_frequency} Hz: {e:?}");
+                }
+                // SAFETY: the channel drives the pad until it is dropped
+                match pin {
+                    0 => {
+                        let gpio = unsafe { self.gpio1.clone_unchecked() };
+                        self.pwm1 = self.start_pwm(gpio, CHANNELS[0], duty);
+                    }
+                    _ => {
+                        let gpio = unsafe { self.gpio2.clone_unchecked() };
+                        self.pwm2 = self.start_pwm(gpio, CHANNELS[1], duty);
+                    }
+                }
+            }
+            mode => match pin {
+                0 => set_digital(&mut self.gpio1, mode),
+                _ => set_digital(&mut self.gpio2, mode),
+            },
+        }
+    }
+
+    fn is_high(&mut self, pin: usize) -> bool {
+        match pin {
+            0 => self.gpio1.is_input_high(),
+            _ => self.gpio2.is_input_high(),
+        }
+    }
+
+    fn set_high(&mut self, pin: usize, high: bool) {
+        match pin {
+            0 => {
+                self.gpio1.set_output_high(high);
+            }
+            _ => {
+                self.gpio2.set_output_high(high);
+            }
+        }
+    }
+
+    fn millivolts(&mut self, pin: usize) -> Result<u16, ()> {
+        let raw: u16 = match pin {
+            0 => nb::block!(self.adc.read(&mut self.analog1))?,
+            _ => nb::block!(self.adc.read(&mut self.analog2))?,
+        };
+        Ok((raw as u32 * FULL_SCALE_MV / ADC_MAX) as u16)
+    }
+
+    fn set_duty(&mut self, pin: usize, duty: u8) {
+        let result = match pin {
+            0 => self.pwm1.as_mut().map(|channel| channel.set_duty(duty)),
+            _ => self.pwm2.as_mut().map(|channel| channel.set_duty(duty)),
+        };
+        if let Some(Err(e)) = result {
+            println!("port b: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Power management
+//!
+//! Without input the Dial goes to sleep in two stages:
+//!
+//! * After [`Config::light_sleep_after_ms`] the CPU enters light sleep. The
+//!   knob pins (GPIO40-42) are not RTC GPIOs and can only wake the chip from
+//!   light sleep, which is why this stage exists. RAM is kept, execution
+//!   continues where it stopped.
+//! * After [`Config::power_off_after_ms`] the application state is saved to
+//!   RTC memory, a BM8563 alarm is set [`Config::wake_after_ms`] ahead and
+//!   the power-hold pin (GPIO46) is released. On battery this switches the
+//!   Dial off; the BM8563 INT output is wired to the power switch, so the
+//!   alarm or the wake key turns it back on. On USB power the chip stays
+//!   supplied and enters deep sleep instead. The knob pins cannot wake it
+//!   from there, so a timer matching the alarm restarts the firmware.
+//!
+//! ```ignore
+//! let mut idle = power::Idle::new(power::Config::default(), time::now_ms());
+//! if let Some(saved) = power::restore_state(&mut buf) { /* ... */ }
+//! loop {
+//!     if input { idle.activity(time::now_ms()); }
+//!     match idle.stage(time::now_ms()) { /* ... */ }
+//! }
+//! ```
+use core::time::Duration;
+
+#[cfg(target_arch = "xtensa")]
+mod sleep;
+
+#[cfg(target_arch = "xtensa")]
+pub use sleep::{light_sleep, power_off, restore_state, save_state, PowerHold};
+
+/// Inactivity timeouts in milliseconds, 0 disables a stage
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub light_sleep_after_ms: u64,
+    pub power_off_after_ms: u64,
+    /// Time from power-off to the RTC alarm that switches the Dial back on,
+    /// at least a second and less than 28 days
+    pub wake_after_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            light_sleep_after_ms: 30_000,
+            power_off_after_ms: 5 * 60_000,
+            wake_after_ms: 30 * 60_000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Awake,
+    LightSleep,
+    PowerOff,
+}
+
+/// Inactivity timer
+pub struct Idle {
+    config: Config,
+    last_activity_ms: u64,
+}
+
+impl Idle {
+    pub fn new(config: Config, now_ms: u64) -> Self {
+        Self {
+            config,
+            last_activity_ms: now_ms,
+        }
+    }
+
+    /// Restarts the timer; call on every input event.
+    pub fn activity(&mut self, now_ms: u64) {
+        self.last_activity_ms = now_ms;
+    }
+
+    /// Time left until [`Stage::PowerOff`], `None` if it is disabled
+    pub fn until_power_off(&self, now_ms: u64) -> Option<Duration> {
+        if self.config.power_off_after_ms == 0 {
+            return None;
+        }
+        let idle = now_ms.saturating_sub(self.last_activity_ms);
+        Some(Duration::from_millis(
+            self.config.power_off_after_ms.saturating_sub(idle),
+        ))
+    }
+
+    /// How long after power-off the RTC alarm switches the Dial back on
+    pub fn wake_after(&self) -> Duration {
+        Duration::from_millis(self.config.wake_after_ms.max(1000))
+    }
+
+    /// The stage the device should be in at `now_ms`
+    pub fn stage(&self, now_ms: u64) -> Stage {
+        let idle = now_ms.saturating_sub(self.last_activity_ms);
+        let expired = |timeout: u64| timeout > 0 && idle >= timeout;
+        if expired(self.config.power_off_after_ms) {
+            Stage::PowerOff
+        } else if expired(self.config.light_sleep_after_ms) {
+            Stage::LightSleep
+        } else {
+            Stage::Awake
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        light_sleep_after_ms: 1_000,
+        power_off_after_ms: 5_000,
+        wake_after_ms: 60_000,
+    };
+
+    #[test]
+    fn stages_follow_inactivity() {
+        let idle = Idle::new(CONFIG, 100);
+        assert_eq!(idle.stage(100), Stage::Awake);
+        assert_eq!(idle.stage(1_099), Stage::Awake);
+        assert_eq!(idle.stage(1_100), Stage::LightSleep);
+        assert_eq!(idle.stage(5_099), Stage::LightSleep);
+        assert_eq!(idle.stage(5_100), Stage::PowerOff);
+        // A timestamp from before the last activity counts as no time passed
+        assert_eq!(idle.stage(0), Stage::Awake);
+    }
+
+    #[test]
+    fn activity_restarts() {
+        let mut idle = Idle::new(CONFIG, 0);
+        assert_eq!(idle.stage(3_000), Stage::LightSleep);
+        idle.activity(3_000);
+        assert_eq!(idle.stage(3_000), Stage::Awake);
+        assert_eq!(idle.stage(5_000), Stage::LightSleep);
+        assert_eq!(idle.stage(8_000), Stage::PowerOff);
+    }
+
+    #[test]
+    fn until_power_off() {
+        let mut idle = Idle::new(CONFIG, 0);
+        assert_eq!(
+            idle.until_power_off(1_500),
+            Some(Duration::from_millis(3_500))
+        );
+        assert_eq!(idle.until_power_off(9_000), Some(Duration::ZERO));
+        idle.activity(9_000);
+        assert_eq!(
+            idle.until_power_off(9_000),
+            Some(Duration::from_millis(5_000))
+        );
+    }
+
+    #[test]
+    fn disabled_stages() {
+        let idle = Idle::new(
+            Config {
+                power_off_after_ms: 0,
+                ..CONFIG
+            },
+            0,
+        );
+        assert_eq!(idle.stage(u64::MAX), Stage::LightSleep);
+        assert_eq!(idle.until_power_off(0), None);
+
+        let idle = Idle::new(
+            Config {
+                light_sleep_after_ms: 0,
+                ..CONFIG
+            },
+            0,
+        );
+        assert_eq!(idle.stage(4_999), Stage::Awake);
+        assert_eq!(idle.stage(5_000), Stage::PowerOff);
+
+        let idle = Idle::new(
+            Config {
+                light_sleep_after_ms: 0,
+                power_off_after_ms: 0,
+                ..CONFIG
+            },
+            0,
+        );
+        assert_eq!(idle.stage(u64::MAX), Stage::Awake);
+    }
+
+    #[test]
+    fn wake_after_has_a_minimum() {
+        assert_eq!(Idle::new(CONFIG, 0).wake_after(), Duration::from_secs(60));
+        let idle = Idle::new(
+            Config {
+                wake_after_ms: 0,
+                ..CONFIG
+            },
+            0,
+        );
+        assert_eq!(idle.wake_after(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,124 @@
+//! Power hold, sleep modes and state retention
+use core::time::Duration;
+
+use esp32s3_hal::{
+    gpio::{Gpio46, Output, PushPull},
+    macros::ram,
+    peripherals::RTC_CNTL,
+    prelude::*,
+    reset::{get_wakeup_cause, SleepSource},
+    rtc_cntl::sleep::{GpioWakeupSource, TimerWakeupSource, WakeSource},
+    Delay, Rtc,
+};
+
+/// Bit of GPIO46 in `RTC_CNTL_DIG_PAD_HOLD_REG`, which covers GPIO21 on
+const HOLD_PAD: u32 = 1 << (46 - 21);
+
+/// Power-hold output (GPIO46); keeps the Dial on while running on battery
+pub struct PowerHold {
+    pin: Gpio46<Output<PushPull>>,
+}
+
+impl PowerHold {
+    /// Asserts the hold right away, so the Dial stays on once the wake key
+    /// is released. The pad is latched as well, so a software reset (out of
+    /// memory, panic, watchdog) does not cut the power on its way through.
+    pub fn new(mut pin: Gpio46<Output<PushPull>>) -> Self {
+        pin.set_high().unwrap();
+        latch_pad(true);
+        Self { pin }
+    }
+
+    /// Lets the power switch turn the Dial off. Only returns when running
+    /// on USB power.
+    pub fn release(&mut self) {
+        latch_pad(false);
+        self.pin.set_low().unwrap();
+    }
+}
+
+/// Freezes GPIO46 at its current level until cleared; this survives all but
+/// a power-on reset.
+fn latch_pad(enable: bool) {
+    // SAFETY: only the hold bit of GPIO46 is touched, and `Rtc` leaves
+    // the digital pad holds alone
+    let rtc_cntl = unsafe { &*RTC_CNTL::PTR };
+    rtc_cntl.dig_pad_hold.modify(|r, w| unsafe {
+        w.bits(if enable {
+            r.bits() | HOLD_PAD
+        } else {
+            r.bits() & !HOLD_PAD
+        })
+    });
+}
+
+/// Sleeps until a GPIO armed with `wake_up_from_light_sleep` changes level
+/// or `timeout` passes. Returns `true` if the timeout woke the chip.
+pub fn light_sleep(rtc: &mut Rtc, delay: &mut Delay, timeout: Option<Duration>) -> bool {
+    let gpio = GpioWakeupSource::new();
+    match timeout {
+        Some(timeout) => {
+            let timer = TimerWakeupSource::new(timeout);
+            rtc.sleep_light(&[&gpio as &dyn WakeSource, &timer], delay);
+        }
+        None => rtc.sleep_light(&[&gpio], delay),
+    }
+    matches!(get_wakeup_cause(), SleepSource::Timer)
+}
+
+/// Releases the power hold; on battery the Dial switches off until the RTC
+/// alarm or the wake key turns it back on. If it is still powered, enters
+/// deep sleep until `wake_after` has passed, which should match the alarm.
+/// Waking up restarts the firmware.
+pub fn power_off(
+    hold: &mut PowerHold,
+    rtc: &mut Rtc,
+    delay: &mut Delay,
+    wake_after: Duration,
+) -> ! {
+    hold.release();
+    delay.delay_ms(100u32);
+    let timer = TimerWakeupSource::new(wake_after);
+    rtc.sleep_deep(&[&timer as &dyn WakeSource], delay)
+}
+
+const STATE_MAGIC: u32 = 0x5354_4154;
+const STATE_LEN: usize = 64;
+
+struct SavedState {
+    magic: u32,
+    len: usize,
+    data: [u8; STATE_LEN],
+}
+
+/// Survives deep sleep, but not a power-off
+#[ram(rtc_fast, uninitialized)]
+static mut SAVED_STATE: SavedState = SavedState {
+    magic: 0,
+    len: 0,
+    data: [0; STATE_LEN],
+};
+
+/// Keeps up to 64 bytes of application state for [`restore_state`].
+pub fn save_state(data: &[u8]) {
+    let len = data.len().min(STATE_LEN);
+    critical_section::with(|_| unsafe {
+        SAVED_STATE.data[..len].copy_from_slice(&data[..len]);
+        SAVED_STATE.len = len;
+        SAVED_STATE.magic = STATE_MAGIC;
+    });
+}
+
+/// Copies the state saved before the last deep sleep into `data` and
+/// returns its length. The state is consumed.
+pub fn restore_state(data: &mut [u8]) -> Option<usize> {
+    critical_section::with(|_| unsafe {
+        if SAVED_STATE.magic != STATE_MAGIC || SAVED_STATE.len > STATE_LEN {
+            return None;
+        }
+        SAVED_STATE.magic = 0;
+        let len = SAVED_STATE.len.min(data.len());
+        data[..len].copy_from_slice(&SAVED_STATE.data[..len]);
+        Some(len)
+    })
+}
@@ -0,0 +1,332 @@
+//! QMP6988 barometric pressure sensor
+//!
+//! Measurements run in forced mode and are compensated with the floating
+//! point formulas from the datasheet.
+//!
+//! ```ignore
+//! let mut qmp = qmp6988::QMP6988::new(i2c)?;
+//! let measurement = qmp.measure(&mut delay)?;
+//! ```
+use embedded_hal::blocking::{
+    delay::DelayMs,
+    i2c::{Write, WriteRead},
+};
+
+pub const ADDRESS: u8 = 0x70;
+
+const CHIP_ID: u8 = 0xD1;
+const RESET: u8 = 0xE0;
+const IIR: u8 = 0xF1;
+const DEVICE_STAT: u8 = 0xF3;
+const CTRL_MEAS: u8 = 0xF4;
+const PRESS_TXD2: u8 = 0xF7;
+const COE_B00_1: u8 = 0xA0;
+
+const CHIP_ID_VALUE: u8 = 0x5C;
+const RESET_VALUE: u8 = 0xE6;
+/// `DEVICE_STAT` bit set while a conversion runs
+const MEASURE: u8 = 1 << 3;
+const FORCED_MODE: u8 = 0b01;
+/// Raw readings are offset by 2^23
+const RAW_OFFSET: i32 = 1 << 23;
+const CALIBRATION_LEN: usize = 25;
+const POLL_MS: u32 = 5;
+const POLL_LIMIT: u32 = 40;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The bus transfer failed
+    I2c(E),
+    /// The chip ID does not match
+    InvalidChip(u8),
+    /// The conversion did not finish
+    Timeout,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
+}
+
+/// Oversampling of one conversion
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Oversampling {
+    Skip = 0,
+    X1 = 1,
+    X2 = 2,
+    X4 = 3,
+    X8 = 4,
+    X16 = 5,
+    X32 = 6,
+    X64 = 7,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    /// Degrees Celsius
+    pub temperature: f32,
+    /// Pascal
+    pub pressure: f32,
+}
+
+/// Calibration coefficients converted to floating point
+#[derive(Clone, Copy, Debug)]
+struct Coefficients {
+    a0: f64,
+    a1: f64,
+    a2: f64,
+    b00: f64,
+    bt1: f64,
+    bt2: f64,
+    bp1: f64,
+    b11: f64,
+    bp2: f64,
+    b12: f64,
+    b21: f64,
+    bp3: f64,
+}
+
+impl Coefficients {
+    fn from_otp(otp: &[u8; CALIBRATION_LEN]) -> Self {
+        let i16_at = |i: usize| i16::from_be_bytes([otp[i], otp[i + 1]]) as f64;
+        // 20 bit values, the low nibble is shared in the last byte
+        let i20 = |hi: u8, mid: u8, lo: u8| {
+            (((hi as u32) << 24 | (mid as u32) << 16 | (lo as u32) << 12) as i32 >> 12) as f64
+        };
+        // Conversion factor A + S * OTP / 32767 per coefficient
+        let convert = |a: f64, s: f64, i: usize| a + s * i16_at(i) / 32767.0;
+        Self {
+            a0: i20(otp[18], otp[19], otp[24] & 0x0F) / 16.0,
+            b00: i20(otp[0], otp[1], otp[24] >> 4) / 16.0,
+            bt1: convert(1.00e-01, 9.10e-02, 2),
+            bt2: convert(1.20e-08, 1.20e-06, 4),
+            bp1: convert(3.30e-02, 1.90e-02, 6),
+            b11: convert(2.10e-07, 1.40e-07, 8),
+            bp2: convert(-6.30e-10, 3.50e-10, 10),
+            b12: convert(2.90e-13, 7.60e-13, 12),
+            b21: convert(2.10e-15, 1.20e-14, 14),
+            bp3: convert(1.30e-16, 7.90e-17, 16),
+            a1: convert(-6.30e-03, 4.30e-04, 20),
+            a2: convert(-1.90e-11, 1.20e-10, 22),
+        }
+    }
+
+    /// Temperature in 1/256 °C
+    fn temperature(&self, dt: f64) -> f64 {
+        self.a0 + self.a1 * dt + self.a2 * dt * dt
+    }
+
+    /// Pressure in Pa at temperature `tr` in 1/256 °C
+    fn pressure(&self, dp: f64, tr: f64) -> f64 {
+        self.b00
+            + self.bt1 * tr
+            + self.bp1 * dp
+            + self.b11 * dp * tr
+            + self.bt2 * tr * tr
+            + self.bp2 * dp * dp
+            + self.b12 * dp * tr * tr
+            + self.b21 * dp * dp * tr
+            + self.bp3 * dp * dp * dp
+    }
+}
+
+pub struct QMP6988<I2C> {
+    i2c: I2C,
+    address: u8,
+    coefficients: Coefficients,
+    temperature: Oversampling,
+    pressure: Oversampling,
+}
+
+impl<I2C, E> QMP6988<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    /// Checks the chip ID and reads the calibration.
+    pub fn new(i2c: I2C) -> Result<Self, Error<E>> {
+        Self::with_address(i2c, ADDRESS)
+    }
+
+    /// The SDO pin selects 0x70 or 0x56.
+    pub fn with_address(mut i2c: I2C, address: u8) -> Result<Self, Error<E>> {
+        let mut id = [0];
+        i2c.write_read(address, &[CHIP_ID], &mut id)?;
+        if id[0] != CHIP_ID_VALUE {
+            return Err(Error::InvalidChip(id[0]));
+        }
+        let mut otp = [0; CALIBRATION_LEN];
+        i2c.write_read(address, &[COE_B00_1], &mut otp)?;
+        Ok(Self {
+            i2c,
+            address,
+            coefficients: Coefficients::from_otp(&otp),
+            temperature: Oversampling::X2,
+            pressure: Oversampling::X16,
+        })
+    }
+
+    pub fn reset(&mut self) -> Result<(), Error<E>> {
+        self.i2c.write(self.address, &[RESET, RESET_VALUE])?;
+        Ok(())
+    }
+
+    pub fn set_oversampling(&mut self, temperature: Oversampling, pressure: Oversampling) {
+        self.temperature = temperature;
+        self.pressure = pressure;
+    }
+
+    /// IIR filter coefficient 0 (off) to 7 (32)
+    pub fn set_filter(&mut self, filter: u8) -> Result<(), Error<E>> {
+        self.i2c.write(self.address, &[IIR, filter.min(7)])?;
+        Ok(())
+    }
+
+    /// Runs a forced mode conversion.
+    pub fn measure<D: DelayMs<u32>>(&mut self, delay: &mut D) -> Result<Measurement, Error<E>> {
+        let ctrl = (self.temperature as u8) << 5 | (self.pressure as u8) << 2 | FORCED_MODE;
+        self.i2c.write(self.address, &[CTRL_MEAS, ctrl])?;
+
+        let mut done = false;
+        for _ in 0..POLL_LIMIT {
+            delay.delay_ms(POLL_MS);
+            let mut status = [0];
+            self.i2c
+                .write_read(self.address, &[DEVICE_STAT], &mut status)?;
+            if status[0] & MEASURE == 0 {
+                done = true;
+                break;
+            }
+        }
+        if !done {
+            return Err(Error::Timeout);
+        }
+
+        let mut data = [0; 6];
+        self.i2c
+            .write_read(self.address, &[PRESS_TXD2], &mut data)?;
+        let raw = |d: &[u8]| ((d[0] as i32) << 16 | (d[1] as i32) << 8 | d[2] as i32) - RAW_OFFSET;
+        let dp = raw(&data[..3]) as f64;
+        let dt = raw(&data[3..]) as f64;
+
+        let tr = self.coefficients.temperature(dt);
+        Ok(Measurement {
+            temperature: (tr / 256.0) as f32,
+            pressure: self.coefficients.pressure(dp, tr) as f32,
+        })
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sht3x::tests::Delay;
+
+    /// Calibration of the worked example, with negative `a0` and positive
+    /// `b00` sharing the last byte
+    const OTP: [u8; CALIBRATION_LEN] = [
+        0x61, 0xA8, 0xFC, 0x18, 0xF8, 0x30, 0x0F, 0xA0, 0xF4, 0x48, 0x05, 0xDC, 0xFE, 0x0C, 0x02,
+        0xBC, 0xFC, 0x7C, 0xC2, 0xF6, 0x0B, 0xB8, 0xFB, 0x50, 0x5F,
+    ];
+
+    /// Register file of the sensor. A conversion keeps `MEASURE` set for
+    /// `busy` status reads.
+    pub(crate) struct Registers {
+        pub registers: [u8; 256],
+        pub busy: u32,
+    }
+
+    impl Registers {
+        /// A chip with the example calibration and readings of 25 °C and
+        /// 101325 Pa
+        pub fn example() -> Self {
+            let mut registers = [0; 256];
+            registers[CHIP_ID as usize] = CHIP_ID_VALUE;
+            registers[COE_B00_1 as usize..][..CALIBRATION_LEN].copy_from_slice(&OTP);
+            registers[PRESS_TXD2 as usize..][..6]
+                .copy_from_slice(&[0xA0, 0x2D, 0xD3, 0x49, 0x98, 0x2E]);
+            Self { registers, busy: 2 }
+        }
+    }
+
+    impl WriteRead for Registers {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            let start = bytes[0] as usize;
+            buffer.copy_from_slice(&self.registers[start..start + buffer.len()]);
+            if bytes[0] == DEVICE_STAT && self.busy > 0 {
+                self.busy -= 1;
+                buffer[0] |= MEASURE;
+            }
+            Ok(())
+        }
+    }
+
+    impl Write for Registers {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            self.registers[bytes[0] as usize] = bytes[1];
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn otp_sign_extension() {
+        let mut otp = [0; CALIBRATION_LEN];
+        // a0 = -1, b00 = 2^19 - 1, in 1/16
+        otp[18..20].copy_from_slice(&[0xFF, 0xFF]);
+        otp[0..2].copy_from_slice(&[0x7F, 0xFF]);
+        otp[24] = 0xFF;
+        let coefficients = Coefficients::from_otp(&otp);
+        assert_eq!(coefficients.a0, -1.0 / 16.0);
+        assert_eq!(coefficients.b00, 524_287.0 / 16.0);
+
+        // Most negative value, the nibbles must not mix
+        otp[18..20].copy_from_slice(&[0x80, 0x00]);
+        otp[24] = 0xF0;
+        let coefficients = Coefficients::from_otp(&otp);
+        assert_eq!(coefficients.a0, -524_288.0 / 16.0);
+
+        let coefficients = Coefficients::from_otp(&OTP);
+        assert_eq!(coefficients.a0, -250_001.0 / 16.0);
+        assert_eq!(coefficients.b00, 400_005.0 / 16.0);
+    }
+
+    #[test]
+    fn compensation() {
+        let mut qmp = QMP6988::new(Registers::example()).unwrap();
+        let mut delay = Delay(0);
+        let measurement = qmp.measure(&mut delay).unwrap();
+        assert!((measurement.temperature - 25.0).abs() < 0.001);
+        assert!((measurement.pressure - 101_325.28).abs() < 0.05);
+        assert_eq!(delay.0, 3 * POLL_MS);
+        // Temperature x2, pressure x16, forced mode
+        assert_eq!(qmp.release().registers[CTRL_MEAS as usize], 0x55);
+    }
+
+    #[test]
+    fn conversion_timeout() {
+        let mut registers = Registers::example();
+        registers.busy = u32::MAX;
+        let mut qmp = QMP6988::new(registers).unwrap();
+        assert!(matches!(qmp.measure(&mut Delay(0)), Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn invalid_chip() {
+        let mut registers = Registers::example();
+        registers.registers[CHIP_ID as usize] = 0x58;
+        assert!(matches!(
+            QMP6988::new(registers),
+            Err(Error::InvalidChip(0x58))
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+/// Delays writes of a changing value
+///
+/// A knob turned for a few seconds would otherwise write a record per
+/// detent. Every [`changed`](Debounce::changed) restarts the quiet period
+/// and [`poll`](Debounce::poll) reports once it elapsed. A value that keeps
+/// changing is still saved every `max_delay_ms`.
+#[derive(Clone, Copy, Debug)]
+pub struct Debounce {
+    quiet_ms: u64,
+    max_delay_ms: u64,
+    /// First and last change not saved yet
+    pending: Option<(u64, u64)>,
+}
+
+impl Debounce {
+    pub fn new(quiet_ms: u64, max_delay_ms: u64) -> Self {
+        Self {
+            quiet_ms,
+            max_delay_ms,
+            pending: None,
+        }
+    }
+
+    pub fn changed(&mut self, now_ms: u64) {
+        let first = self.pending.map_or(now_ms, |(first, _)| first);
+        self.pending = Some((first, now_ms));
+    }
+
+    /// Whether to save now
+    pub fn poll(&mut self, now_ms: u64) -> bool {
+        match self.pending {
+            Some((first, last))
+                if now_ms.saturating_sub(last) >= self.quiet_ms
+                    || now_ms.saturating_sub(first) >= self.max_delay_ms =>
+            {
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a change is pending, e.g. before powering off. Clears it.
+    pub fn flush(&mut self) -> bool {
+        self.pending.take().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_quiet_period() {
+        let mut debounce = Debounce::new(1_000, 30_000);
+        assert!(!debounce.poll(0));
+        debounce.changed(100);
+        assert!(!debounce.poll(1_099));
+        // Another change restarts the quiet period
+        debounce.changed(900);
+        assert!(!debounce.poll(1_100));
+        assert!(debounce.poll(1_900));
+        // Reported once
+        assert!(!debounce.poll(5_000));
+    }
+
+    #[test]
+    fn saves_while_turning_after_max_delay() {
+        let mut debounce = Debounce::new(1_000, 30_000);
+        let mut saves = std::vec::Vec::new();
+        for now in (0..65_000).step_by(500) {
+            debounce.changed(now);
+            if debounce.poll(now) {
+                saves.push(now);
+            }
+        }
+        assert_eq!(saves, [30_000, 60_500]);
+    }
+
+    #[test]
+    fn flush_clears_pending_change() {
+        let mut debounce = Debounce::new(1_000, 30_000);
+        assert!(!debounce.flush());
+        debounce.changed(0);
+        assert!(debounce.flush());
+        assert!(!debounce.flush());
+        assert!(!debounce.poll(10_000));
+    }
+}
@@ -0,0 +1,645 @@
+//! Persistent settings in flash
+//!
+//! A small key/value store on a dedicated flash region. Values are typed
+//! [`Setting`]s with a version, so a newer firmware can still decode what an
+//! older one wrote.
+//!
+//! The region is a ring of erase sectors. Records are only ever appended
+//! (key, version, length, data, CRC-32) to the active sector; the last valid
+//! record of a key wins. A full sector is compacted into the next one, which
+//! spreads erases over the whole region. The sector header, carrying a
+//! sequence number and its complement, is written after the copied records,
+//! so a power loss during compaction, even one that tears the header, leaves
+//! the previous sector active. A record torn by a
+//! power loss fails its CRC and is ignored.
+//!
+//! ```ignore
+//! let mut settings = settings::Store::new(FlashStorage::new(), SETTINGS_OFFSET, 6)?;
+//! let calibration = settings.get::<Affine>()?.unwrap_or_default();
+//! settings.set(&calibration)?;
+//! ```
+//!
+//! Values that change often, like the knob position, go through a
+//! [`Debounce`] to limit flash wear.
+use embedded_storage::nor_flash::NorFlash;
+use heapless::Vec;
+
+mod debounce;
+#[cfg(any(test, feature = "ram-flash"))]
+mod ram;
+mod records;
+
+pub use debounce::Debounce;
+#[cfg(any(test, feature = "ram-flash"))]
+pub use ram::RamFlash;
+pub use records::key;
+
+const SECTOR_MAGIC: [u8; 4] = *b"DSET";
+/// Magic, sequence number and its complement
+const SECTOR_HEADER_LEN: usize = 12;
+/// Key, version and length
+const RECORD_HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 4;
+/// Key of erased flash
+const FREE: u16 = 0xFFFF;
+/// Largest value a record holds
+pub const MAX_DATA: usize = 64;
+/// Largest supported write and read granularity
+const MAX_ALIGN: usize = 16;
+/// Buffer size for [`Store::read_raw`]
+pub const MAX_RECORD: usize = RECORD_HEADER_LEN + MAX_DATA + CRC_LEN + MAX_ALIGN;
+/// Most distinct keys in the store
+pub const MAX_KEYS: usize = 32;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    Flash(E),
+    /// The value is larger than [`MAX_DATA`]
+    TooLarge,
+    /// The live values do not fit a sector, or there are more than
+    /// [`MAX_KEYS`] keys
+    Full,
+    /// The region is not made of at least two whole sectors inside the
+    /// flash, or the flash needs a larger alignment than supported
+    InvalidRegion,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::Flash(e)
+    }
+}
+
+/// A typed value in the store
+pub trait Setting: Sized {
+    /// Unique key, see [`key`]
+    const KEY: u16;
+    /// Increment when the encoding changes
+    const VERSION: u8;
+
+    /// Encodes into `buf` and returns the length used.
+    fn encode(&self, buf: &mut [u8; MAX_DATA]) -> usize;
+
+    /// Decodes data written with `version`; `None` treats it as unset.
+    fn decode(version: u8, data: &[u8]) -> Option<Self>;
+}
+
+/// CRC-32 (IEEE)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// A stored value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub key: u16,
+    pub version: u8,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    key: u16,
+    /// Offset of the record from the start of the region
+    offset: u32,
+}
+
+pub struct Store<F> {
+    flash: F,
+    offset: u32,
+    sectors: u32,
+    align: usize,
+    active: u32,
+    sequence: u32,
+    /// Next free offset in the active sector, relative to the region
+    end: u32,
+    index: Vec<Entry, MAX_KEYS>,
+}
+
+impl<F: NorFlash> Store<F> {
+    /// Mounts the store on `sectors` erase sectors from `offset`, formatting
+    /// the region if it holds no store yet.
+    ///
+    /// Compaction needs a spare sector, so the region has at least two.
+    // `is_multiple_of` is newer than the pinned toolchain
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    pub fn new(flash: F, offset: u32, sectors: u32) -> Result<Self, Error<F::Error>> {
+        let align = F::WRITE_SIZE.max(F::READ_SIZE);
+        let end = offset as u64 + sectors as u64 * F::ERASE_SIZE as u64;
+        if sectors < 2
+            || align > MAX_ALIGN
+            || offset % F::ERASE_SIZE as u32 != 0
+            || end > flash.capacity() as u64
+        {
+            return Err(Error::InvalidRegion);
+        }
+        let mut store = Self {
+            flash,
+            offset,
+            sectors,
+            align,
+            active: 0,
+            sequence: 0,
+            end: 0,
+            index: Vec::new(),
+        };
+
+        let mut active = None;
+        for sector in 0..sectors {
+            if let Some(sequence) = store.sector_sequence(sector)? {
+                // Sequences grow by one per compaction and never wrap in
+                // practice
+                let newer = match active {
+                    Some((_, newest)) => sequence > newest,
+                    None => true,
+                };
+                if newer {
+                    active = Some((sector, sequence));
+                }
+            }
+        }
+        match active {
+            Some((sector, sequence)) => {
+                store.active = sector;
+                store.sequence = sequence;
+                store.scan()?;
+            }
+            None => store.format()?,
+        }
+        Ok(store)
+    }
+
+    /// Reads a setting, `None` if it was never written or does not decode.
+    pub fn get<T: Setting>(&mut self) -> Result<Option<T>, Error<F::Error>> {
+        let mut buf = [0; MAX_RECORD];
+        Ok(self
+            .read_raw(T::KEY, &mut buf)?
+            .and_then(|record| T::decode(record.version, record.data)))
+    }
+
+    /// Writes a setting unless the stored value is the same.
+    pub fn set<T: Setting>(&mut self, value: &T) -> Result<(), Error<F::Error>> {
+        let mut data = [0; MAX_DATA];
+        let len = value.encode(&mut data);
+        self.write_raw(T::KEY, T::VERSION, &data[..len])
+    }
+
+    /// The stored record of `key`
+    pub fn read_raw<'b>(
+        &mut self,
+        key: u16,
+        buf: &'b mut [u8; MAX_RECORD],
+    ) -> Result<Option<Record<'b>>, Error<F::Error>> {
+        let Some(entry) = self.index.iter().find(|entry| entry.key == key).copied() else {
+            return Ok(None);
+        };
+        self.read_record(entry.offset, buf)
+    }
+
+    pub fn write_raw(&mut self, key: u16, version: u8, data: &[u8]) -> Result<(), Error<F::Error>> {
+        if data.len() > MAX_DATA {
+            return Err(Error::TooLarge);
+        }
+        let mut buf = [0; MAX_RECORD];
+        let stored = self.read_raw(key, &mut buf)?;
+        if stored == Some(Record { key, version, data }) {
+            return Ok(());
+        }
+        if !self.index.iter().any(|entry| entry.key == key) && self.index.is_full() {
+            return Err(Error::Full);
+        }
+
+        let len = self.record_len(data.len());
+        if self.end as usize + len > self.sector_end(self.active) as usize {
+            self.compact(len)?;
+        }
+
+        let mut record = [0xFF; MAX_RECORD];
+        record[..2].copy_from_slice(&key.to_le_bytes());
+        record[2] = version;
+        record[3] = data.len() as u8;
+        record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + data.len()].copy_from_slice(data);
+        let crc_at = RECORD_HEADER_LEN + data.len();
+        let crc = crc32(&record[..crc_at]);
+        record[crc_at..crc_at + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+        let offset = self.end;
+        self.end += len as u32;
+        self.flash.write(self.offset + offset, &record[..len])?;
+        self.update_index(key, offset);
+        Ok(())
+    }
+
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    fn sector_start(&self, sector: u32) -> u32 {
+        sector * F::ERASE_SIZE as u32
+    }
+
+    fn sector_end(&self, sector: u32) -> u32 {
+        self.sector_start(sector) + F::ERASE_SIZE as u32
+    }
+
+    fn round_up(&self, len: usize) -> usize {
+        len.div_ceil(self.align) * self.align
+    }
+
+    fn record_len(&self, data_len: usize) -> usize {
+        self.round_up(RECORD_HEADER_LEN + data_len + CRC_LEN)
+    }
+
+    fn first_record(&self, sector: u32) -> u32 {
+        self.sector_start(sector) + self.round_up(SECTOR_HEADER_LEN) as u32
+    }
+
+    fn update_index(&mut self, key: u16, offset: u32) {
+        match self.index.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => entry.offset = offset,
+            None => {
+                // Callers check for room first
+                self.index.push(Entry { key, offset }).ok();
+            }
+        }
+    }
+
+    fn sector_sequence(&mut self, sector: u32) -> Result<Option<u32>, Error<F::Error>> {
+        let mut header = [0; MAX_ALIGN];
+        let len = self.round_up(SECTOR_HEADER_LEN);
+        self.flash
+            .read(self.offset + self.sector_start(sector), &mut header[..len])?;
+        let sequence = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let check = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if header[..4] != SECTOR_MAGIC || check != !sequence {
+            return Ok(None);
+        }
+        Ok(Some(sequence))
+    }
+
+    fn write_sector_header(&mut self, sector: u32, sequence: u32) -> Result<(), Error<F::Error>> {
+        let mut header = [0xFF; MAX_ALIGN];
+        header[..4].copy_from_slice(&SECTOR_MAGIC);
+        header[4..8].copy_from_slice(&sequence.to_le_bytes());
+        header[8..12].copy_from_slice(&(!sequence).to_le_bytes());
+        let len = self.round_up(SECTOR_HEADER_LEN);
+        self.flash
+            .write(self.offset + self.sector_start(sector), &header[..len])?;
+        Ok(())
+    }
+
+    fn format(&mut self) -> Result<(), Error<F::Error>> {
+        self.active = 0;
+        self.sequence = 0;
+        self.flash
+            .erase(self.offset, self.offset + F::ERASE_SIZE as u32)?;
+        self.write_sector_header(0, 0)?;
+        self.end = self.first_record(0);
+        self.index.clear();
+        Ok(())
+    }
+
+    /// Reads the record at `offset`. `Ok(None)` marks the end of the log:
+    /// erased flash, or a record torn by a power loss.
+    fn read_record<'b>(
+        &mut self,
+        offset: u32,
+        buf: &'b mut [u8; MAX_RECORD],
+    ) -> Result<Option<Record<'b>>, Error<F::Error>> {
+        let sector_end = self.sector_end(offset / F::ERASE_SIZE as u32);
+        let head = self.round_up(RECORD_HEADER_LEN);
+        if offset as usize + head > sector_end as usize {
+            return Ok(None);
+        }
+        self.flash.read(self.offset + offset, &mut buf[..head])?;
+        let key = u16::from_le_bytes([buf[0], buf[1]]);
+        let (version, len) = (buf[2], buf[3] as usize);
+        if key == FREE || len > MAX_DATA {
+            return Ok(None);
+        }
+        let total = self.record_len(len);
+        if offset as usize + total > sector_end as usize {
+            return Ok(None);
+        }
+        self.flash.read(self.offset + offset, &mut buf[..total])?;
+        let crc_at = RECORD_HEADER_LEN + len;
+        let crc = u32::from_le_bytes([
+            buf[crc_at],
+            buf[crc_at + 1],
+            buf[crc_at + 2],
+            buf[crc_at + 3],
+        ]);
+        if crc32(&buf[..crc_at]) != crc {
+            return Ok(None);
+        }
+        Ok(Some(Record {
+            key,
+            version,
+            data: &buf[RECORD_HEADER_LEN..crc_at],
+        }))
+    }
+
+    /// Rebuilds the index and finds the end of the active sector.
+    fn scan(&mut self) -> Result<(), Error<F::Error>> {
+        self.index.clear();
+        let mut offset = self.first_record(self.active);
+        let mut buf = [0; MAX_RECORD];
+        while let Some(Record { key, data, .. }) = self.read_record(offset, &mut buf)? {
+            let len = self.record_len(data.len());
+            if self.index.iter().any(|entry| entry.key == key) || !self.index.is_full() {
+                self.update_index(key, offset);
+            }
+            offset += len as u32;
+        }
+        // Anything after the last valid record may be partly programmed, so
+        // a torn record makes the sector full
+        let mut erased = [0; MAX_ALIGN];
+        let head = self.round_up(RECORD_HEADER_LEN);
+        if offset as usize + head <= self.sector_end(self.active) as usize {
+            self.flash.read(self.offset + offset, &mut erased[..head])?;
+            if erased[..head].iter().any(|&b| b != 0xFF) {
+                offset = self.sector_end(self.active);
+            }
+        }
+        self.end = offset;
+        Ok(())
+    }
+
+    /// Moves the live records into the next sector, leaving room for a
+    /// record of `reserve` bytes.
+    fn compact(&mut self, reserve: usize) -> Result<(), Error<F::Error>> {
+        let live: usize = {
+            let mut total = 0;
+            let mut buf = [0; MAX_RECORD];
+            for i in 0..self.index.len() {
+                let offset = self.index[i].offset;
+                if let Some(record) = self.read_record(offset, &mut buf)? {
+                    total += self.record_len(record.data.len());
+                }
+            }
+            total
+        };
+        let target = (self.active + 1) % self.sectors;
+        let first = self.first_record(target);
+        if first as usize + live + reserve > self.sector_end(target) as usize {
+            return Err(Error::Full);
+        }
+
+        let start = self.offset + self.sector_start(target);
+        self.flash.erase(start, start + F::ERASE_SIZE as u32)?;
+        let mut offset = first;
+        let mut buf = [0; MAX_RECORD];
+        for i in 0..self.index.len() {
+            let old = self.index[i].offset;
+            let len = match self.read_record(old, &mut buf)? {
+                Some(record) => self.record_len(record.data.len()),
+                None => continue,
+            };
+            // The record is still in `buf`, including its CRC
+            self.flash.write(self.offset + offset, &buf[..len])?;
+            self.index[i].offset = offset;
+            offset += len as u32;
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        self.write_sector_header(target, self.sequence)?;
+        self.active = target;
+        self.end = offset;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTOR: usize = 4096;
+    type Flash = RamFlash<{ 4 * SECTOR }>;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Counter(u32);
+
+    impl Setting for Counter {
+        const KEY: u16 = 100;
+        const VERSION: u8 = 2;
+
+        fn encode(&self, buf: &mut [u8; MAX_DATA]) -> usize {
+            buf[..4].copy_from_slice(&self.0.to_le_bytes());
+            4
+        }
+
+        fn decode(version: u8, data: &[u8]) -> Option<Self> {
+            match version {
+                1 => Some(Counter(*data.first()? as u32)),
+                2 => Some(Counter(u32::from_le_bytes(data.try_into().ok()?))),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Blob([u8; 40]);
+
+    impl Setting for Blob {
+        const KEY: u16 = 101;
+        const VERSION: u8 = 1;
+
+        fn encode(&self, buf: &mut [u8; MAX_DATA]) -> usize {
+            buf[..40].copy_from_slice(&self.0);
+            40
+        }
+
+        fn decode(_version: u8, data: &[u8]) -> Option<Self> {
+            Some(Blob(data.try_into().ok()?))
+        }
+    }
+
+    fn mount(flash: Flash) -> Store<Flash> {
+        Store::new(flash, 0, 4).unwrap()
+    }
+
+    fn counter(store: &mut Store<Flash>) -> Option<u32> {
+        store.get::<Counter>().unwrap().map(|c| c.0)
+    }
+
+    /// A store whose active sector has no room for another counter record
+    fn nearly_full() -> Store<Flash> {
+        let mut store = mount(Flash::new());
+        store.set(&Blob([5; 40])).unwrap();
+        let mut i = 0;
+        while store.end as usize + store.record_len(4) <= store.sector_end(store.active) as usize {
+            store.set(&Counter(i)).unwrap();
+            i += 1;
+        }
+        store
+    }
+
+    #[test]
+    fn region_validation() {
+        let invalid = |offset, sectors| {
+            matches!(
+                Store::new(Flash::new(), offset, sectors),
+                Err(Error::InvalidRegion)
+            )
+        };
+        assert!(invalid(0, 0));
+        assert!(invalid(0, 1));
+        assert!(invalid(100, 2));
+        assert!(invalid(SECTOR as u32, 4));
+        assert!(!invalid(SECTOR as u32, 3));
+    }
+
+    #[test]
+    fn values_survive_remount() {
+        let mut store = mount(Flash::new());
+        assert_eq!(counter(&mut store), None);
+        store.set(&Counter(7)).unwrap();
+        store.set(&Blob([3; 40])).unwrap();
+
+        let mut store = mount(store.release());
+        assert_eq!(counter(&mut store), Some(7));
+        assert_eq!(store.get::<Blob>().unwrap(), Some(Blob([3; 40])));
+        assert!(matches!(
+            store.write_raw(1, 1, &[0; MAX_DATA + 1]),
+            Err(Error::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn unchanged_value_is_not_written() {
+        let mut store = mount(Flash::new());
+        store.set(&Counter(1)).unwrap();
+        let end = store.end;
+        store.set(&Counter(1)).unwrap();
+        assert_eq!(store.end, end);
+    }
+
+    #[test]
+    fn version_decode() {
+        let mut store = mount(Flash::new());
+        store.write_raw(Counter::KEY, 1, &[9]).unwrap();
+        assert_eq!(counter(&mut store), Some(9));
+        store.write_raw(Counter::KEY, 3, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(counter(&mut store), None);
+        store.set(&Counter(0x0102_0304)).unwrap();
+        assert_eq!(counter(&mut store), Some(0x0102_0304));
+    }
+
+    #[test]
+    fn full_key_index() {
+        let mut store = mount(Flash::new());
+        for key in 0..MAX_KEYS as u16 {
+            store.write_raw(key, 1, &[key as u8; 60]).unwrap();
+        }
+        assert!(matches!(store.write_raw(1000, 1, &[0]), Err(Error::Full)));
+        // Known keys keep working through compactions
+        for i in 0..200u16 {
+            store
+                .write_raw(i % MAX_KEYS as u16, 1, &[i as u8; 60])
+                .unwrap();
+        }
+        let mut store = mount(store.release());
+        let mut buf = [0; MAX_RECORD];
+        let record = store.read_raw(199 % MAX_KEYS as u16, &mut buf).unwrap();
+        assert_eq!(record.unwrap().data, &[199; 60][..]);
+        assert!(matches!(store.write_raw(1000, 1, &[0]), Err(Error::Full)));
+    }
+
+    #[test]
+    fn erases_spread_across_sectors() {
+        let mut store = mount(Flash::new());
+        store.set(&Blob([1; 40])).unwrap();
+        for i in 0..5000 {
+            store.set(&Counter(i)).unwrap();
+        }
+        let flash = store.release();
+        let counts: [u32; 4] = core::array::from_fn(|i| flash.erase_count((i * SECTOR) as u32));
+        let min = *counts.iter().min().unwrap();
+        let max = *counts.iter().max().unwrap();
+        assert!(min > 1 && max - min <= 1, "{counts:?}");
+
+        let mut store = mount(flash);
+        assert_eq!(counter(&mut store), Some(4999));
+        assert_eq!(store.get::<Blob>().unwrap(), Some(Blob([1; 40])));
+    }
+
+    #[test]
+    fn torn_record_write() {
+        let mut store = mount(Flash::new());
+        store.set(&Counter(1)).unwrap();
+        let snapshot = store.release();
+
+        for budget in 0..12 {
+            let mut flash = snapshot.clone();
+            flash.fail_after(budget);
+            let mut store = mount(flash);
+            assert!(store.set(&Counter(2)).is_err());
+
+            let mut flash = store.release();
+            flash.power_on();
+            let mut store = mount(flash);
+            assert_eq!(counter(&mut store), Some(1), "budget {budget}");
+            // Partly programmed bytes are never written over
+            store.set(&Counter(3)).unwrap();
+            let mut store = mount(store.release());
+            assert_eq!(counter(&mut store), Some(3), "budget {budget}");
+        }
+    }
+
+    #[test]
+    fn interrupted_compaction_keeps_old_sector() {
+        let store = nearly_full();
+        let old = store.active;
+        let live: usize = store
+            .index
+            .iter()
+            .map(|entry| entry.key)
+            .map(|key| if key == Blob::KEY { 40 } else { 4 })
+            .map(|len| store.record_len(len))
+            .sum();
+        let mut store = store;
+        let last = counter(&mut store).unwrap();
+        let snapshot = store.release();
+
+        let header = SECTOR_HEADER_LEN;
+        // One erase, the copied records, the sector header and the new record
+        let total = 1 + live + header + 12;
+        for budget in 0..total {
+            let mut flash = snapshot.clone();
+            flash.fail_after(budget);
+            let mut store = mount(flash);
+            assert!(store.set(&Counter(last + 1)).is_err());
+
+            let mut flash = store.release();
+            flash.power_on();
+            let mut store = mount(flash);
+            // Header bytes that match erased flash may complete it early
+            if budget < 1 + live + SECTOR_MAGIC.len() {
+                assert_eq!(store.active, old, "budget {budget}");
+            }
+            if store.active == old {
+                assert!(budget < 1 + live + header, "budget {budget}");
+                assert_eq!(counter(&mut store), Some(last), "budget {budget}");
+            } else {
+                assert_eq!(store.active, (old + 1) % 4, "budget {budget}");
+                let value = counter(&mut store).unwrap();
+                assert!(value == last || value == last + 1, "budget {budget}");
+            }
+            assert_eq!(store.get::<Blob>().unwrap(), Some(Blob([5; 40])));
+
+            store.set(&Counter(12345)).unwrap();
+            let mut store = mount(store.release());
+            assert_eq!(counter(&mut store), Some(12345), "budget {budget}");
+        }
+    }
+}
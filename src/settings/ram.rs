@@ -0,0 +1,143 @@
+//! Flash in RAM for host tests
+//!
+//! Behaves like NOR flash: erasing sets bytes to 0xFF and writing can only
+//! clear bits. A power loss can be simulated by limiting how many more
+//! bytes get programmed or sectors erased.
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+const ERASE_SIZE: usize = 4096;
+const MAX_SECTORS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotAligned,
+    OutOfBounds,
+    /// The simulated power loss hit
+    PowerLoss,
+}
+
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Error::NotAligned => NorFlashErrorKind::NotAligned,
+            Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Error::PowerLoss => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// `SIZE` bytes of flash, at most 64 sectors
+#[derive(Clone)]
+pub struct RamFlash<const SIZE: usize> {
+    data: [u8; SIZE],
+    /// Bytes programmed or sectors erased before the power loss
+    budget: Option<usize>,
+    erases: [u32; MAX_SECTORS],
+}
+
+impl<const SIZE: usize> RamFlash<SIZE> {
+    /// Erased flash
+    pub fn new() -> Self {
+        Self {
+            data: [0xFF; SIZE],
+            budget: None,
+            erases: [0; MAX_SECTORS],
+        }
+    }
+
+    /// Loses power after `operations` more programmed bytes or erased
+    /// sectors. The interrupted write keeps the bytes programmed so far,
+    /// an interrupted erase leaves the sector untouched.
+    pub fn fail_after(&mut self, operations: usize) {
+        self.budget = Some(operations);
+    }
+
+    /// Restores power.
+    pub fn power_on(&mut self) {
+        self.budget = None;
+    }
+
+    /// How often the sector at `offset` was erased
+    pub fn erase_count(&self, offset: u32) -> u32 {
+        self.erases[offset as usize / ERASE_SIZE]
+    }
+
+    fn spend(&mut self) -> Result<(), Error> {
+        match &mut self.budget {
+            Some(0) => Err(Error::PowerLoss),
+            Some(left) => {
+                *left -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl<const SIZE: usize> Default for RamFlash<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> ErrorType for RamFlash<SIZE> {
+    type Error = Error;
+}
+
+impl<const SIZE: usize> ReadNorFlash for RamFlash<SIZE> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let data = self
+            .data
+            .get(start..start + bytes.len())
+            .ok_or(Error::OutOfBounds)?;
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SIZE
+    }
+}
+
+// `is_multiple_of` is newer than the pinned toolchain
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+impl<const SIZE: usize> NorFlash for RamFlash<SIZE> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let (from, to) = (from as usize, to as usize);
+        if from % ERASE_SIZE != 0 || to % ERASE_SIZE != 0 || from > to {
+            return Err(Error::NotAligned);
+        }
+        if to > SIZE {
+            return Err(Error::OutOfBounds);
+        }
+        for sector in (from..to).step_by(ERASE_SIZE) {
+            self.spend()?;
+            self.data[sector..sector + ERASE_SIZE].fill(0xFF);
+            self.erases[sector / ERASE_SIZE] += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        if start % Self::WRITE_SIZE != 0 || bytes.len() % Self::WRITE_SIZE != 0 {
+            return Err(Error::NotAligned);
+        }
+        if start + bytes.len() > SIZE {
+            return Err(Error::OutOfBounds);
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            self.spend()?;
+            self.data[start + i] &= byte;
+        }
+        Ok(())
+    }
+}
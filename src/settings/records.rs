@@ -0,0 +1,108 @@
+//! [`Setting`] implementations of the firmware's persisted values
+use super::{Setting, MAX_DATA};
+
+/// Keys of all settings, kept in one place so they stay unique
+pub mod key {
+    pub const TOUCH_CALIBRATION: u16 = 1;
+    pub const PORT_B: u16 = 2;
+    pub const UI_STATE: u16 = 3;
+}
+
+/// Copies a fixed size encoding into the record buffer.
+fn put(buf: &mut [u8; MAX_DATA], bytes: &[u8]) -> usize {
+    buf[..bytes.len()].copy_from_slice(bytes);
+    bytes.len()
+}
+
+#[cfg(feature = "touch")]
+impl Setting for crate::transform::Affine {
+    const KEY: u16 = key::TOUCH_CALIBRATION;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, buf: &mut [u8; MAX_DATA]) -> usize {
+        put(buf, &self.to_bytes())
+    }
+
+    fn decode(version: u8, data: &[u8]) -> Option<Self> {
+        match version {
+            1 => Some(Self::from_bytes(data.try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "port-b")]
+impl Setting for crate::port_b::Config {
+    const KEY: u16 = key::PORT_B;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, buf: &mut [u8; MAX_DATA]) -> usize {
+        put(buf, &self.to_bytes())
+    }
+
+    fn decode(version: u8, data: &[u8]) -> Option<Self> {
+        match version {
+            1 => Self::from_bytes(data.try_into().ok()?),
+            _ => None,
+        }
+    }
+}
+
+impl Setting for crate::ui::State {
+    const KEY: u16 = key::UI_STATE;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, buf: &mut [u8; MAX_DATA]) -> usize {
+        put(buf, &self.to_bytes())
+    }
+
+    fn decode(version: u8, data: &[u8]) -> Option<Self> {
+        match version {
+            1 => Some(Self::from_bytes(data.try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        settings::{RamFlash, Store},
+        ui::{Screen, State},
+    };
+
+    type Flash = RamFlash<{ 2 * 4096 }>;
+
+    #[test]
+    fn ui_state_round_trip() {
+        let mut store = Store::new(Flash::new(), 0, 2).unwrap();
+        assert_eq!(store.get::<State>().unwrap(), None);
+
+        let state = State {
+            position: -1234,
+            screen: Screen::Dial.next(),
+        };
+        store.set(&state).unwrap();
+        let mut store = Store::new(store.release(), 0, 2).unwrap();
+        assert_eq!(store.get::<State>().unwrap(), Some(state));
+    }
+
+    #[test]
+    fn ui_state_unknown_screen() {
+        let mut store = Store::new(Flash::new(), 0, 2).unwrap();
+        store
+            .write_raw(key::UI_STATE, 1, &[7, 0, 0, 0, 0xEE])
+            .unwrap();
+        assert_eq!(
+            store.get::<State>().unwrap(),
+            Some(State {
+                position: 7,
+                screen: Screen::Dial,
+            })
+        );
+        // A record of the wrong size is dropped
+        store.write_raw(key::UI_STATE, 1, &[7, 0, 0, 0]).unwrap();
+        assert_eq!(store.get::<State>().unwrap(), None);
+    }
+}
@@ -0,0 +1,194 @@
+//! SHT3x temperature and humidity sensor
+//!
+//! ```ignore
+//! let mut sht = sht3x::SHT3x::new(i2c);
+//! let measurement = sht.measure(&mut delay)?;
+//! ```
+use embedded_hal::blocking::{
+    delay::DelayMs,
+    i2c::{Read, Write},
+};
+
+pub const ADDRESS: u8 = 0x44;
+
+/// Single shot, high repeatability, no clock stretching
+const MEASURE_HIGH: [u8; 2] = [0x24, 0x00];
+const SOFT_RESET: [u8; 2] = [0x30, 0xA2];
+/// Maximum high repeatability measurement duration
+const MEASURE_MS: u32 = 16;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The bus transfer failed
+    I2c(E),
+    /// A data word failed its CRC
+    Crc,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    /// Degrees Celsius
+    pub temperature: f32,
+    /// Relative humidity in percent
+    pub humidity: f32,
+}
+
+/// CRC-8 with polynomial 0x31 and initial value 0xFF
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFF;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                crc << 1 ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn word<E>(data: &[u8]) -> Result<u16, Error<E>> {
+    if crc8(&data[..2]) != data[2] {
+        return Err(Error::Crc);
+    }
+    Ok(u16::from_be_bytes([data[0], data[1]]))
+}
+
+pub struct SHT3x<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> SHT3x<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, ADDRESS)
+    }
+
+    /// The ADDR pin selects 0x44 or 0x45.
+    pub fn with_address(i2c: I2C, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    pub fn reset(&mut self) -> Result<(), Error<E>> {
+        self.i2c.write(self.address, &SOFT_RESET)?;
+        Ok(())
+    }
+
+    /// Runs a single shot measurement.
+    pub fn measure<D: DelayMs<u32>>(&mut self, delay: &mut D) -> Result<Measurement, Error<E>> {
+        self.i2c.write(self.address, &MEASURE_HIGH)?;
+        delay.delay_ms(MEASURE_MS);
+        let mut data = [0; 6];
+        self.i2c.read(self.address, &mut data)?;
+        let temperature = word(&data[..3])? as f32;
+        let humidity = word(&data[3..])? as f32;
+        Ok(Measurement {
+            temperature: -45.0 + 175.0 * temperature / 65535.0,
+            humidity: 100.0 * humidity / 65535.0,
+        })
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Sensor that answers a measurement command with `data`
+    pub(crate) struct Sensor {
+        pub data: [u8; 6],
+        command: Option<[u8; 2]>,
+    }
+
+    impl Sensor {
+        /// Raw readings with valid CRCs
+        pub fn new(temperature: u16, humidity: u16) -> Self {
+            let mut data = [0; 6];
+            for (chunk, word) in data.chunks_mut(3).zip([temperature, humidity]) {
+                chunk[..2].copy_from_slice(&word.to_be_bytes());
+                chunk[2] = crc8(&chunk[..2]);
+            }
+            Self {
+                data,
+                command: None,
+            }
+        }
+    }
+
+    impl Write for Sensor {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            self.command = Some(bytes.try_into().unwrap());
+            Ok(())
+        }
+    }
+
+    impl Read for Sensor {
+        type Error = ();
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            assert_eq!(self.command.take(), Some(MEASURE_HIGH));
+            buffer.copy_from_slice(&self.data);
+            Ok(())
+        }
+    }
+
+    /// Adds up the requested delays
+    pub(crate) struct Delay(pub u32);
+
+    impl DelayMs<u32> for Delay {
+        fn delay_ms(&mut self, ms: u32) {
+            self.0 += ms;
+        }
+    }
+
+    #[test]
+    fn crc() {
+        // Example from the datasheet
+        assert_eq!(crc8(&[0xBE, 0xEF]), 0x92);
+        assert_eq!(word::<()>(&[0xBE, 0xEF, 0x92]).unwrap(), 0xBEEF);
+        assert!(matches!(word::<()>(&[0xBE, 0xEF, 0x93]), Err(Error::Crc)));
+    }
+
+    #[test]
+    fn conversion() {
+        let mut sht = SHT3x::new(Sensor::new(0x6666, 0x8000));
+        let mut delay = Delay(0);
+        let measurement = sht.measure(&mut delay).unwrap();
+        assert!((measurement.temperature - 25.0).abs() < 0.001);
+        assert!((measurement.humidity - 50.0).abs() < 0.001);
+        assert_eq!(delay.0, MEASURE_MS);
+
+        let mut sht = SHT3x::new(Sensor::new(0, 0xFFFF));
+        let measurement = sht.measure(&mut delay).unwrap();
+        assert_eq!(measurement.temperature, -45.0);
+        assert_eq!(measurement.humidity, 100.0);
+    }
+
+    #[test]
+    fn crc_failure() {
+        for corrupt in [2, 4, 5] {
+            let mut sensor = Sensor::new(0x6666, 0x8000);
+            sensor.data[corrupt] ^= 0x01;
+            let mut sht = SHT3x::new(sensor);
+            assert!(matches!(sht.measure(&mut Delay(0)), Err(Error::Crc)));
+        }
+    }
+}
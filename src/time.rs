@@ -0,0 +1,7 @@
+//! Monotonic time since boot, used to timestamp input events
+use esp32s3_hal::systimer::SystemTimer;
+
+/// Milliseconds since boot
+pub fn now_ms() -> u64 {
+    SystemTimer::now() / (SystemTimer::TICKS_PER_SECOND / 1000)
+}
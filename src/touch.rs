@@ -0,0 +1,162 @@
+//! Interrupt-driven touch input
+//!
+//! The FT3267 pulls its INT line (GPIO14) low while the panel is touched.
+//! [`Touch`] only talks to the controller when that happened, leaving the
+//! I2C bus idle and the CPU free to sleep while nobody touches the screen.
+//!
+//! Reports are mapped to display coordinates by a [`TouchTransform`] before
+//! they are handed out.
+//!
+//! ```ignore
+//! let mut touch = touch::Touch::new(ft3267::FT3267::new(i2c), io.pins.gpio14.into_pull_up_input());
+//! loop {
+//!     touch.wait_for_touch().await;
+//!     let report = touch.touch()?;
+//! }
+//! ```
+use core::{
+    cell::RefCell,
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use critical_section::Mutex;
+use embedded_hal::blocking::i2c::WriteRead;
+use esp32s3_hal::{
+    gpio::{Event, Gpio14, Input, PullUp},
+    interrupt, peripherals,
+    prelude::*,
+};
+
+use crate::{
+    ft3267::{Error, Phase, TouchPoint, TouchReport, FT3267},
+    transform::TouchTransform,
+    waker::WakerCell,
+};
+
+static INT: Mutex<RefCell<Option<Gpio14<Input<PullUp>>>>> = Mutex::new(RefCell::new(None));
+static TOUCHED: AtomicBool = AtomicBool::new(false);
+static WAKER: WakerCell = WakerCell::new();
+
+pub struct Touch<I2C> {
+    controller: FT3267<I2C>,
+    transform: TouchTransform,
+    last: TouchReport,
+}
+
+impl<I2C, E> Touch<I2C>
+where
+    I2C: WriteRead<Error = E>,
+{
+    pub fn new(controller: FT3267<I2C>, mut int: Gpio14<Input<PullUp>>) -> Self {
+        int.listen(Event::FallingEdge);
+        critical_section::with(|cs| INT.borrow_ref_mut(cs).replace(int));
+
+        interrupt::enable(peripherals::Interrupt::GPIO, interrupt::Priority::Priority2).unwrap();
+
+        Self {
+            controller,
+            transform: TouchTransform::default(),
+            last: TouchReport::default(),
+        }
+    }
+
+    pub fn set_transform(&mut self, transform: TouchTransform) {
+        self.transform = transform;
+    }
+
+    /// Returns the current touch points in display coordinates.
+    ///
+    /// The controller is only read while INT is asserted and once more after
+    /// it was released, to pick up the final up events. Otherwise the last
+    /// known state is returned with no fingers in [`Phase::Down`] or
+    /// [`Phase::Up`].
+    pub fn touch(&mut self) -> Result<TouchReport, Error<E>> {
+        self.touch_raw()
+            .map(|report| self.transform.apply_report(&report))
+    }
+
+    /// Like [`Touch::touch`], but in panel coordinates
+    pub fn touch_raw(&mut self) -> Result<TouchReport, Error<E>> {
+        let pending = TOUCHED.swap(false, Ordering::SeqCst) || is_asserted() || self.last.count() > 0;
+        if !pending {
+            return Ok(self.last);
+        }
+
+        let report = self.controller.touch()?;
+        self.last = TouchReport {
+            points: report.points.map(|p| {
+                p.filter(|p| p.phase != Phase::Up).map(|p| match p.phase {
+                    Phase::Down => TouchPoint {
+                        phase: Phase::Move,
+                        ..p
+                    },
+                    _ => p,
+                })
+            }),
+        };
+        Ok(report)
+    }
+
+    /// Waits until the panel is touched.
+    pub async fn wait_for_touch(&mut self) {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if TOUCHED.load(Ordering::SeqCst) || is_asserted() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Arms INT as a light-sleep wakeup source. Light sleep wakeup is level
+    /// triggered, so this replaces the edge interrupt until
+    /// [`Touch::unlisten_wakeup`] is called.
+    pub fn listen_wakeup(&mut self) {
+        critical_section::with(|cs| {
+            if let Some(int) = INT.borrow_ref_mut(cs).as_mut() {
+                int.listen_with_options(Event::LowLevel, false, false, true);
+            }
+        });
+    }
+
+    pub fn unlisten_wakeup(&mut self) {
+        critical_section::with(|cs| {
+            if let Some(int) = INT.borrow_ref_mut(cs).as_mut() {
+                int.listen(Event::FallingEdge);
+            }
+        });
+        // A touch may have woken us up
+        if is_asserted() {
+            TOUCHED.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn controller(&mut self) -> &mut FT3267<I2C> {
+        &mut self.controller
+    }
+}
+
+fn is_asserted() -> bool {
+    critical_section::with(|cs| {
+        INT.borrow_ref(cs)
+            .as_ref()
+            .map_or(false, |int| int.is_low().unwrap())
+    })
+}
+
+/// Handles an edge on INT; call from the `GPIO` interrupt.
+pub fn on_interrupt() {
+    critical_section::with(|cs| {
+        if let Some(int) = INT.borrow_ref_mut(cs).as_mut() {
+            if int.is_interrupt_set() {
+                int.clear_interrupt();
+                TOUCHED.store(true, Ordering::SeqCst);
+                WAKER.wake();
+            }
+        }
+    });
+}
@@ -0,0 +1,277 @@
+//! Mapping from touch panel to display coordinates
+//!
+//! The FT3267 reports positions in its own panel coordinates. A
+//! [`TouchTransform`] first applies the affine calibration, which takes care
+//! of the panel being mounted at 90 degrees and of per-unit offset and scale
+//! errors, and then the rotation the application uses for the display.
+use crate::ft3267::TouchReport;
+
+/// Side length of the display in pixels
+const SIZE: f32 = 240.0;
+
+/// A raw panel position and the display position it should map to
+pub type Sample = ((f32, f32), (f32, f32));
+
+/// `x' = a * x + b * y + c`, `y' = d * x + e * y + f`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Affine {
+    /// Serialized size in bytes
+    pub const LEN: usize = 24;
+
+    /// Nominal mapping of an uncalibrated Dial: the panel is rotated by 90
+    /// degrees against the display.
+    pub const MOUNTING: Self = Self {
+        a: 0.0,
+        b: 1.0,
+        c: 0.0,
+        d: -1.0,
+        e: 0.0,
+        f: SIZE,
+    };
+
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            self.a * x + self.b * y + self.c,
+            self.d * x + self.e * y + self.f,
+        )
+    }
+
+    /// Least-squares fit of `(raw, screen)` pairs; needs at least three
+    /// points that are not on one line.
+    pub fn fit(points: &[Sample]) -> Option<Self> {
+        if points.len() < 3 {
+            return None;
+        }
+        // Normal equations M * [a b c] = vx and M * [d e f] = vy
+        let mut m = [[0.0f32; 3]; 3];
+        let mut vx = [0.0f32; 3];
+        let mut vy = [0.0f32; 3];
+        for &((x, y), (sx, sy)) in points {
+            let row = [x, y, 1.0];
+            for i in 0..3 {
+                for j in 0..3 {
+                    m[i][j] += row[i] * row[j];
+                }
+                vx[i] += row[i] * sx;
+                vy[i] += row[i] * sy;
+            }
+        }
+        let [a, b, c] = solve3(&m, &vx)?;
+        let [d, e, f] = solve3(&m, &vy)?;
+        Some(Self { a, b, c, d, e, f })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        for (chunk, v) in bytes
+            .chunks_exact_mut(4)
+            .zip([self.a, self.b, self.c, self.d, self.e, self.f])
+        {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        let mut v = [0.0; 6];
+        for (v, chunk) in v.iter_mut().zip(bytes.chunks_exact(4)) {
+            *v = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        let [a, b, c, d, e, f] = v;
+        Self { a, b, c, d, e, f }
+    }
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self::MOUNTING
+    }
+}
+
+/// Solves a 3x3 linear system by Cramer's rule.
+fn solve3(m: &[[f32; 3]; 3], v: &[f32; 3]) -> Option<[f32; 3]> {
+    fn det(m: &[[f32; 3]; 3]) -> f32 {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+    let d = det(m);
+    if d.abs() < f32::EPSILON {
+        return None;
+    }
+    let mut result = [0.0; 3];
+    for (col, r) in result.iter_mut().enumerate() {
+        let mut mc = *m;
+        for row in 0..3 {
+            mc[row][col] = v[row];
+        }
+        *r = det(&mc) / d;
+    }
+    Some(result)
+}
+
+/// Rotation of the display content, clockwise
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        match self {
+            Rotation::Rotate0 => (x, y),
+            Rotation::Rotate90 => (y, SIZE - x),
+            Rotation::Rotate180 => (SIZE - x, SIZE - y),
+            Rotation::Rotate270 => (SIZE - y, x),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TouchTransform {
+    pub calibration: Affine,
+    pub rotation: Rotation,
+}
+
+impl TouchTransform {
+    /// Maps a panel position to display coordinates, clamped to the display.
+    pub fn apply(&self, x: u16, y: u16) -> (u16, u16) {
+        let (x, y) = self.calibration.apply(x as f32, y as f32);
+        let (x, y) = self.rotation.apply(x, y);
+        (x.clamp(0.0, SIZE) as u16, y.clamp(0.0, SIZE) as u16)
+    }
+
+    pub fn apply_report(&self, report: &TouchReport) -> TouchReport {
+        TouchReport {
+            points: report.points.map(|p| {
+                p.map(|mut p| {
+                    (p.x, p.y) = self.apply(p.x, p.y);
+                    p
+                })
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEARED: Affine = Affine {
+        a: 0.02,
+        b: 1.05,
+        c: -3.0,
+        d: -0.97,
+        e: 0.01,
+        f: 236.0,
+    };
+
+    fn assert_close(got: (f32, f32), want: (f32, f32)) {
+        assert!(
+            (got.0 - want.0).abs() < 1e-3 && (got.1 - want.1).abs() < 1e-3,
+            "{got:?} != {want:?}"
+        );
+    }
+
+    #[test]
+    fn mounting_rotates_panel() {
+        for (x, y) in [(0.0, 0.0), (240.0, 0.0), (30.0, 200.0), (120.0, 120.0)] {
+            assert_eq!(Affine::MOUNTING.apply(x, y), (y, SIZE - x));
+        }
+        assert_eq!(Affine::default(), Affine::MOUNTING);
+    }
+
+    #[test]
+    fn fit_recovers_transform() {
+        let raw = [(30.0, 40.0), (200.0, 35.0), (190.0, 210.0), (45.0, 190.0)];
+        let points: std::vec::Vec<Sample> = raw
+            .iter()
+            .map(|&(x, y)| ((x, y), SHEARED.apply(x, y)))
+            .collect();
+        let fitted = Affine::fit(&points).unwrap();
+        for (x, y) in [(0.0, 0.0), (120.0, 120.0), (240.0, 240.0)] {
+            assert_close(fitted.apply(x, y), SHEARED.apply(x, y));
+        }
+    }
+
+    #[test]
+    fn fit_averages_noise() {
+        // Taps that miss the target by the same amount in opposite
+        // directions cancel out
+        let points = [
+            ((10.0, 10.0), (11.0, 10.0)),
+            ((10.0, 10.0), (9.0, 10.0)),
+            ((100.0, 10.0), (100.0, 10.0)),
+            ((10.0, 100.0), (10.0, 100.0)),
+        ];
+        let fitted = Affine::fit(&points).unwrap();
+        assert_close(fitted.apply(10.0, 10.0), (10.0, 10.0));
+    }
+
+    #[test]
+    fn fit_rejects_degenerate_points() {
+        let sample = |x, y| ((x, y), (x, y));
+        assert_eq!(Affine::fit(&[]), None);
+        assert_eq!(Affine::fit(&[sample(0.0, 0.0), sample(10.0, 20.0)]), None);
+        // On one line
+        let line = [
+            sample(0.0, 0.0),
+            sample(10.0, 10.0),
+            sample(20.0, 20.0),
+            sample(30.0, 30.0),
+        ];
+        assert_eq!(Affine::fit(&line), None);
+        // The same spot every time
+        assert_eq!(Affine::fit(&[sample(50.0, 50.0); 4]), None);
+    }
+
+    #[test]
+    fn rotations() {
+        let p = (30.0, 50.0);
+        assert_eq!(Rotation::Rotate0.apply(p.0, p.1), (30.0, 50.0));
+        assert_eq!(Rotation::Rotate90.apply(p.0, p.1), (50.0, 210.0));
+        assert_eq!(Rotation::Rotate180.apply(p.0, p.1), (210.0, 190.0));
+        assert_eq!(Rotation::Rotate270.apply(p.0, p.1), (190.0, 30.0));
+        // A quarter turn forth and back is the identity
+        let (x, y) = Rotation::Rotate90.apply(p.0, p.1);
+        assert_eq!(Rotation::Rotate270.apply(x, y), p);
+    }
+
+    #[test]
+    fn transform_clamps_to_display() {
+        let transform = TouchTransform::default();
+        assert_eq!(transform.apply(30, 50), (50, 210));
+        // Raw positions past the panel edge map outside the display
+        assert_eq!(transform.apply(300, 400), (240, 0));
+
+        let transform = TouchTransform {
+            calibration: Affine {
+                c: -50.0,
+                ..Affine::MOUNTING
+            },
+            rotation: Rotation::Rotate180,
+        };
+        assert_eq!(transform.apply(10, 20), (240, 10));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        for affine in [Affine::MOUNTING, SHEARED] {
+            assert_eq!(Affine::from_bytes(&affine.to_bytes()), affine);
+        }
+        assert_eq!(Affine::MOUNTING.to_bytes()[4..8], 1.0f32.to_le_bytes());
+    }
+}
@@ -0,0 +1,67 @@
+//! State of the demo UI
+//!
+//! What the user picked on the Dial: the knob position and the screen on
+//! display. [`State`] survives deep sleep in RTC memory and power cycles in
+//! the [`settings`](crate::settings) store.
+
+/// Screens of the demo, switched by swiping
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Screen {
+    /// Knob position on the rim
+    #[default]
+    Dial = 0,
+    /// Readings of the Port A sensor unit
+    #[cfg(feature = "port-a")]
+    Sensors = 1,
+}
+
+impl Screen {
+    const ALL: &'static [Screen] = &[
+        Screen::Dial,
+        #[cfg(feature = "port-a")]
+        Screen::Sensors,
+    ];
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|s| *s == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+
+    /// `None` for a screen this build does not have
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|s| *s as u8 == value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct State {
+    /// Detents turned since the first boot
+    pub position: i32,
+    pub screen: Screen,
+}
+
+impl State {
+    pub const LEN: usize = 5;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..4].copy_from_slice(&self.position.to_le_bytes());
+        bytes[4] = self.screen as u8;
+        bytes
+    }
+
+    /// An unknown screen falls back to the default one.
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        Self {
+            position: i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            screen: Screen::from_u8(bytes[4]).unwrap_or_default(),
+        }
+    }
+}
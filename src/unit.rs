@@ -0,0 +1,249 @@
+//! M5 sensor units on the Grove Port A bus
+//!
+//! Every unit implements [`SensorUnit`], which reports its readings as a
+//! list of [`Reading`]s so the UI can show any unit the same way. A
+//! [`bus::scan`](crate::bus::scan) tells which units are plugged in.
+//!
+//! ```ignore
+//! let found = bus::scan(&mut port_a.device());
+//! if unit::is_present(unit::ENV_ADDRESSES, &found) {
+//!     let mut env = Env::new(port_a.device(), port_a.device())?;
+//!     for reading in env.read(&mut delay)? {
+//!         println!("{}: {} {}", reading.quantity.name(), reading.value, reading.quantity.unit());
+//!     }
+//! }
+//! ```
+use embedded_hal::blocking::{
+    delay::DelayMs,
+    i2c::{Read, Write, WriteRead},
+};
+use heapless::Vec;
+
+use crate::{qmp6988, sht3x};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quantity {
+    Temperature,
+    Humidity,
+    Pressure,
+}
+
+impl Quantity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quantity::Temperature => "temperature",
+            Quantity::Humidity => "humidity",
+            Quantity::Pressure => "pressure",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Quantity::Temperature => "C",
+            Quantity::Humidity => "%",
+            Quantity::Pressure => "hPa",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reading {
+    pub quantity: Quantity,
+    /// In [`Quantity::unit`]
+    pub value: f32,
+}
+
+/// Most readings a unit reports at once
+pub const MAX_READINGS: usize = 4;
+
+pub trait SensorUnit {
+    type Error;
+
+    fn name(&self) -> &'static str;
+
+    /// Takes a measurement of every quantity the unit provides.
+    fn read<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Vec<Reading, MAX_READINGS>, Self::Error>;
+}
+
+/// Whether a [`bus::scan`](crate::bus::scan) result contains all
+/// `addresses` of a unit.
+pub fn is_present(addresses: &[u8], found: &[u8]) -> bool {
+    addresses.iter().all(|address| found.contains(address))
+}
+
+#[derive(Debug)]
+pub enum EnvError<E> {
+    Sht3x(sht3x::Error<E>),
+    Qmp6988(qmp6988::Error<E>),
+}
+
+impl<E> From<sht3x::Error<E>> for EnvError<E> {
+    fn from(e: sht3x::Error<E>) -> Self {
+        EnvError::Sht3x(e)
+    }
+}
+
+impl<E> From<qmp6988::Error<E>> for EnvError<E> {
+    fn from(e: qmp6988::Error<E>) -> Self {
+        EnvError::Qmp6988(e)
+    }
+}
+
+pub const ENV_ADDRESSES: &[u8] = &[sht3x::ADDRESS, qmp6988::ADDRESS];
+
+/// ENV III unit: SHT30 for temperature and humidity, QMP6988 for pressure
+pub struct Env<I2C> {
+    sht: sht3x::SHT3x<I2C>,
+    qmp: qmp6988::QMP6988<I2C>,
+}
+
+impl<I2C, E> Env<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Takes one handle per chip.
+    pub fn new(sht: I2C, qmp: I2C) -> Result<Self, EnvError<E>> {
+        Ok(Self {
+            sht: sht3x::SHT3x::new(sht),
+            qmp: qmp6988::QMP6988::new(qmp)?,
+        })
+    }
+}
+
+impl<I2C, E> SensorUnit for Env<I2C>
+where
+    I2C: Read<Error = E> + Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = EnvError<E>;
+
+    fn name(&self) -> &'static str {
+        "ENV III"
+    }
+
+    fn read<D: DelayMs<u32>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Vec<Reading, MAX_READINGS>, Self::Error> {
+        let climate = self.sht.measure(delay)?;
+        let pressure = self.qmp.measure(delay)?;
+        let mut readings = Vec::new();
+        // The SHT30 is the more accurate thermometer of the two
+        readings
+            .extend_from_slice(&[
+                Reading {
+                    quantity: Quantity::Temperature,
+                    value: climate.temperature,
+                },
+                Reading {
+                    quantity: Quantity::Humidity,
+                    value: climate.humidity,
+                },
+                Reading {
+                    quantity: Quantity::Pressure,
+                    value: pressure.pressure / 100.0,
+                },
+            ])
+            .ok();
+        Ok(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::boxed::Box;
+
+    use crate::{qmp6988::tests::Registers, sht3x::tests::Sensor};
+
+    /// One handle of the ENV III bus, each talking to its own chip
+    enum Chip {
+        Sht(Sensor),
+        Qmp(Box<Registers>),
+    }
+
+    impl Read for Chip {
+        type Error = ();
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ()> {
+            match self {
+                Chip::Sht(sht) => sht.read(address, buffer),
+                Chip::Qmp(_) => panic!("plain read from the QMP6988"),
+            }
+        }
+    }
+
+    impl Write for Chip {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            match self {
+                Chip::Sht(sht) => sht.write(address, bytes),
+                Chip::Qmp(qmp) => qmp.write(address, bytes),
+            }
+        }
+    }
+
+    impl WriteRead for Chip {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            match self {
+                Chip::Sht(_) => panic!("register read from the SHT3x"),
+                Chip::Qmp(qmp) => qmp.write_read(address, bytes, buffer),
+            }
+        }
+    }
+
+    #[test]
+    fn presence() {
+        assert!(is_present(ENV_ADDRESSES, &[0x44, 0x51, 0x70]));
+        assert!(!is_present(ENV_ADDRESSES, &[0x44]));
+        assert!(!is_present(ENV_ADDRESSES, &[]));
+        assert!(is_present(&[], &[0x44]));
+    }
+
+    #[test]
+    fn env_readings() {
+        let mut env = Env::new(
+            Chip::Sht(Sensor::new(0x6666, 0x8000)),
+            Chip::Qmp(Box::new(Registers::example())),
+        )
+        .unwrap();
+        assert_eq!(env.name(), "ENV III");
+        let readings = env.read(&mut crate::sht3x::tests::Delay(0)).unwrap();
+        let quantities: std::vec::Vec<_> = readings.iter().map(|r| r.quantity).collect();
+        assert_eq!(
+            quantities,
+            [
+                Quantity::Temperature,
+                Quantity::Humidity,
+                Quantity::Pressure
+            ]
+        );
+        assert!((readings[0].value - 25.0).abs() < 0.001);
+        assert!((readings[1].value - 50.0).abs() < 0.001);
+        assert!((readings[2].value - 1013.253).abs() < 0.001);
+    }
+
+    #[test]
+    fn env_errors() {
+        let mut sensor = Sensor::new(0x6666, 0x8000);
+        sensor.data[5] ^= 0x01;
+        let mut env =
+            Env::new(Chip::Sht(sensor), Chip::Qmp(Box::new(Registers::example()))).unwrap();
+        assert!(matches!(
+            env.read(&mut crate::sht3x::tests::Delay(0)),
+            Err(EnvError::Sht3x(sht3x::Error::Crc))
+        ));
+
+        let mut registers = Registers::example();
+        registers.registers[0xD1] = 0; // chip ID
+        assert!(matches!(
+            Env::new(Chip::Sht(Sensor::new(0, 0)), Chip::Qmp(Box::new(registers))),
+            Err(EnvError::Qmp6988(qmp6988::Error::InvalidChip(0)))
+        ));
+    }
+}
@@ -0,0 +1,36 @@
+//! Waker storage shared between a future and the interrupt that completes it
+use core::{cell::RefCell, task::Waker};
+
+use critical_section::Mutex;
+
+pub struct WakerCell(Mutex<RefCell<Option<Waker>>>);
+
+impl Default for WakerCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakerCell {
+    pub const fn new() -> Self {
+        Self(Mutex::new(RefCell::new(None)))
+    }
+
+    /// Stores `waker`, replacing a previously registered one unless both
+    /// would wake the same task.
+    pub fn register(&self, waker: &Waker) {
+        critical_section::with(|cs| {
+            let mut slot = self.0.borrow_ref_mut(cs);
+            match slot.as_ref() {
+                Some(w) if w.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        })
+    }
+
+    pub fn wake(&self) {
+        if let Some(waker) = critical_section::with(|cs| self.0.borrow_ref_mut(cs).take()) {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,959 @@
+//! WS1850S (MFRC522 compatible) 13.56 MHz RFID reader
+//!
+//! Implements the ISO 14443A parts needed for MIFARE Classic and Ultralight
+//! (NTAG) cards: presence detection, anticollision and select for 4, 7 and
+//! 10 byte UIDs, MIFARE authentication and block/page access.
+//!
+//! ```ignore
+//! let mut rfid = ws1850s::WS1850S::new(i2c);
+//! rfid.init()?;
+//! if let Some(card) = rfid.read_card()? {
+//!     rfid.authenticate(KeyType::A, 4, &DEFAULT_KEY, &card.uid)?;
+//!     let data = rfid.read_block(4)?;
+//!     rfid.stop_crypto()?;
+//!     rfid.halt()?;
+//! }
+//! ```
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+const ADDRESS: u8 = 0x28;
+
+const COMMAND: u8 = 0x01;
+const COM_IRQ: u8 = 0x04;
+const ERROR: u8 = 0x06;
+const STATUS_2: u8 = 0x08;
+const FIFO_DATA: u8 = 0x09;
+const FIFO_LEVEL: u8 = 0x0A;
+const CONTROL: u8 = 0x0C;
+const BIT_FRAMING: u8 = 0x0D;
+const COLL: u8 = 0x0E;
+const MODE: u8 = 0x11;
+const TX_CONTROL: u8 = 0x14;
+const TX_ASK: u8 = 0x15;
+const T_MODE: u8 = 0x2A;
+const T_PRESCALER: u8 = 0x2B;
+const T_RELOAD_H: u8 = 0x2C;
+const T_RELOAD_L: u8 = 0x2D;
+const VERSION: u8 = 0x37;
+
+// Reader commands
+const IDLE: u8 = 0x00;
+const MF_AUTHENT: u8 = 0x0E;
+const TRANSCEIVE: u8 = 0x0C;
+const SOFT_RESET: u8 = 0x0F;
+/// `CommandReg` power down bit, set while the reset is in progress
+const POWER_DOWN: u8 = 1 << 4;
+
+// `ComIrqReg` bits
+const RX_IRQ: u8 = 1 << 5;
+const IDLE_IRQ: u8 = 1 << 4;
+const TIMER_IRQ: u8 = 1 << 0;
+
+// `ErrorReg` bits
+const COLL_ERR: u8 = 1 << 3;
+const BUFFER_OVFL: u8 = 1 << 4;
+const PARITY_ERR: u8 = 1 << 1;
+const PROTOCOL_ERR: u8 = 1 << 0;
+
+/// `Status2Reg` bit set while Crypto1 is active
+const MF_CRYPTO1_ON: u8 = 1 << 3;
+/// `CollReg` bit: received bits after a collision are cleared
+const VALUES_AFTER_COLL: u8 = 1 << 7;
+/// `CollReg` bit: no collision detected or its position is out of range
+const COLL_POS_NOT_VALID: u8 = 1 << 5;
+/// `BitFramingReg` bit starting the transmission of a transceive
+const START_SEND: u8 = 1 << 7;
+
+// Card commands
+const REQA: u8 = 0x26;
+const WUPA: u8 = 0x52;
+const HLTA: u8 = 0x50;
+const SELECT: [u8; 3] = [0x93, 0x95, 0x97];
+/// Cascade tag, first UID byte of a level that is followed by another one
+const CT: u8 = 0x88;
+const MF_READ: u8 = 0x30;
+const MF_WRITE: u8 = 0xA0;
+const UL_WRITE: u8 = 0xA2;
+/// 4 bit MIFARE acknowledge
+const MF_ACK: u8 = 0x0A;
+
+const FIFO_SIZE: usize = 64;
+/// Upper bound for interrupt polls; the reader timer normally ends a
+/// transfer after 25 ms
+const POLL_LIMIT: u32 = 2000;
+
+/// Factory default MIFARE Classic key
+pub const DEFAULT_KEY: [u8; 6] = [0xFF; 6];
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The bus transfer failed
+    I2c(E),
+    /// No card answered
+    Timeout,
+    /// Several cards answered at once
+    Collision,
+    /// Parity, protocol or buffer error, holds `ErrorReg`
+    Communication(u8),
+    /// The response does not fit the buffer
+    BufferOverflow,
+    /// The response CRC does not match
+    Crc,
+    /// The card did not acknowledge, holds the 4 bit answer
+    Nak(u8),
+    /// The response has an unexpected length or content
+    InvalidResponse,
+    /// MIFARE authentication failed
+    Authentication,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(e: E) -> Self {
+        Error::I2c(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyType {
+    A = 0x60,
+    B = 0x61,
+}
+
+/// 4, 7 or 10 byte card UID
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Uid {
+    bytes: [u8; 10],
+    len: u8,
+}
+
+impl Uid {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CardType {
+    MifareMini,
+    MifareClassic1K,
+    MifareClassic4K,
+    /// MIFARE Ultralight and NTAG21x
+    Ultralight,
+    /// Any other select acknowledge
+    Other(u8),
+}
+
+impl CardType {
+    fn from_sak(sak: u8) -> Self {
+        match sak & 0x7F {
+            0x09 => CardType::MifareMini,
+            0x08 => CardType::MifareClassic1K,
+            0x18 => CardType::MifareClassic4K,
+            0x00 => CardType::Ultralight,
+            sak => CardType::Other(sak),
+        }
+    }
+}
+
+/// A selected card
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Card {
+    pub uid: Uid,
+    /// Select acknowledge
+    pub sak: u8,
+}
+
+impl Card {
+    pub fn card_type(&self) -> CardType {
+        CardType::from_sak(self.sak)
+    }
+}
+
+/// ISO 14443A CRC, little endian
+pub fn crc_a(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0x6363;
+    for &byte in data {
+        let mut b = byte ^ crc as u8;
+        b ^= b << 4;
+        let b = b as u16;
+        crc = (crc >> 8) ^ (b << 8) ^ (b << 3) ^ (b >> 4);
+    }
+    crc.to_le_bytes()
+}
+
+fn check_crc<E>(data: &[u8]) -> Result<(), Error<E>> {
+    let Some(split) = data.len().checked_sub(2) else {
+        return Err(Error::InvalidResponse);
+    };
+    if crc_a(&data[..split]) != data[split..] {
+        return Err(Error::Crc);
+    }
+    Ok(())
+}
+
+pub struct WS1850S<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> WS1850S<I2C>
+where
+    I2C: WriteRead<Error = E> + Write<Error = E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            address: ADDRESS,
+        }
+    }
+
+    /// Resets the reader, sets a 25 ms receive timeout and turns the
+    /// antenna on.
+    pub fn init(&mut self) -> Result<(), Error<E>> {
+        self.write(COMMAND, SOFT_RESET)?;
+        let mut reset = false;
+        for _ in 0..POLL_LIMIT {
+            if self.read(COMMAND)? & POWER_DOWN == 0 {
+                reset = true;
+                break;
+            }
+        }
+        if !reset {
+            return Err(Error::Timeout);
+        }
+
+        // Timer starts after each transmission, 40 kHz tick, 1000 ticks
+        self.write(T_MODE, 0x80)?;
+        self.write(T_PRESCALER, 0xA9)?;
+        self.write(T_RELOAD_H, 0x03)?;
+        self.write(T_RELOAD_L, 0xE8)?;
+        // 100% ASK modulation
+        self.write(TX_ASK, 0x40)?;
+        // CRC preset 0x6363 as in ISO 14443A
+        self.write(MODE, 0x3D)?;
+        self.set_antenna(true)
+    }
+
+    /// Chip version from `VersionReg`
+    pub fn version(&mut self) -> Result<u8, Error<E>> {
+        self.read(VERSION)
+    }
+
+    /// Switches the RF field; off saves power while no card is expected.
+    pub fn set_antenna(&mut self, on: bool) -> Result<(), Error<E>> {
+        let control = self.read(TX_CONTROL)?;
+        let control = if on { control | 0x03 } else { control & !0x03 };
+        self.write(TX_CONTROL, control)
+    }
+
+    /// Whether an idle card is in the field.
+    pub fn is_card_present(&mut self) -> Result<bool, Error<E>> {
+        match self.request(REQA) {
+            Ok(_) | Err(Error::Collision) => Ok(true),
+            Err(Error::Timeout) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Wakes a halted or idle card, returning its ATQA.
+    pub fn wake_up(&mut self) -> Result<[u8; 2], Error<E>> {
+        self.request(WUPA)
+    }
+
+    /// Selects an idle card in the field, `None` if there is none.
+    pub fn read_card(&mut self) -> Result<Option<Card>, Error<E>> {
+        match self.request(REQA) {
+            Ok(_) | Err(Error::Collision) => self.select().map(Some),
+            Err(Error::Timeout) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Runs anticollision and select over all cascade levels after a
+    /// [`WS1850S::is_card_present`] or [`WS1850S::wake_up`].
+    pub fn select(&mut self) -> Result<Card, Error<E>> {
+        let mut uid = Uid {
+            bytes: [0; 10],
+            len: 0,
+        };
+        for sel in SELECT {
+            let (bytes, sak) = self.select_level(sel)?;
+            let cascade = sak & 0x04 != 0;
+            // A cascade tag takes the first byte when more levels follow
+            let bytes = match (cascade, bytes[0] == CT) {
+                (true, true) => &bytes[1..],
+                (false, _) => &bytes[..],
+                (true, false) => return Err(Error::InvalidResponse),
+            };
+            let start = uid.len as usize;
+            if start + bytes.len() > uid.bytes.len() {
+                return Err(Error::InvalidResponse);
+            }
+            uid.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+            uid.len += bytes.len() as u8;
+            if !cascade {
+                return Ok(Card { uid, sak });
+            }
+        }
+        Err(Error::InvalidResponse)
+    }
+
+    /// Puts the selected card into the halt state.
+    pub fn halt(&mut self) -> Result<(), Error<E>> {
+        let mut frame = [HLTA, 0, 0, 0];
+        let crc = crc_a(&frame[..2]);
+        frame[2..].copy_from_slice(&crc);
+        // The card does not answer a successful halt
+        match self.transceive(&frame, &mut [0; 4], 0, 0) {
+            Err(Error::Timeout) => Ok(()),
+            Ok(_) => Err(Error::InvalidResponse),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Authenticates a MIFARE Classic sector for the following block
+    /// accesses.
+    pub fn authenticate(
+        &mut self,
+        key_type: KeyType,
+        block: u8,
+        key: &[u8; 6],
+        uid: &Uid,
+    ) -> Result<(), Error<E>> {
+        let uid = uid.as_bytes();
+        let mut frame = [0; 12];
+        frame[0] = key_type as u8;
+        frame[1] = block;
+        frame[2..8].copy_from_slice(key);
+        // The last four UID bytes take part in the authentication
+        frame[8..].copy_from_slice(&uid[uid.len().saturating_sub(4)..]);
+        self.communicate(MF_AUTHENT, IDLE_IRQ, &frame, None, 0, 0)?;
+        if self.read(STATUS_2)? & MF_CRYPTO1_ON == 0 {
+            return Err(Error::Authentication);
+        }
+        Ok(())
+    }
+
+    /// Ends an authenticated session, required before selecting another
+    /// card.
+    pub fn stop_crypto(&mut self) -> Result<(), Error<E>> {
+        let status = self.read(STATUS_2)?;
+        self.write(STATUS_2, status & !MF_CRYPTO1_ON)
+    }
+
+    /// Reads a 16 byte MIFARE Classic block, or four Ultralight pages
+    /// starting at `block`.
+    pub fn read_block(&mut self, block: u8) -> Result<[u8; 16], Error<E>> {
+        let mut frame = [MF_READ, block, 0, 0];
+        let crc = crc_a(&frame[..2]);
+        frame[2..].copy_from_slice(&crc);
+        let mut response = [0; 18];
+        let (len, _) = self.transceive(&frame, &mut response, 0, 0)?;
+        if len != response.len() {
+            return Err(Error::InvalidResponse);
+        }
+        check_crc(&response)?;
+        let mut data = [0; 16];
+        data.copy_from_slice(&response[..16]);
+        Ok(data)
+    }
+
+    /// Writes a 16 byte MIFARE Classic block.
+    pub fn write_block(&mut self, block: u8, data: &[u8; 16]) -> Result<(), Error<E>> {
+        let mut frame = [MF_WRITE, block, 0, 0];
+        let crc = crc_a(&frame[..2]);
+        frame[2..].copy_from_slice(&crc);
+        self.transceive_ack(&frame)?;
+
+        let mut frame = [0; 18];
+        frame[..16].copy_from_slice(data);
+        frame[16..].copy_from_slice(&crc_a(data));
+        self.transceive_ack(&frame)
+    }
+
+    /// Writes a 4 byte Ultralight page.
+    pub fn write_page(&mut self, page: u8, data: &[u8; 4]) -> Result<(), Error<E>> {
+        let mut frame = [0; 8];
+        frame[0] = UL_WRITE;
+        frame[1] = page;
+        frame[2..6].copy_from_slice(data);
+        let crc = crc_a(&frame[..6]);
+        frame[6..].copy_from_slice(&crc);
+        self.transceive_ack(&frame)
+    }
+
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Sends a 7 bit REQA or WUPA and returns the ATQA.
+    fn request(&mut self, command: u8) -> Result<[u8; 2], Error<E>> {
+        self.clear_bits(COLL, VALUES_AFTER_COLL)?;
+        let mut atqa = [0; 2];
+        let (len, valid_bits) = self.transceive(&[command], &mut atqa, 7, 0)?;
+        if len != 2 || valid_bits != 0 {
+            return Err(Error::InvalidResponse);
+        }
+        Ok(atqa)
+    }
+
+    /// Resolves the four UID bytes of one cascade level and selects them.
+    fn select_level(&mut self, sel: u8) -> Result<([u8; 4], u8), Error<E>> {
+        self.clear_bits(COLL, VALUES_AFTER_COLL)?;
+
+        // SEL, NVB, four UID bytes, BCC and CRC
+        let mut buffer = [0; 9];
+        buffer[0] = sel;
+        let mut known_bits = 0;
+        while known_bits < 32 {
+            let tx_last_bits = (known_bits % 8) as u8;
+            let index = 2 + known_bits / 8;
+            // Number of valid bytes and bits sent, SEL and NVB included
+            buffer[1] = (index as u8) << 4 | tx_last_bits;
+            let len = index + (tx_last_bits != 0) as usize;
+            let frame = buffer;
+            match self.transceive(
+                &frame[..len],
+                &mut buffer[index..7],
+                tx_last_bits,
+                tx_last_bits,
+            ) {
+                Ok(_) => known_bits = 32,
+                Err(Error::Collision) => {
+                    let coll = self.read(COLL)?;
+                    if coll & COLL_POS_NOT_VALID != 0 {
+                        return Err(Error::Collision);
+                    }
+                    let position = match (coll & 0x1F) as usize {
+                        0 => 32,
+                        position => position,
+                    };
+                    if position <= known_bits {
+                        return Err(Error::Collision);
+                    }
+                    // Resolve the collision towards the card with a 1 bit
+                    known_bits = position;
+                    let bit = known_bits - 1;
+                    buffer[2 + bit / 8] |= 1 << (bit % 8);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let bcc = buffer[2..6].iter().fold(0, |bcc, b| bcc ^ b);
+        if bcc != buffer[6] {
+            return Err(Error::InvalidResponse);
+        }
+        buffer[1] = 0x70;
+        let crc = crc_a(&buffer[..7]);
+        buffer[7..].copy_from_slice(&crc);
+        let mut sak = [0; 3];
+        let (len, _) = self.transceive(&buffer, &mut sak, 0, 0)?;
+        if len != sak.len() {
+            return Err(Error::InvalidResponse);
+        }
+        check_crc(&sak)?;
+
+        let mut uid = [0; 4];
+        uid.copy_from_slice(&buffer[2..6]);
+        Ok((uid, sak[0]))
+    }
+
+    /// Sends a frame that the card answers with a 4 bit ACK.
+    fn transceive_ack(&mut self, frame: &[u8]) -> Result<(), Error<E>> {
+        let mut ack = [0; 1];
+        let (len, valid_bits) = self.transceive(frame, &mut ack, 0, 0)?;
+        if len != 1 || valid_bits != 4 {
+            return Err(Error::InvalidResponse);
+        }
+        if ack[0] & 0x0F != MF_ACK {
+            return Err(Error::Nak(ack[0] & 0x0F));
+        }
+        Ok(())
+    }
+
+    fn transceive(
+        &mut self,
+        send: &[u8],
+        back: &mut [u8],
+        tx_last_bits: u8,
+        rx_align: u8,
+    ) -> Result<(usize, u8), Error<E>> {
+        self.communicate(
+            TRANSCEIVE,
+            RX_IRQ | IDLE_IRQ,
+            send,
+            Some(back),
+            tx_last_bits,
+            rx_align,
+        )
+    }
+
+    /// Runs `command` on `send` and returns the number of bytes received
+    /// into `back` and the valid bits of the last one (0 for all).
+    ///
+    /// With `rx_align` the first received bit lands at that position of
+    /// `back[0]`, keeping the lower bits already there. On a collision the
+    /// bits received so far are still stored in `back`.
+    fn communicate(
+        &mut self,
+        command: u8,
+        wait_irq: u8,
+        send: &[u8],
+        back: Option<&mut [u8]>,
+        tx_last_bits: u8,
+        rx_align: u8,
+    ) -> Result<(usize, u8), Error<E>> {
+        if send.len() > FIFO_SIZE {
+            return Err(Error::BufferOverflow);
+        }
+        self.write(COMMAND, IDLE)?;
+        self.write(COM_IRQ, 0x7F)?;
+        // Flush the FIFO
+        self.write(FIFO_LEVEL, 0x80)?;
+        let mut data = [0; FIFO_SIZE + 1];
+        data[0] = FIFO_DATA;
+        data[1..=send.len()].copy_from_slice(send);
+        self.i2c.write(self.address, &data[..=send.len()])?;
+        self.write(BIT_FRAMING, rx_align << 4 | tx_last_bits)?;
+        self.write(COMMAND, command)?;
+        if command == TRANSCEIVE {
+            self.write(BIT_FRAMING, START_SEND | rx_align << 4 | tx_last_bits)?;
+        }
+
+        let mut done = false;
+        for _ in 0..POLL_LIMIT {
+            let irq = self.read(COM_IRQ)?;
+            if irq & wait_irq != 0 {
+                done = true;
+                break;
+            }
+            if irq & TIMER_IRQ != 0 {
+                return Err(Error::Timeout);
+            }
+        }
+        if !done {
+            return Err(Error::Timeout);
+        }
+
+        let error = self.read(ERROR)?;
+        if error & (BUFFER_OVFL | PARITY_ERR | PROTOCOL_ERR) != 0 {
+            return Err(Error::Communication(error));
+        }
+        let Some(back) = back else {
+            return Ok((0, 0));
+        };
+
+        let len = self.read(FIFO_LEVEL)? as usize;
+        if len > back.len() {
+            return Err(Error::BufferOverflow);
+        }
+        let keep = back.first().copied().unwrap_or(0);
+        if len > 0 {
+            self.i2c
+                .write_read(self.address, &[FIFO_DATA], &mut back[..len])?;
+            if rx_align != 0 {
+                let mask = 0xFF << rx_align;
+                back[0] = keep & !mask | back[0] & mask;
+            }
+        }
+        let valid_bits = self.read(CONTROL)? & 0x07;
+        if error & COLL_ERR != 0 {
+            return Err(Error::Collision);
+        }
+        Ok((len, valid_bits))
+    }
+
+    fn read(&mut self, register: u8) -> Result<u8, Error<E>> {
+        let mut value = [0];
+        self.i2c.write_read(self.address, &[register], &mut value)?;
+        Ok(value[0])
+    }
+
+    fn write(&mut self, register: u8, value: u8) -> Result<(), Error<E>> {
+        self.i2c.write(self.address, &[register, value])?;
+        Ok(())
+    }
+
+    fn clear_bits(&mut self, register: u8, bits: u8) -> Result<(), Error<E>> {
+        let value = self.read(register)?;
+        self.write(register, value & !bits)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{vec, vec::Vec};
+
+    use super::*;
+
+    /// A card in the field, answering each cascade level with scripted UID
+    /// bytes and SAK
+    pub(crate) struct Card {
+        levels: Vec<([u8; 4], u8)>,
+        /// Cascade levels completed so far
+        level: usize,
+        selected: bool,
+        halted: bool,
+        pub(crate) memory: [u8; 180],
+        pending_write: Option<u8>,
+    }
+
+    impl Card {
+        /// A well-behaved card with a 4, 7 or 10 byte UID
+        pub(crate) fn new(uid: &[u8], sak: u8) -> Self {
+            let levels = match *uid {
+                [a, b, c, d] => vec![([a, b, c, d], sak)],
+                [a, b, c, d, e, f, g] => vec![([CT, a, b, c], 0x04), ([d, e, f, g], sak)],
+                [a, b, c, d, e, f, g, h, i, j] => vec![
+                    ([CT, a, b, c], 0x04),
+                    ([CT, d, e, f], 0x04),
+                    ([g, h, i, j], sak),
+                ],
+                _ => panic!("invalid UID length"),
+            };
+            Self::scripted(levels)
+        }
+
+        pub(crate) fn scripted(levels: Vec<([u8; 4], u8)>) -> Self {
+            Self {
+                levels,
+                level: 0,
+                selected: false,
+                halted: false,
+                memory: [0; 180],
+                pending_write: None,
+            }
+        }
+
+        /// UID bytes and BCC of a cascade level
+        fn level_bytes(&self, level: usize) -> Option<[u8; 5]> {
+            let (b, _) = self.levels.get(level)?;
+            Some([b[0], b[1], b[2], b[3], b[0] ^ b[1] ^ b[2] ^ b[3]])
+        }
+
+        fn is_active_at(&self, level: usize) -> bool {
+            self.level == level && !self.halted
+        }
+    }
+
+    fn bit(bytes: &[u8], i: usize) -> bool {
+        bytes[i / 8] >> (i % 8) & 1 != 0
+    }
+
+    /// Register-level model of the reader and the cards in its field
+    pub(crate) struct Reader {
+        registers: [u8; 64],
+        fifo_in: Vec<u8>,
+        fifo_out: Vec<u8>,
+        pub(crate) cards: Vec<Card>,
+    }
+
+    impl Reader {
+        pub(crate) fn new(cards: Vec<Card>) -> Self {
+            Self {
+                registers: [0; 64],
+                fifo_in: Vec::new(),
+                fifo_out: Vec::new(),
+                cards,
+            }
+        }
+
+        /// Completes a transfer with `response` and its valid bits in the
+        /// last byte, or times out with `None`.
+        fn respond(&mut self, response: Option<(Vec<u8>, u8)>, error: u8, coll: u8) {
+            match response {
+                Some((data, last_bits)) => {
+                    self.fifo_out = data;
+                    self.registers[COM_IRQ as usize] = RX_IRQ | IDLE_IRQ;
+                    self.registers[CONTROL as usize] = last_bits;
+                }
+                None => {
+                    self.fifo_out.clear();
+                    self.registers[COM_IRQ as usize] = TIMER_IRQ;
+                }
+            }
+            self.registers[ERROR as usize] = error;
+            self.registers[COLL as usize] = coll;
+        }
+
+        fn ack(&mut self) {
+            self.respond(Some((vec![MF_ACK], 4)), 0, 0)
+        }
+
+        fn selected(&mut self) -> &mut Card {
+            self.cards.iter_mut().find(|card| card.selected).unwrap()
+        }
+
+        fn transceive(&mut self) {
+            let frame = core::mem::take(&mut self.fifo_in);
+            let short_frame = self.registers[BIT_FRAMING as usize] & 0x07 == 7;
+            if short_frame && frame.len() == 1 {
+                return self.request(frame[0]);
+            }
+            let crc_ok =
+                frame.len() >= 3 && crc_a(&frame[..frame.len() - 2]) == frame[frame.len() - 2..];
+            match frame[0] {
+                sel if SELECT.contains(&sel) => {
+                    let level = SELECT.iter().position(|&s| s == sel).unwrap();
+                    if frame[1] == 0x70 {
+                        assert!(crc_ok);
+                        self.select(level, &frame[2..7]);
+                    } else {
+                        self.anticollision(level, &frame);
+                    }
+                }
+                HLTA => {
+                    for card in self.cards.iter_mut().filter(|card| card.selected) {
+                        card.halted = true;
+                        card.selected = false;
+                    }
+                    self.respond(None, 0, 0)
+                }
+                MF_READ => {
+                    let card = self.selected();
+                    let start = frame[1] as usize * 4;
+                    let mut data: Vec<u8> =
+                        (0..16).map(|i| card.memory[(start + i) % 180]).collect();
+                    data.extend_from_slice(&crc_a(&data));
+                    self.respond(Some((data, 0)), 0, 0)
+                }
+                MF_WRITE => {
+                    self.selected().pending_write = Some(frame[1]);
+                    self.ack()
+                }
+                UL_WRITE => {
+                    let page = frame[1] as usize * 4;
+                    self.selected().memory[page..page + 4].copy_from_slice(&frame[2..6]);
+                    self.ack()
+                }
+                _ => {
+                    // Second half of a MIFARE write
+                    assert!(crc_ok && frame.len() == 18);
+                    let card = self.selected();
+                    let block = card.pending_write.take().unwrap() as usize * 4;
+                    card.memory[block..block + 16].copy_from_slice(&frame[..16]);
+                    self.ack()
+                }
+            }
+        }
+
+        fn request(&mut self, command: u8) {
+            let mut answered = false;
+            for card in self.cards.iter_mut() {
+                if !card.halted || command == WUPA {
+                    card.level = 0;
+                    card.selected = false;
+                    card.halted = false;
+                    answered = true;
+                }
+            }
+            self.respond(answered.then(|| (vec![0x44, 0x00], 0)), 0, 0)
+        }
+
+        fn select(&mut self, level: usize, uid: &[u8]) {
+            let mut response = None;
+            for card in self
+                .cards
+                .iter_mut()
+                .filter(|card| card.is_active_at(level))
+            {
+                match card.level_bytes(level) {
+                    Some(bytes) if bytes[..] == *uid => {
+                        let sak = card.levels[level].1;
+                        card.level += 1;
+                        card.selected = card.level == card.levels.len();
+                        let crc = crc_a(&[sak]);
+                        response = Some((vec![sak, crc[0], crc[1]], 0));
+                    }
+                    // Cards that do not match drop out until the next request
+                    _ => card.level = usize::MAX,
+                }
+            }
+            self.respond(response, 0, 0)
+        }
+
+        /// Answers with the bits after the `NVB` known ones. Where the cards
+        /// disagree, a collision is reported.
+        fn anticollision(&mut self, level: usize, frame: &[u8]) {
+            let known = ((frame[1] >> 4) as usize - 2) * 8 + (frame[1] & 0x07) as usize;
+            let answers: Vec<[u8; 5]> = self
+                .cards
+                .iter()
+                .filter(|card| card.is_active_at(level))
+                .filter_map(|card| card.level_bytes(level))
+                .filter(|bytes| (0..known).all(|i| bit(bytes, i) == bit(&frame[2..], i)))
+                .collect();
+            let Some(first) = answers.first() else {
+                return self.respond(None, 0, 0);
+            };
+            let mut data = [0u8; 5];
+            let mut collision = None;
+            for i in known..40 {
+                let value = bit(first, i);
+                if answers.iter().any(|bytes| bit(bytes, i) != value) {
+                    collision = Some(i);
+                    break;
+                }
+                if value {
+                    data[i / 8] |= 1 << (i % 8);
+                }
+            }
+            let data = data[known / 8..].to_vec();
+            match collision {
+                // CollPos counts from 1, 32 reads as 0
+                Some(i) => self.respond(Some((data, 0)), COLL_ERR, ((i + 1) % 32) as u8),
+                None => self.respond(Some((data, 0)), 0, COLL_POS_NOT_VALID),
+            }
+        }
+
+        fn authenticate(&mut self) {
+            let frame = core::mem::take(&mut self.fifo_in);
+            let card = self.selected();
+            let uid_tail = card.levels.last().unwrap().0;
+            let ok = frame[2..8] == DEFAULT_KEY && frame[8..] == uid_tail;
+            self.registers[STATUS_2 as usize] = if ok { MF_CRYPTO1_ON } else { 0 };
+            self.registers[COM_IRQ as usize] = IDLE_IRQ;
+            self.registers[ERROR as usize] = 0;
+        }
+    }
+
+    impl Write for Reader {
+        type Error = ();
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            let (register, value) = (bytes[0], &bytes[1..]);
+            if register == FIFO_DATA {
+                self.fifo_in.extend_from_slice(value);
+                return Ok(());
+            }
+            self.registers[register as usize] = value[0];
+            match (register, value[0]) {
+                (FIFO_LEVEL, flush) if flush & 0x80 != 0 => {
+                    self.fifo_in.clear();
+                    self.fifo_out.clear();
+                }
+                (COMMAND, SOFT_RESET) => self.registers[COMMAND as usize] = IDLE,
+                (COMMAND, MF_AUTHENT) => self.authenticate(),
+                (BIT_FRAMING, framing)
+                    if framing & START_SEND != 0
+                        && self.registers[COMMAND as usize] == TRANSCEIVE =>
+                {
+                    self.transceive()
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    impl WriteRead for Reader {
+        type Error = ();
+
+        fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            assert_eq!(address, ADDRESS);
+            match bytes[0] {
+                FIFO_DATA => {
+                    for byte in buffer.iter_mut() {
+                        *byte = self.fifo_out.remove(0);
+                    }
+                }
+                FIFO_LEVEL => buffer[0] = self.fifo_out.len() as u8,
+                register => buffer[0] = self.registers[register as usize],
+            }
+            Ok(())
+        }
+    }
+
+    fn reader(cards: Vec<Card>) -> WS1850S<Reader> {
+        let mut rfid = WS1850S::new(Reader::new(cards));
+        rfid.init().unwrap();
+        rfid
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc_a(&[HLTA, 0x00]), [0x57, 0xCD]);
+        assert_eq!(crc_a(&[MF_READ, 0x00]), [0x02, 0xA8]);
+    }
+
+    #[test]
+    fn single_size_uid() {
+        let mut rfid = reader(vec![Card::new(&[0xDE, 0xAD, 0xBE, 0xEF], 0x08)]);
+        assert!(rfid.is_card_present().unwrap());
+        let card = rfid.read_card().unwrap().unwrap();
+        assert_eq!(card.uid.as_bytes(), [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(card.card_type(), CardType::MifareClassic1K);
+
+        rfid.authenticate(KeyType::A, 4, &DEFAULT_KEY, &card.uid)
+            .unwrap();
+        rfid.write_block(4, &[7; 16]).unwrap();
+        assert_eq!(rfid.read_block(4).unwrap(), [7; 16]);
+        rfid.stop_crypto().unwrap();
+
+        rfid.halt().unwrap();
+        assert!(!rfid.is_card_present().unwrap());
+        assert!(rfid.read_card().unwrap().is_none());
+        rfid.wake_up().unwrap();
+        assert!(rfid.select().is_ok());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let mut rfid = reader(vec![Card::new(&[1, 2, 3, 4], 0x08)]);
+        let card = rfid.read_card().unwrap().unwrap();
+        assert!(matches!(
+            rfid.authenticate(KeyType::A, 4, &[0; 6], &card.uid),
+            Err(Error::Authentication)
+        ));
+    }
+
+    #[test]
+    fn cascade_levels_and_collisions() {
+        let mut rfid = reader(vec![
+            Card::new(&[0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66], 0x00),
+            // Differs from the first card in the second cascade level only
+            Card::new(&[0x04, 0x11, 0x22, 0x33, 0x45, 0x55, 0x66], 0x00),
+            Card::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], 0x00),
+        ]);
+        let mut seen = Vec::new();
+        while let Some(card) = rfid.read_card().unwrap() {
+            assert_eq!(card.card_type(), CardType::Ultralight);
+            rfid.write_page(5, &[1, 2, 3, 4]).unwrap();
+            assert_eq!(rfid.read_block(5).unwrap()[..4], [1, 2, 3, 4]);
+            seen.push(card.uid.as_bytes().to_vec());
+            rfid.halt().unwrap();
+        }
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+                vec![0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66],
+                vec![0x04, 0x11, 0x22, 0x33, 0x45, 0x55, 0x66],
+            ]
+        );
+    }
+
+    #[test]
+    fn cascade_without_cascade_tag() {
+        // The SAKs announce more levels, but the UID bytes lack the CT. Taken
+        // as they are, three levels would not fit a 10 byte UID.
+        let card = Card::scripted(vec![
+            ([1, 2, 3, 4], 0x04),
+            ([5, 6, 7, 8], 0x04),
+            ([9, 10, 11, 12], 0x00),
+        ]);
+        let mut rfid = reader(vec![card]);
+        assert!(matches!(rfid.read_card(), Err(Error::InvalidResponse)));
+    }
+
+    #[test]
+    fn too_many_cascade_levels() {
+        // Every level claims that another one follows
+        let card = Card::scripted(vec![([CT, 1, 2, 3], 0x04); 3]);
+        let mut rfid = reader(vec![card]);
+        assert!(matches!(rfid.read_card(), Err(Error::InvalidResponse)));
+    }
+}